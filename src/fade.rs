@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The longest fade `!fade` and `!crossfade` accept.
+pub const MAX_FADE: Duration = Duration::from_secs(10);
+/// How often the volume changes during a fade.
+pub const STEP: Duration = Duration::from_millis(50);
+
+/// A guild's fades; a zero duration turns one off.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FadeSettings {
+    /// On `!stop` and when something else starts playing.
+    pub fade_out: Duration,
+    /// Overlap between consecutive queued tracks. Never used after live streams, which
+    /// have no end to start the next track before.
+    pub crossfade: Duration,
+}
+
+impl Default for FadeSettings {
+    fn default() -> Self {
+        FadeSettings {
+            fade_out: Duration::from_secs(2),
+            crossfade: Duration::ZERO,
+        }
+    }
+}
+
+/// `2`, `1.5` or `0` seconds, up to `MAX_FADE`.
+pub fn parse_seconds(text: &str) -> Option<Duration> {
+    let secs: f64 = text.trim().trim_end_matches('s').parse().ok()?;
+    if !secs.is_finite() || secs < 0.0 {
+        return None;
+    }
+    let duration = Duration::from_secs_f64(secs);
+    (duration <= MAX_FADE).then_some(duration)
+}
+
+/// `1.5s`, or `off` for zero.
+pub fn describe(duration: Duration) -> String {
+    if duration.is_zero() {
+        "off".to_string()
+    } else {
+        format!("{}s", duration.as_secs_f32())
+    }
+}
+
+/// The volumes to set going from `from` to `to` over `duration`, each with how long after
+/// the start to set it. The last one is always `to`.
+pub fn steps(from: f32, to: f32, duration: Duration) -> Vec<(Duration, f32)> {
+    let count = (duration.as_millis() / STEP.as_millis()) as u32;
+    if count == 0 {
+        return vec![(Duration::ZERO, to)];
+    }
+    (1..=count)
+        .map(|step| {
+            let progress = step as f32 / count as f32;
+            (STEP * step, from + (to - from) * progress)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fade_steps() {
+        let fade = steps(1.0, 0.0, Duration::from_millis(200));
+        assert_eq!(
+            fade,
+            vec![
+                (Duration::from_millis(50), 0.75),
+                (Duration::from_millis(100), 0.5),
+                (Duration::from_millis(150), 0.25),
+                (Duration::from_millis(200), 0.0),
+            ]
+        );
+        assert_eq!(steps(0.0, 0.8, Duration::ZERO), vec![(Duration::ZERO, 0.8)]);
+        assert_eq!(steps(0.0, 1.0, Duration::from_secs(2)).len(), 40);
+    }
+
+    #[test]
+    fn seconds() {
+        assert_eq!(parse_seconds("1.5"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_seconds("3s"), Some(Duration::from_secs(3)));
+        assert_eq!(parse_seconds("0"), Some(Duration::ZERO));
+        assert_eq!(parse_seconds("11"), None);
+        assert_eq!(parse_seconds("-1"), None);
+        assert_eq!(parse_seconds("soon"), None);
+        assert_eq!(describe(Duration::from_millis(1500)), "1.5s");
+        assert_eq!(describe(Duration::ZERO), "off");
+    }
+}
@@ -0,0 +1,68 @@
+use async_trait::async_trait;
+use reqwest::header::CONTENT_TYPE;
+use std::{error::Error, fmt::Debug};
+
+/// What the server says about a link, without downloading it.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct UrlProbe {
+    pub content_type: Option<String>,
+    pub content_length: Option<u64>,
+    /// Icecast/Shoutcast `icy-*` headers were present.
+    pub icy: bool,
+}
+
+/// Web pages the bot scrapes (YouTube search/watch pages) and files it downloads
+/// (message attachments) are fetched through here.
+#[async_trait]
+pub trait Fetcher: Debug + Send + Sync {
+    async fn fetch_text(&self, url: &str)
+        -> Result<String, Box<dyn Error + Send + Sync + 'static>>;
+
+    async fn fetch_bytes(
+        &self,
+        url: &str,
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync + 'static>>;
+
+    async fn probe(&self, url: &str) -> Result<UrlProbe, Box<dyn Error + Send + Sync + 'static>>;
+}
+
+#[derive(Debug, Default)]
+pub struct HttpFetcher {
+    client: reqwest::Client,
+}
+
+#[async_trait]
+impl Fetcher for HttpFetcher {
+    async fn fetch_text(
+        &self,
+        url: &str,
+    ) -> Result<String, Box<dyn Error + Send + Sync + 'static>> {
+        let content = self.client.get(url).send().await?.text().await?;
+        Ok(content)
+    }
+
+    async fn fetch_bytes(
+        &self,
+        url: &str,
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync + 'static>> {
+        let response = self.client.get(url).send().await?.error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    async fn probe(&self, url: &str) -> Result<UrlProbe, Box<dyn Error + Send + Sync + 'static>> {
+        // A GET, since plenty of stream servers don't answer HEAD. The body is never read.
+        let response = self.client.get(url).send().await?.error_for_status()?;
+        let headers = response.headers();
+
+        Ok(UrlProbe {
+            content_type: headers
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string()),
+            content_length: response.content_length(),
+            icy: headers
+                .keys()
+                .any(|name| name.as_str().starts_with("icy-") || name.as_str() == "ice-audio-info"),
+        })
+    }
+}
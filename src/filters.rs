@@ -0,0 +1,307 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// An ffmpeg audio filter preset for `!filter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    Bass,
+    Treble,
+    Nightcore,
+    Vaporwave,
+    Speed,
+    #[serde(rename = "8d")]
+    EightD,
+    Loudnorm,
+}
+
+impl Filter {
+    pub const ALL: [Filter; 7] = [
+        Filter::Bass,
+        Filter::Treble,
+        Filter::Nightcore,
+        Filter::Vaporwave,
+        Filter::Speed,
+        Filter::EightD,
+        Filter::Loudnorm,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Filter::Bass => "bass",
+            Filter::Treble => "treble",
+            Filter::Nightcore => "nightcore",
+            Filter::Vaporwave => "vaporwave",
+            Filter::Speed => "speed",
+            Filter::EightD => "8d",
+            Filter::Loudnorm => "loudnorm",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim().to_lowercase();
+        match name.as_str() {
+            "bassboost" | "bass-boost" => Some(Filter::Bass),
+            "normalize" => Some(Filter::Loudnorm),
+            _ => Self::ALL.into_iter().find(|filter| filter.name() == name),
+        }
+    }
+
+    /// The ffmpeg `-af` filters, for 48 kHz input.
+    pub fn chain(self) -> &'static str {
+        match self {
+            Filter::Bass => "bass=g=10:f=110:w=0.6",
+            Filter::Treble => "treble=g=6:f=3000",
+            Filter::Nightcore => "aresample=48000,asetrate=60000,aresample=48000",
+            Filter::Vaporwave => "aresample=48000,asetrate=38400,aresample=48000",
+            Filter::Speed => "atempo=1.25",
+            Filter::EightD => "apulsator=hz=0.125",
+            Filter::Loudnorm => "loudnorm=I=-16:TP=-1.5:LRA=11",
+        }
+    }
+
+    /// How much faster than normal the filter plays a track.
+    pub fn tempo(self) -> f64 {
+        match self {
+            Filter::Nightcore | Filter::Speed => 1.25,
+            Filter::Vaporwave => 0.8,
+            _ => 1.0,
+        }
+    }
+}
+
+/// `filters` with `filter` switched on or off. Presets that change the speed replace each
+/// other rather than stack.
+pub fn toggle(filters: &[Filter], filter: Filter) -> Vec<Filter> {
+    if filters.contains(&filter) {
+        return filters
+            .iter()
+            .copied()
+            .filter(|active| *active != filter)
+            .collect();
+    }
+    let mut toggled: Vec<Filter> = filters
+        .iter()
+        .copied()
+        .filter(|active| filter.tempo() == 1.0 || active.tempo() == 1.0)
+        .collect();
+    toggled.push(filter);
+    toggled
+}
+
+/// The `-af` argument for `filters`, if there are any.
+pub fn chain(filters: &[Filter]) -> Option<String> {
+    if filters.is_empty() {
+        return None;
+    }
+    Some(
+        filters
+            .iter()
+            .map(|filter| filter.chain())
+            .collect::<Vec<_>>()
+            .join(","),
+    )
+}
+
+/// `bass, nightcore`, or `none`.
+pub fn describe(filters: &[Filter]) -> String {
+    if filters.is_empty() {
+        return "none".to_string();
+    }
+    filters
+        .iter()
+        .map(|filter| filter.name())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// YouTube's `loudnessDb` is relative to this level.
+const YOUTUBE_REFERENCE_LUFS: f32 = -14.0;
+/// The level `!normalize` aims for, the same as the `loudnorm` preset's.
+const TARGET_LUFS: f32 = -16.0;
+/// Quiet uploads are turned up at most this much; the limiter catches the peaks.
+const MAX_GAIN_DB: f32 = 6.0;
+
+/// How `!normalize` levels a track.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Leveling {
+    /// Measured by ffmpeg while it plays, for anything YouTube hasn't measured.
+    Loudnorm,
+    /// A fixed gain in dB from the loudness YouTube measured, which keeps the dynamics.
+    Gain(f32),
+}
+
+impl Leveling {
+    /// From the watch page's `loudnessDb`, when the track has one.
+    pub fn new(youtube_loudness: Option<f32>) -> Self {
+        match youtube_loudness.filter(|db| db.is_finite()) {
+            Some(db) => {
+                Leveling::Gain((TARGET_LUFS - YOUTUBE_REFERENCE_LUFS - db).min(MAX_GAIN_DB))
+            }
+            None => Leveling::Loudnorm,
+        }
+    }
+
+    fn chain(self) -> String {
+        match self {
+            Leveling::Loudnorm => Filter::Loudnorm.chain().to_string(),
+            Leveling::Gain(gain) => format!("volume={:.2}dB,alimiter=limit=0.84", gain),
+        }
+    }
+}
+
+/// What a track plays through: the guild's presets and, with `!normalize` on, its leveling.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Effects {
+    pub filters: Vec<Filter>,
+    pub leveling: Option<Leveling>,
+}
+
+impl Effects {
+    pub fn new(filters: Vec<Filter>, leveling: Option<Leveling>) -> Self {
+        Effects { filters, leveling }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty() && self.leveling.is_none()
+    }
+
+    /// The `-af` argument, leveling first. The `loudnorm` preset isn't run twice.
+    pub fn chain(&self) -> Option<String> {
+        let filters: Vec<Filter> = self
+            .filters
+            .iter()
+            .copied()
+            .filter(|filter| {
+                *filter != Filter::Loudnorm || self.leveling != Some(Leveling::Loudnorm)
+            })
+            .collect();
+        let leveling = self.leveling.map(Leveling::chain);
+        match (leveling, chain(&filters)) {
+            (Some(leveling), Some(filters)) => Some(format!("{},{}", leveling, filters)),
+            (leveling, filters) => leveling.or(filters),
+        }
+    }
+
+    /// `bass, normalized`, or `none`.
+    pub fn describe(&self) -> String {
+        match (self.leveling, self.filters.is_empty()) {
+            (None, _) => describe(&self.filters),
+            (Some(_), true) => "normalized".to_string(),
+            (Some(_), false) => format!("{}, normalized", describe(&self.filters)),
+        }
+    }
+
+    pub fn timeline(&self, start: Duration) -> Timeline {
+        Timeline::new(start, &self.filters)
+    }
+}
+
+/// Where the playing input started in its track and how fast it plays, to tell how far into
+/// the track a handle's position is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timeline {
+    pub start: Duration,
+    pub tempo: f64,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Timeline {
+            start: Duration::ZERO,
+            tempo: 1.0,
+        }
+    }
+}
+
+impl Timeline {
+    pub fn new(start: Duration, filters: &[Filter]) -> Self {
+        Timeline {
+            start,
+            tempo: filters.iter().map(|filter| filter.tempo()).product(),
+        }
+    }
+
+    /// The position in the track after the input has played for `played`.
+    pub fn position(&self, played: Duration) -> Duration {
+        self.start + played.mul_f64(self.tempo)
+    }
+
+    /// How long the input still plays before a track of `length` ends.
+    pub fn remaining(&self, length: Duration, played: Duration) -> Duration {
+        length
+            .saturating_sub(self.position(played))
+            .div_f64(self.tempo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_and_chains() {
+        assert_eq!(Filter::parse("BassBoost"), Some(Filter::Bass));
+        assert_eq!(Filter::parse("8D"), Some(Filter::EightD));
+        assert_eq!(Filter::parse("karaoke"), None);
+
+        let filters = toggle(&[], Filter::Bass);
+        let filters = toggle(&filters, Filter::Nightcore);
+        assert_eq!(
+            chain(&filters).as_deref(),
+            Some("bass=g=10:f=110:w=0.6,aresample=48000,asetrate=60000,aresample=48000")
+        );
+        let filters = toggle(&filters, Filter::Vaporwave);
+        assert_eq!(filters, vec![Filter::Bass, Filter::Vaporwave]);
+        assert_eq!(describe(&toggle(&filters, Filter::Vaporwave)), "bass");
+        assert_eq!(chain(&[]), None);
+        assert_eq!(describe(&[]), "none");
+
+        let json = serde_json::to_string(&[Filter::EightD, Filter::Loudnorm]).unwrap();
+        assert_eq!(json, r#"["8d","loudnorm"]"#);
+    }
+
+    #[test]
+    fn leveling() {
+        assert_eq!(Leveling::new(Some(-7.25)), Leveling::Gain(5.25));
+        assert_eq!(Leveling::new(Some(-12.0)), Leveling::Gain(MAX_GAIN_DB));
+        assert_eq!(Leveling::new(Some(3.0)), Leveling::Gain(-5.0));
+        assert_eq!(Leveling::new(None), Leveling::Loudnorm);
+
+        let effects = Effects::new(
+            vec![Filter::Loudnorm, Filter::Bass],
+            Some(Leveling::Loudnorm),
+        );
+        assert_eq!(
+            effects.chain().as_deref(),
+            Some("loudnorm=I=-16:TP=-1.5:LRA=11,bass=g=10:f=110:w=0.6")
+        );
+        assert_eq!(effects.describe(), "loudnorm, bass, normalized");
+        let effects = Effects::new(Vec::new(), Some(Leveling::Gain(-5.0)));
+        assert_eq!(
+            effects.chain().as_deref(),
+            Some("volume=-5.00dB,alimiter=limit=0.84")
+        );
+        assert_eq!(effects.describe(), "normalized");
+        assert!(Effects::default().is_empty());
+        assert_eq!(Effects::default().chain(), None);
+    }
+
+    #[test]
+    fn positions_follow_the_tempo() {
+        let timeline = Timeline::new(Duration::from_secs(60), &[Filter::Bass, Filter::Speed]);
+        assert_eq!(timeline.tempo, 1.25);
+        assert_eq!(
+            timeline.position(Duration::from_secs(40)),
+            Duration::from_secs(110)
+        );
+        assert_eq!(
+            timeline.remaining(Duration::from_secs(160), Duration::from_secs(40)),
+            Duration::from_secs(40)
+        );
+        assert_eq!(
+            Timeline::default().position(Duration::from_secs(5)),
+            Duration::from_secs(5)
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{
+    fade::FadeSettings,
+    filters::{Effects, Filter, Leveling},
+    history::HistoryEntry,
+    playlists::Playlist,
+    schedule::Schedule,
+    stations::Station,
+    volume::Volume,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    env,
+    error::Error,
+    path::{Path, PathBuf},
+};
+use tokio::sync::Mutex;
+use twilight_model::id::{marker::GuildMarker, Id};
+
+/// Everything the bot remembers about a guild between restarts.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GuildData {
+    #[serde(default)]
+    pub stations: Vec<Station>,
+    #[serde(default)]
+    pub schedules: Vec<Schedule>,
+    #[serde(default)]
+    pub playlists: Vec<Playlist>,
+    /// Oldest first.
+    #[serde(default)]
+    pub history: Vec<HistoryEntry>,
+    #[serde(default)]
+    pub autoplay: bool,
+    #[serde(default)]
+    pub fade: FadeSettings,
+    /// `!filter` presets, in the order they were switched on.
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+    /// `!normalize`: level every track to the same loudness.
+    #[serde(default)]
+    pub normalize: bool,
+    /// Every track starts at this volume.
+    #[serde(default)]
+    pub volume: Volume,
+    /// `!announce`: post the song titles of radio streams where they were started.
+    #[serde(default)]
+    pub announce_titles: bool,
+}
+
+impl GuildData {
+    /// What the guild's tracks play through. `youtube_loudness` is the watch page's
+    /// `loudnessDb` of a YouTube track, if it has one.
+    pub fn effects(&self, youtube_loudness: Option<f32>) -> Effects {
+        Effects::new(
+            self.filters.clone(),
+            self.normalize.then(|| Leveling::new(youtube_loudness)),
+        )
+    }
+}
+
+/// `AURRAS_DATA_DIR` or `./data`.
+pub fn data_dir() -> PathBuf {
+    env::var_os("AURRAS_DATA_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("./data"))
+}
+
+/// One JSON file per guild, cached after the first read.
+#[derive(Debug)]
+pub struct GuildStore {
+    dir: PathBuf,
+    guilds: Mutex<HashMap<Id<GuildMarker>, GuildData>>,
+}
+
+impl GuildStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        GuildStore {
+            dir: dir.into(),
+            guilds: Mutex::default(),
+        }
+    }
+
+    fn path(&self, guild_id: Id<GuildMarker>) -> PathBuf {
+        self.dir.join(format!("{}.json", guild_id))
+    }
+
+    async fn read(path: &Path) -> Result<GuildData, Box<dyn Error + Send + Sync + 'static>> {
+        match tokio::fs::read(path).await {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(why) if why.kind() == std::io::ErrorKind::NotFound => Ok(GuildData::default()),
+            Err(why) => Err(why.into()),
+        }
+    }
+
+    /// Every guild with saved data.
+    pub async fn guild_ids(
+        &self,
+    ) -> Result<Vec<Id<GuildMarker>>, Box<dyn Error + Send + Sync + 'static>> {
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(why) if why.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(why) => return Err(why.into()),
+        };
+        let mut guild_ids = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
+                if let Some(guild_id) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse().ok())
+                    .and_then(Id::new_checked)
+                {
+                    guild_ids.push(guild_id);
+                }
+            }
+        }
+        Ok(guild_ids)
+    }
+
+    pub async fn get(
+        &self,
+        guild_id: Id<GuildMarker>,
+    ) -> Result<GuildData, Box<dyn Error + Send + Sync + 'static>> {
+        self.update(guild_id, |data| data.clone()).await
+    }
+
+    /// Applies `change` and writes the result back when it changed anything.
+    pub async fn update<T>(
+        &self,
+        guild_id: Id<GuildMarker>,
+        change: impl FnOnce(&mut GuildData) -> T,
+    ) -> Result<T, Box<dyn Error + Send + Sync + 'static>> {
+        let mut guilds = self.guilds.lock().await;
+        let data = match guilds.entry(guild_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(Self::read(&self.path(guild_id)).await?),
+        };
+
+        let before = data.clone();
+        let result = change(data);
+        if *data != before {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            // Write next to the file and rename, so a crash never leaves half a file.
+            let path = self.path(guild_id);
+            let temp = path.with_extension("json.tmp");
+            tokio::fs::write(&temp, serde_json::to_vec_pretty(data)?).await?;
+            tokio::fs::rename(&temp, &path).await?;
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stations::builtin_stations;
+
+    #[tokio::test]
+    async fn persists_between_stores() {
+        let dir = env::temp_dir().join(format!("aurras-store-{}", std::process::id()));
+        let guild_id = Id::new(745_000_000_000_000_001);
+        let station = builtin_stations().remove(0);
+
+        let store = GuildStore::new(&dir);
+        assert_eq!(store.get(guild_id).await.unwrap(), GuildData::default());
+        assert!(!dir.exists());
+        store
+            .update(guild_id, |data| data.stations.push(station.clone()))
+            .await
+            .unwrap();
+
+        let reopened = GuildStore::new(&dir);
+        assert_eq!(
+            reopened.get(guild_id).await.unwrap().stations,
+            vec![station]
+        );
+
+        tokio::fs::write(dir.join("1.json"), b"{not json")
+            .await
+            .unwrap();
+        assert!(reopened.get(Id::new(1)).await.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
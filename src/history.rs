@@ -0,0 +1,123 @@
+use crate::{media_item_type::MediaItemType, track::Track, url_utils};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+/// How many played tracks a guild remembers.
+pub const MAX_HISTORY: usize = 50;
+
+/// A track as it started playing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub track: Track,
+    pub played_at: SystemTime,
+}
+
+impl HistoryEntry {
+    /// `Title - requested by tester, 5m ago`
+    pub fn line(&self, now: SystemTime) -> String {
+        let elapsed = now.duration_since(self.played_at).unwrap_or_default();
+        format!(
+            "{} - requested by {}, {}",
+            self.track.display_title(),
+            self.track.requester,
+            ago(elapsed)
+        )
+    }
+
+    /// Only links can go back into the queue; local files are played with `!local`.
+    pub fn can_requeue(&self) -> bool {
+        url_utils::is_url(&self.track.source_url)
+    }
+}
+
+fn ago(elapsed: Duration) -> String {
+    let minutes = elapsed.as_secs() / 60;
+    match minutes {
+        0 => "just now".to_string(),
+        1..=59 => format!("{}m ago", minutes),
+        60..=1439 => format!("{}h ago", minutes / 60),
+        _ => format!("{}d ago", minutes / 1440),
+    }
+}
+
+/// Adds `track` as the newest entry, dropping the oldest beyond `MAX_HISTORY`. Radio
+/// stations aren't tracks and are left out, as is the same track started twice in a row.
+pub fn record(history: &mut Vec<HistoryEntry>, track: Track, played_at: SystemTime) -> bool {
+    if track.kind == MediaItemType::Radio
+        || history.last().map(|entry| &entry.track) == Some(&track)
+    {
+        return false;
+    }
+    history.push(HistoryEntry { track, played_at });
+    if history.len() > MAX_HISTORY {
+        history.drain(..history.len() - MAX_HISTORY);
+    }
+    true
+}
+
+/// The track `!previous` puts back: the newest one that can be queued and isn't `current`.
+pub fn previous<'a>(history: &'a [HistoryEntry], current: Option<&Track>) -> Option<&'a Track> {
+    history
+        .iter()
+        .rev()
+        .filter(|entry| Some(&entry.track) != current)
+        .find(|entry| entry.can_requeue())
+        .map(|entry| &entry.track)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use twilight_model::id::Id;
+
+    fn track(kind: MediaItemType, url: &str) -> Track {
+        Track::requested_by(kind, url, Id::new(745_000_000_000_000_003), "tester")
+    }
+
+    #[test]
+    fn keeps_the_newest_tracks() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut history = Vec::new();
+        for index in 0..MAX_HISTORY + 5 {
+            let url = format!("https://www.youtube.com/watch?v={}", index);
+            assert!(record(
+                &mut history,
+                track(MediaItemType::Youtube, &url),
+                start + Duration::from_secs(index as u64)
+            ));
+        }
+        assert_eq!(history.len(), MAX_HISTORY);
+        assert_eq!(
+            history[0].track.source_url,
+            "https://www.youtube.com/watch?v=5"
+        );
+
+        let last = history.last().unwrap().track.clone();
+        assert!(!record(&mut history, last, start));
+        assert!(!record(
+            &mut history,
+            track(MediaItemType::Radio, ""),
+            start
+        ));
+        assert_eq!(history.len(), MAX_HISTORY);
+
+        let line = history[0].line(start + Duration::from_secs(2 * 3600));
+        assert_eq!(line, "watch - requested by tester, 1h ago");
+    }
+
+    #[test]
+    fn previous_skips_the_current_track() {
+        let played_at = SystemTime::UNIX_EPOCH;
+        let older = track(MediaItemType::Youtube, "https://youtu.be/older");
+        let local = track(MediaItemType::LocalFile, "/music/song.mp3");
+        let current = track(MediaItemType::Youtube, "https://youtu.be/current");
+        let mut history = Vec::new();
+        for track in [&older, &local, &current] {
+            record(&mut history, track.clone(), played_at);
+        }
+
+        assert_eq!(previous(&history, Some(&current)), Some(&older));
+        assert_eq!(previous(&history, None), Some(&current));
+        assert_eq!(previous(&history[1..2], None), None);
+    }
+}
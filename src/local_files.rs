@@ -0,0 +1,311 @@
+use crate::fetcher::Fetcher;
+use std::{
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::process::Command;
+
+/// Formats symphonia is built with in `Cargo.toml`.
+pub const AUDIO_EXTENSIONS: [&str; 4] = ["mp3", "ogg", "flac", "m4a"];
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LocalTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+/// `!local` only serves files below this directory: `AURRAS_MUSIC_DIR` or `./music`.
+pub fn music_dir() -> PathBuf {
+    env::var_os("AURRAS_MUSIC_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("./music"))
+}
+
+pub fn is_audio_file(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Resolves `query` as a path relative to `dir`, falling back to the first audio file
+/// whose path contains every word of the query. Never leaves `dir`.
+pub fn find_local(
+    dir: &Path,
+    query: &str,
+) -> Result<Option<PathBuf>, Box<dyn Error + Send + Sync + 'static>> {
+    let dir = fs::canonicalize(dir)?;
+
+    if let Ok(path) = fs::canonicalize(dir.join(query)) {
+        if path.starts_with(&dir) && path.is_file() && is_audio_file(&path.to_string_lossy()) {
+            return Ok(Some(path));
+        }
+    }
+
+    let words: Vec<String> = query
+        .split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect();
+    if words.is_empty() {
+        return Ok(None);
+    }
+
+    let mut files = Vec::new();
+    collect_audio_files(&dir, &mut files)?;
+    files.sort();
+
+    Ok(files.into_iter().find(|path| {
+        let name = path
+            .strip_prefix(&dir)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_lowercase();
+        words.iter().all(|word| name.contains(word.as_str()))
+    }))
+}
+
+/// Audio files below `dir`. Symlinks are skipped, since they can point outside it.
+fn collect_audio_files(
+    dir: &Path,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        if file_type.is_dir() {
+            collect_audio_files(&path, files)?;
+        } else if file_type.is_file() && is_audio_file(&path.to_string_lossy()) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The largest attachment `!play` downloads: `AURRAS_MAX_ATTACHMENT_MB` or 25 MB.
+pub fn max_attachment_size() -> u64 {
+    env::var("AURRAS_MAX_ATTACHMENT_MB")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .filter(|mb| *mb > 0)
+        .map(|mb: u64| mb * 1024 * 1024)
+        .unwrap_or(25 * 1024 * 1024)
+}
+
+fn attachment_dir() -> PathBuf {
+    env::temp_dir().join("aurras")
+}
+
+/// Saves a Discord attachment in the temp directory so it can be played as a file.
+/// Attachments over `max_attachment_size` are refused before anything is downloaded.
+pub async fn download_attachment(
+    fetcher: &dyn Fetcher,
+    url: &str,
+    id: u64,
+    filename: &str,
+    size: u64,
+) -> Result<PathBuf, Box<dyn Error + Send + Sync + 'static>> {
+    let limit = max_attachment_size();
+    if size > limit {
+        return Err(format!(
+            "{} is {} bytes, over the {} byte limit",
+            filename, size, limit
+        )
+        .into());
+    }
+    let dir = attachment_dir();
+    tokio::fs::create_dir_all(&dir).await?;
+
+    let filename: String = filename
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let path = dir.join(format!("{}_{}", id, filename));
+
+    let bytes = fetcher.fetch_bytes(url).await?;
+    if bytes.len() as u64 > limit {
+        return Err(format!("{} is over the {} byte limit", filename, limit).into());
+    }
+    tokio::fs::write(&path, bytes).await?;
+
+    Ok(path)
+}
+
+/// Deletes `path` if `download_attachment` saved it. Called once its track ended or was
+/// replaced; files from the music directory are left alone.
+pub fn remove_attachment(path: &Path) {
+    if !path.starts_with(attachment_dir()) {
+        return;
+    }
+    if let Err(why) = fs::remove_file(path) {
+        if why.kind() != std::io::ErrorKind::NotFound {
+            println!("Could not remove {}: {}", path.display(), why);
+        }
+    }
+}
+
+/// Reads title/artist/album tags and the duration with `ffprobe`.
+pub async fn probe_tags(path: &Path) -> Result<LocalTags, Box<dyn Error + Send + Sync + 'static>> {
+    let output = Command::new("ffprobe")
+        .arg("-v")
+        .arg("quiet")
+        .arg("-show_entries")
+        .arg("format=duration:format_tags:stream_tags")
+        .arg("-of")
+        .arg("default=noprint_wrappers=1")
+        .arg(path)
+        .output()
+        .await?;
+
+    Ok(parse_ffprobe(&String::from_utf8_lossy(&output.stdout)))
+}
+
+pub fn parse_ffprobe(output: &str) -> LocalTags {
+    let mut tags = LocalTags::default();
+
+    for line in output.lines() {
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) if !value.trim().is_empty() => (key.to_lowercase(), value.trim()),
+            _ => continue,
+        };
+        let slot = match key.as_str() {
+            "tag:title" => &mut tags.title,
+            "tag:artist" => &mut tags.artist,
+            "tag:album" => &mut tags.album,
+            "duration" => {
+                if tags.duration.is_none() {
+                    tags.duration = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+                        .map(Duration::from_secs_f64);
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        if slot.is_none() {
+            *slot = Some(value.to_string());
+        }
+    }
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_harness::FixtureFetcher;
+
+    #[test]
+    fn audio_extensions() {
+        assert!(is_audio_file("song.MP3"));
+        assert!(is_audio_file("dir/song.flac"));
+        assert!(!is_audio_file("song.txt"));
+        assert!(!is_audio_file("mp3"));
+    }
+
+    #[test]
+    fn ffprobe_tags() {
+        let output = "duration=212.500000\nTAG:title=Never Gonna Give You Up\nTAG:ARTIST=Rick Astley\nTAG:album=\nTAG:title=Stream title\n";
+        assert_eq!(
+            parse_ffprobe(output),
+            LocalTags {
+                title: Some("Never Gonna Give You Up".to_string()),
+                artist: Some("Rick Astley".to_string()),
+                album: None,
+                duration: Some(Duration::from_millis(212_500)),
+            }
+        );
+        assert_eq!(parse_ffprobe("duration=N/A\ngarbage"), LocalTags::default());
+    }
+
+    #[test]
+    fn finds_files_inside_music_dir_only() {
+        let dir = env::temp_dir().join(format!("aurras-local-{}", std::process::id()));
+        fs::create_dir_all(dir.join("Rick Astley")).unwrap();
+        fs::write(dir.join("Rick Astley/Never Gonna Give You Up.mp3"), b"").unwrap();
+        fs::write(dir.join("notes.txt"), b"").unwrap();
+
+        let found = find_local(&dir, "rick never").unwrap().unwrap();
+        assert!(found.ends_with("Rick Astley/Never Gonna Give You Up.mp3"));
+        let found = find_local(&dir, "Rick Astley/Never Gonna Give You Up.mp3").unwrap();
+        assert!(found.is_some());
+        assert_eq!(find_local(&dir, "notes").unwrap(), None);
+        assert_eq!(find_local(&dir, "../../etc/passwd").unwrap(), None);
+        assert_eq!(find_local(&dir, "").unwrap(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn attachments_are_capped_and_removed() {
+        let fetcher = FixtureFetcher::default().page("https://cdn.example/", "not really audio");
+
+        let too_big = max_attachment_size() + 1;
+        let refused = download_attachment(
+            &fetcher,
+            "https://cdn.example/big.mp3",
+            1,
+            "big.mp3",
+            too_big,
+        );
+        assert!(refused.await.is_err());
+        assert!(fetcher.requests().is_empty());
+
+        let id = u64::from(std::process::id());
+        let path = download_attachment(&fetcher, "https://cdn.example/a.mp3", id, "a b.mp3", 16)
+            .await
+            .unwrap();
+        assert!(path.ends_with(format!("{}_a_b.mp3", id)));
+        assert!(path.is_file());
+        remove_attachment(&path);
+        assert!(!path.exists());
+        // Removing it twice, or a file that was never downloaded, is a no-op.
+        remove_attachment(&path);
+    }
+
+    #[test]
+    fn music_dir_files_are_not_removed() {
+        let dir = env::temp_dir().join(format!("aurras-keep-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let song = dir.join("song.mp3");
+        fs::write(&song, b"").unwrap();
+
+        remove_attachment(&song);
+        assert!(song.is_file());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_music_dir_are_skipped() {
+        let root = env::temp_dir().join(format!("aurras-symlink-{}", std::process::id()));
+        let dir = root.join("music");
+        let outside = root.join("outside");
+        fs::create_dir_all(&dir).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("secret song.mp3"), b"").unwrap();
+        std::os::unix::fs::symlink(&outside, dir.join("linked")).unwrap();
+        std::os::unix::fs::symlink(outside.join("secret song.mp3"), dir.join("song.mp3")).unwrap();
+
+        assert_eq!(find_local(&dir, "secret").unwrap(), None);
+        assert_eq!(find_local(&dir, "song").unwrap(), None);
+        assert_eq!(find_local(&dir, "linked/secret song.mp3").unwrap(), None);
+        assert_eq!(find_local(&dir, "song.mp3").unwrap(), None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
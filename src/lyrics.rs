@@ -0,0 +1,367 @@
+use crate::fetcher::Fetcher;
+use async_trait::async_trait;
+use lazy_static_include::lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+use std::{env, error::Error, fmt::Debug, path::PathBuf, sync::Arc};
+
+/// Discord's limit for an embed description.
+pub const PAGE_LENGTH: usize = 4096;
+
+/// The song `!lyrics` looks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SongQuery {
+    pub artist: Option<String>,
+    pub title: String,
+}
+
+impl SongQuery {
+    /// `Artist - Title`, or just a title.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (artist, title) = match text.split_once(" - ") {
+            Some((artist, title)) => (Some(artist.trim()), title),
+            None => (None, text),
+        };
+        let title = clean_title(title);
+        if title.is_empty() {
+            return None;
+        }
+        Some(SongQuery {
+            artist: artist
+                .filter(|artist| !artist.is_empty())
+                .map(str::to_string),
+            title,
+        })
+    }
+
+    /// From a video title and its channel: uploads titled `Artist - Title (Official Video)`
+    /// keep their artist, otherwise the channel stands in for it.
+    pub fn from_video(title: &str, author: &str) -> Option<Self> {
+        let song = Self::parse(title)?;
+        if song.artist.is_some() {
+            return Some(song);
+        }
+        let author = clean_author(author);
+        Some(SongQuery {
+            artist: Some(author).filter(|author| !author.is_empty()),
+            ..song
+        })
+    }
+
+    pub fn label(&self) -> String {
+        match &self.artist {
+            Some(artist) => format!("{} - {}", artist, self.title),
+            None => self.title.clone(),
+        }
+    }
+}
+
+/// Drops what uploaders add to a song title: `(Official Video)`, `[HD]`, `ft. ...`, `| Label`.
+pub fn clean_title(title: &str) -> String {
+    lazy_static! {
+        static ref DECORATION_REGEX: Regex = Regex::new(
+            r"(?i)\s*[(\[][^)\]]*\b(official|video|audio|lyrics?|hd|hq|4k|remaster(ed)?|visuali[sz]er|mv|explicit|clip)\b[^)\]]*[)\]]"
+        )
+        .unwrap();
+        static ref FEATURING_REGEX: Regex =
+            Regex::new(r"(?i)\s+[(\[]?(ft\.?|feat\.?|featuring)\s.*$").unwrap();
+    }
+    let title = DECORATION_REGEX.replace_all(title, "");
+    let title = FEATURING_REGEX.replace(&title, "");
+    title
+        .split('|')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .trim_matches(|c| c == '"' || c == '“' || c == '”')
+        .trim()
+        .to_string()
+}
+
+fn clean_author(author: &str) -> String {
+    let author = author.trim();
+    let author = author.strip_suffix(" - Topic").unwrap_or(author);
+    let author = author.strip_suffix("VEVO").unwrap_or(author);
+    author.trim().to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lyrics {
+    pub artist: String,
+    pub title: String,
+    pub text: String,
+}
+
+/// Where `!lyrics` finds lyrics.
+#[async_trait]
+pub trait LyricsProvider: Debug + Send + Sync {
+    async fn lyrics(
+        &self,
+        song: &SongQuery,
+    ) -> Result<Option<Lyrics>, Box<dyn Error + Send + Sync + 'static>>;
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct LrcLibTrack {
+    track_name: String,
+    artist_name: String,
+    instrumental: bool,
+    plain_lyrics: Option<String>,
+}
+
+/// The lrclib.net search API.
+#[derive(Debug)]
+pub struct LrcLibApi {
+    fetcher: Arc<dyn Fetcher>,
+    base_url: String,
+}
+
+impl LrcLibApi {
+    pub fn new(fetcher: Arc<dyn Fetcher>, base_url: &str) -> Self {
+        LrcLibApi {
+            fetcher,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub fn search_url(
+        &self,
+        song: &SongQuery,
+    ) -> Result<String, Box<dyn Error + Send + Sync + 'static>> {
+        let mut params = vec![("track_name", song.title.as_str())];
+        if let Some(artist) = &song.artist {
+            params.push(("artist_name", artist.as_str()));
+        }
+        let url =
+            reqwest::Url::parse_with_params(&format!("{}/api/search", self.base_url), &params)?;
+        Ok(url.to_string())
+    }
+}
+
+#[async_trait]
+impl LyricsProvider for LrcLibApi {
+    async fn lyrics(
+        &self,
+        song: &SongQuery,
+    ) -> Result<Option<Lyrics>, Box<dyn Error + Send + Sync + 'static>> {
+        let content = self.fetcher.fetch_text(&self.search_url(song)?).await?;
+        let tracks: Vec<LrcLibTrack> = serde_json::from_str(&content)?;
+        Ok(tracks.into_iter().find_map(|track| {
+            let text = track.plain_lyrics.filter(|text| !text.trim().is_empty());
+            let text = match (text, track.instrumental) {
+                (Some(text), _) => text,
+                (None, true) => "*Instrumental*".to_string(),
+                (None, false) => return None,
+            };
+            Some(Lyrics {
+                artist: track.artist_name,
+                title: track.track_name,
+                text,
+            })
+        }))
+    }
+}
+
+/// `Artist - Title.txt` files in a directory.
+#[derive(Debug)]
+pub struct LocalLyrics {
+    dir: PathBuf,
+}
+
+impl LocalLyrics {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        LocalLyrics { dir: dir.into() }
+    }
+}
+
+/// Lowercase letters and digits only, so punctuation and spacing don't matter.
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[async_trait]
+impl LyricsProvider for LocalLyrics {
+    async fn lyrics(
+        &self,
+        song: &SongQuery,
+    ) -> Result<Option<Lyrics>, Box<dyn Error + Send + Sync + 'static>> {
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("txt") {
+                continue;
+            }
+            let stem = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(stem) => stem.to_string(),
+                None => continue,
+            };
+            let (artist, title) = stem.split_once(" - ").unwrap_or(("", stem.as_str()));
+            let matches = normalize(title) == normalize(&song.title)
+                && match &song.artist {
+                    Some(wanted) => normalize(artist) == normalize(wanted),
+                    None => true,
+                };
+            if matches {
+                return Ok(Some(Lyrics {
+                    artist: artist.to_string(),
+                    title: title.to_string(),
+                    text: tokio::fs::read_to_string(&path).await?,
+                }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// `AURRAS_LYRICS_DIR` when set, otherwise the API at `AURRAS_LYRICS_URL` (lrclib.net by
+/// default).
+pub fn provider_from_env(fetcher: Arc<dyn Fetcher>) -> Arc<dyn LyricsProvider> {
+    if let Some(dir) = env::var_os("AURRAS_LYRICS_DIR") {
+        return Arc::new(LocalLyrics::new(dir));
+    }
+    let base_url =
+        env::var("AURRAS_LYRICS_URL").unwrap_or_else(|_| "https://lrclib.net".to_string());
+    Arc::new(LrcLibApi::new(fetcher, &base_url))
+}
+
+/// Splits `text` into pages of at most `limit` characters, between lines where possible.
+pub fn pages(text: &str, limit: usize) -> Vec<String> {
+    let mut pages = Vec::new();
+    let mut page = String::new();
+    let mut page_length = 0;
+    for line in text.trim().lines() {
+        let chars: Vec<char> = line.chars().collect();
+        let pieces: Vec<String> = if chars.is_empty() {
+            vec![String::new()]
+        } else {
+            chars
+                .chunks(limit)
+                .map(|piece| piece.iter().collect())
+                .collect()
+        };
+        for piece in pieces {
+            let length = piece.chars().count();
+            if page_length > 0 && page_length + 1 + length > limit {
+                pages.push(page.trim_end().to_string());
+                page = String::new();
+                page_length = 0;
+            }
+            if page_length > 0 {
+                page.push('\n');
+                page_length += 1;
+            }
+            page.push_str(&piece);
+            page_length += length;
+        }
+    }
+    if !page.trim().is_empty() {
+        pages.push(page.trim_end().to_string());
+    }
+    pages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_harness::FixtureFetcher;
+
+    const SEARCH_RESULTS: &str = include_str!("../tests/fixtures/lrclib_search.json");
+
+    fn song(artist: Option<&str>, title: &str) -> SongQuery {
+        SongQuery {
+            artist: artist.map(str::to_string),
+            title: title.to_string(),
+        }
+    }
+
+    #[test]
+    fn songs_from_video_titles() {
+        assert_eq!(
+            SongQuery::from_video(
+                "Rick Astley - Never Gonna Give You Up (Official Music Video)",
+                "Rick Astley"
+            ),
+            Some(song(Some("Rick Astley"), "Never Gonna Give You Up"))
+        );
+        assert_eq!(
+            SongQuery::from_video("Take On Me [4K Remastered] | a-ha", "a-ha - Topic"),
+            Some(song(Some("a-ha"), "Take On Me"))
+        );
+        assert_eq!(
+            SongQuery::from_video("“Shape of You” ft. Someone (Lyrics)", "EdSheeranVEVO"),
+            Some(song(Some("EdSheeran"), "Shape of You"))
+        );
+        assert_eq!(
+            SongQuery::parse("Daft Punk - Around the World (Radio Edit)"),
+            Some(song(Some("Daft Punk"), "Around the World (Radio Edit)"))
+        );
+        assert_eq!(SongQuery::parse("(Official Video)"), None);
+    }
+
+    #[tokio::test]
+    async fn searches_lrclib() {
+        let fetcher = Arc::new(
+            FixtureFetcher::default().page("https://lrclib.example.com/api/search", SEARCH_RESULTS),
+        );
+        let api = LrcLibApi::new(fetcher.clone(), "https://lrclib.example.com/");
+
+        let lyrics = api
+            .lyrics(&song(Some("Rick Astley"), "Never Gonna Give You Up"))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(lyrics.artist, "Rick Astley");
+        assert!(lyrics.text.starts_with("We're no strangers to love\n"));
+        assert_eq!(
+            fetcher.requests(),
+            vec!["https://lrclib.example.com/api/search?track_name=Never+Gonna+Give+You+Up&artist_name=Rick+Astley"]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_local_files() {
+        let dir = env::temp_dir().join(format!("aurras-lyrics-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("Rick Astley - Never Gonna Give You Up.txt"),
+            "Never gonna",
+        )
+        .unwrap();
+        let provider = LocalLyrics::new(&dir);
+
+        let found = provider
+            .lyrics(&song(None, "never gonna give you up"))
+            .await
+            .unwrap();
+        assert_eq!(
+            found.map(|lyrics| lyrics.text).as_deref(),
+            Some("Never gonna")
+        );
+        assert!(provider
+            .lyrics(&song(Some("Someone Else"), "Never Gonna Give You Up"))
+            .await
+            .unwrap()
+            .is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pages_break_between_lines() {
+        let verse = "Never gonna give you up\n".repeat(300);
+        let split = pages(&verse, PAGE_LENGTH);
+        assert_eq!(split.len(), 2);
+        assert!(split.iter().all(|page| page.chars().count() <= PAGE_LENGTH));
+        assert!(split[0].ends_with("give you up"));
+        assert_eq!(split.join("\n"), verse.trim_end());
+
+        let long_line = "ă".repeat(10);
+        assert_eq!(pages(&long_line, 4), vec!["ăăăă", "ăăăă", "ăă"]);
+        assert!(pages("  \n", PAGE_LENGTH).is_empty());
+    }
+}
@@ -1,20 +1,26 @@
 use async_trait::async_trait;
+use chrono::{Local, NaiveDateTime, Timelike};
 use futures::StreamExt;
 use regex::Regex;
 use songbird::{
-    input::{ChildContainer, Compose, YoutubeDl},
-    tracks::{PlayMode, TrackHandle, TrackState},
-    EventContext, EventHandler, Songbird,
+    input::{AuxMetadata, ChildContainer, File as FileInput, HttpRequest, Input, YoutubeDl},
+    tracks::{PlayMode, Track as AudioTrack, TrackState},
+    Songbird,
 };
 
 use std::{
     collections::HashMap,
+    env,
     error::Error,
     fs::{self, File, OpenOptions},
     future::Future,
+    path::{Path, PathBuf},
     process::{self, exit, Stdio},
-    sync::Arc,
-    time::Duration,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
 };
 use std::{fs::read_to_string, io::prelude::*};
 use twilight_gateway::{
@@ -22,16 +28,23 @@ use twilight_gateway::{
     Event, EventType, Intents,
 };
 use twilight_model::{
-    channel::Message,
+    channel::{embed::Embed, ChannelType, Message, ReactionType},
     gateway::{
-        payload::outgoing::{update_presence::UpdatePresencePayload, UpdatePresence},
-        presence::{Activity, ActivityType, MinimalActivity, Status},
+        payload::{incoming::MessageCreate, outgoing::update_presence::UpdatePresencePayload},
+        presence::{Activity, ActivityType, Status},
+    },
+    guild::Permissions,
+    id::{
+        marker::{ChannelMarker, GuildMarker, MessageMarker, UserMarker},
+        Id,
     },
-    id::{marker::GuildMarker, Id},
 };
 
 use std::io::{BufRead, BufReader};
-use tokio::sync::{Mutex, RwLock};
+use tokio::{
+    sync::{mpsc, Mutex, RwLock},
+    task::JoinHandle,
+};
 
 use std::process::Command;
 use twilight_cache_inmemory::{InMemoryCache, ResourceType};
@@ -39,57 +52,172 @@ use twilight_cache_inmemory::{InMemoryCache, ResourceType};
 use twilight_http::Client as HttpClient;
 
 use std::time::Instant;
-use twilight_gateway::Cluster;
 use twilight_standby::Standby;
-use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder, ImageSource};
+use twilight_util::builder::embed::{
+    EmbedBuilder, EmbedFieldBuilder, EmbedFooterBuilder, ImageSource,
+};
 
+mod fade;
+mod fetcher;
+mod filters;
+mod guild_store;
+mod history;
+mod local_files;
+mod lyrics;
+mod media_item_type;
+mod messenger;
+mod metadata_cache;
+mod msg_utils;
+mod playlist_file;
+mod playlists;
+mod prefetch;
+mod presence;
+mod queue_page;
+mod radio_browser;
+mod radio_meta;
+mod schedule;
+mod stations;
+#[cfg(test)]
+mod test_harness;
+mod track;
+mod url_utils;
+mod voice;
+mod volume;
 mod yt_utils;
 
+use fetcher::{Fetcher, HttpFetcher};
+use filters::{Effects, Filter, Timeline};
+use guild_store::{GuildData, GuildStore};
+use lyrics::{LyricsProvider, SongQuery};
+use media_item_type::MediaItemType;
+use messenger::Messenger;
+use metadata_cache::{Metadata, MetadataCache};
+use playlists::{Playlist, PlaylistEntry, PlaylistName};
+use prefetch::{ResolvedStream, StreamResolver, YtDlp};
+use presence::Presence;
+use radio_browser::{RadioDirectory, RadioQuery};
+use schedule::{CronSpec, Schedule, ScheduleTarget};
+use stations::{Station, StationHealth};
+use track::Track;
+use url_utils::UrlKind;
+use voice::{PlayingTrack, TrackEventHandler, Voice};
+
 type State = Arc<StateRef>;
 
 #[derive(Debug)]
 struct StateRef {
-    http: HttpClient,
-    trackdata: RwLock<HashMap<Id<GuildMarker>, TrackHandle>>,
-    songbird: Songbird,
-    cluster: Arc<Cluster>,
+    messenger: Arc<dyn Messenger>,
+    fetcher: Arc<dyn Fetcher>,
+    streams: Arc<dyn StreamResolver>,
+    trackdata: RwLock<HashMap<Id<GuildMarker>, Arc<dyn PlayingTrack>>>,
+    station_health: RwLock<StationHealth>,
+    guild_store: GuildStore,
+    /// `!local` only serves files below this directory.
+    music_dir: PathBuf,
+    radio_directory: Arc<dyn RadioDirectory>,
+    lyrics: Arc<dyn LyricsProvider>,
+    metadata: MetadataCache,
+    voice: Arc<dyn Voice>,
+    presence: Arc<dyn Presence>,
     standby: Standby,
     cache: InMemoryCache,
 }
 
 struct Queue1 {
-    queue: Vec<YoutubeDl>,
+    queue: Vec<Track>,
+    /// The stream of the first queued track, resolved while the current one plays.
+    prefetched: Option<ResolvedStream>,
 }
 
-#[async_trait]
-impl EventHandler for Queue1 {
-    async fn act(&self, _ctx: &EventContext<'_>) -> Option<songbird::Event> {
-        if !self.queue.is_empty() {
-            let mut _src = self.queue[0].clone();
+impl Queue1 {
+    /// Queues `tracks` in place of any autoplay pick; a new one is picked once they've
+    /// played.
+    pub fn add(&mut self, tracks: impl IntoIterator<Item = Track>) {
+        self.drop_autoplay();
+        self.queue.extend(tracks);
+    }
+
+    pub fn drop_autoplay(&mut self) {
+        self.queue
+            .retain(|queued| queued.kind != MediaItemType::Autoplay);
+    }
+
+    /// The prefetched stream, when it belongs to `track`.
+    pub fn take_prefetched(&mut self, track: &Track) -> Option<ResolvedStream> {
+        match self.prefetched.take() {
+            Some(stream) if stream.source_url == track.source_url => Some(stream),
+            other => {
+                self.prefetched = other;
+                None
+            }
         }
+    }
 
-        println!("song finished ");
-        return None;
+    /// Forgets everything queued, along with its prefetched stream.
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.prefetched = None;
     }
-}
 
-impl Queue1 {
-    pub fn remove_fist(&mut self) {
-        if self.queue.len() > 0 {
-            self.queue.remove(0);
+    pub fn take_next(&mut self) -> Option<Track> {
+        if self.queue.is_empty() {
+            None
+        } else {
+            Some(self.queue.remove(0))
         }
     }
 }
 
+/// Starts the next queued track once `track` has ended.
 #[derive(Clone)]
+struct TrackEnd {
+    state: State,
+    state_info: Arc<Mutex<StateInfo>>,
+    queue: Arc<Mutex<Queue1>>,
+    guild_id: Id<GuildMarker>,
+    channel_id: Id<ChannelMarker>,
+    track: Track,
+}
+
+#[async_trait]
+impl TrackEventHandler for TrackEnd {
+    async fn act(&self, track_id: u128) {
+        println!("song finished ");
+        // A track restarted with other filters ends too, but it isn't over.
+        let current = self
+            .state
+            .trackdata
+            .read()
+            .await
+            .get(&self.guild_id)
+            .map(|current| current.id());
+        if current != Some(track_id) {
+            return;
+        }
+        spawn(play_next(
+            Arc::clone(&self.state),
+            Arc::clone(&self.state_info),
+            Arc::clone(&self.queue),
+            self.guild_id,
+            self.channel_id,
+            self.track.clone(),
+        ));
+    }
+}
+
+#[derive(Clone, Default)]
 struct StateInfo {
     is_joined: bool,
     current_song_desc: String,
-    current_song_link: String,
+    now_playing: Option<Track>,
+    stream_title: Option<String>,
+    title_watcher: Option<Arc<JoinHandle<()>>>,
     _yt_utils: yt_utils::YtInfo,
-    current_song_length: Option<Duration>,
     is_playing: bool,
     ffmpeg_id: Vec<u32>,
+    timeline: Timeline,
+    /// When `!pause` paused what's playing.
+    paused_at: Option<Instant>,
 }
 
 impl StateInfo {
@@ -99,18 +227,54 @@ impl StateInfo {
     pub fn set_current_song_desc(&mut self, value: String) {
         self.current_song_desc = value;
     }
-    pub fn set_current_song_link(&mut self, value: String) {
-        self.current_song_link = value;
+    pub fn set_now_playing(&mut self, value: Option<Track>) {
+        if let Some(watcher) = self.title_watcher.take() {
+            watcher.abort();
+        }
+        self.stream_title = None;
+        if let Some(previous) = self.now_playing.take() {
+            if previous.kind == MediaItemType::LocalFile && value.as_ref() != Some(&previous) {
+                local_files::remove_attachment(Path::new(&previous.source_url));
+            }
+        }
+        self.now_playing = value;
+    }
+    pub fn set_stream_title(&mut self, value: Option<String>) {
+        self.stream_title = value;
     }
-    pub fn set_current_song_length(&mut self, value: Option<Duration>) {
-        self.current_song_length = value;
+    pub fn set_title_watcher(&mut self, value: JoinHandle<()>) {
+        self.title_watcher = Some(Arc::new(value));
     }
+    /// A track that starts or stops isn't paused any more.
     pub fn set_is_playing(&mut self, value: bool) {
         self.is_playing = value;
+        self.paused_at = None;
+    }
+    pub fn set_paused_at(&mut self, value: Option<Instant>) {
+        self.paused_at = value;
     }
     pub fn set_ffmpeg_id(&mut self, value: u32) {
         self.ffmpeg_id.push(value);
     }
+    pub fn set_timeline(&mut self, value: Timeline) {
+        self.timeline = value;
+    }
+
+    /// What's playing for the presence, with the song on a live stream and a mark when
+    /// it's paused.
+    pub fn presence(&self) -> String {
+        let mut name = match &self.now_playing {
+            Some(track) => track.display_title(),
+            None => return "Nothing".to_string(),
+        };
+        if let Some(title) = &self.stream_title {
+            name.push_str(&format!(" - {}", title));
+        }
+        if self.paused_at.is_some() {
+            name.insert_str(0, "⏸ ");
+        }
+        name
+    }
 }
 
 fn spawn(
@@ -125,7 +289,7 @@ fn spawn(
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-    let (mut events, state, state_info, queue) = {
+    let (mut events, songbird, state, state_info, queue, user_id) = {
         let token = get_discord_token();
         if token.len() < 30 {
             println!("{:?} - {}", token, "Is not valid token !");
@@ -139,6 +303,7 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
             | Intents::GUILD_MEMBERS
             | Intents::GUILDS
             | Intents::GUILD_VOICE_STATES
+            | Intents::GUILD_MESSAGE_REACTIONS
             | Intents::MESSAGE_CONTENT;
 
         let cluster_id = 0;
@@ -184,192 +349,249 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
             return Songbird::twilight(cluster2 /*Arc::new(cluster)*/, user_id);
         });
 
-        let songbird = thi.await?;
+        let songbird = Arc::new(thi.await?);
+        let fetcher: Arc<dyn Fetcher> = Arc::new(HttpFetcher::default());
+        let radio_directory = radio_browser::directory_from_env(Arc::clone(&fetcher))?;
+        let lyrics = lyrics::provider_from_env(Arc::clone(&fetcher));
         (
             events,
+            Arc::clone(&songbird),
             Arc::new(StateRef {
-                http,
+                messenger: Arc::new(http),
+                fetcher,
+                streams: Arc::new(YtDlp),
                 trackdata: Default::default(),
-                songbird,
-                cluster: cluster3,
+                station_health: Default::default(),
+                guild_store: GuildStore::new(guild_store::data_dir()),
+                music_dir: local_files::music_dir(),
+                radio_directory,
+                lyrics,
+                metadata: metadata_cache::cache_from_env(),
+                voice: songbird,
+                presence: cluster3,
                 standby: Standby::new(),
                 cache: InMemoryCache::builder()
-                    .resource_types(ResourceType::VOICE_STATE | ResourceType::GUILD)
+                    .resource_types(
+                        ResourceType::VOICE_STATE
+                            | ResourceType::GUILD
+                            | ResourceType::CHANNEL
+                            | ResourceType::MEMBER
+                            | ResourceType::ROLE,
+                    )
                     .build(),
             }),
             Arc::new(Mutex::new(StateInfo {
                 is_joined: false,
                 current_song_desc: String::default(),
-                current_song_link: String::default(),
+                now_playing: None,
+                stream_title: None,
+                title_watcher: None,
                 _yt_utils: Default::default(),
-                current_song_length: Option::default(),
                 is_playing: false,
                 ffmpeg_id: Vec::default(),
+                timeline: Timeline::default(),
+                paused_at: None,
             })),
             Arc::new(Mutex::new(Queue1 {
                 queue: Vec::default(),
+                prefetched: None,
             })),
+            user_id,
         )
     };
 
+    tokio::spawn(check_station_health(Arc::clone(&state)));
+    tokio::spawn(run_schedules(
+        Arc::clone(&state),
+        Arc::clone(&state_info),
+        Arc::clone(&queue),
+    ));
+
     while let Some((_, event)) = events.next().await {
         state.standby.process(&event);
         state.cache.update(&event);
-        state.songbird.process(&event).await;
+        songbird.process(&event).await;
 
         if (event.kind() == EventType::VoiceStateUpdate)
             || (event.kind() == EventType::VoiceServerUpdate)
         {
             // println!("EVENT KIND: {:?} ", event.kind());
+            if let Some(guild_id) = event.guild_id() {
+                spawn(leave_if_alone(
+                    guild_id,
+                    user_id,
+                    Arc::clone(&state),
+                    Arc::clone(&state_info),
+                    Arc::clone(&queue),
+                ));
+            }
+        }
 
-            let user_id2 = state.http.current_user().await?.model().await?.id;
-
-            let voice_id = state.cache.voice_state(user_id2, event.guild_id().unwrap());
-
-            let testss;
-            match voice_id {
-                Some(test01) => {
-                    testss = Some(test01.channel_id());
-                }
-                None => {
-                    //  println!("No val.");
-                    testss = None;
-                }
+        if let Event::MessageCreate(msg) = event {
+            if msg.guild_id.is_none() || !msg.content.starts_with('!') {
+                continue;
             }
-            if testss.is_some() {
-                let info = state
-                    .cache
-                    .voice_channel_states(testss.unwrap())
-                    .unwrap()
-                    .count();
-                // println!("EVENT UPDATE : {:?} ", info);
-                // println!("INFO < 2 : {:?} ", info < 2);
-                //  println!("IS Joined : {:?} ", state_info.lock().await.is_joined);
-                if info < 2 {
-                    // let u = state.clone();
-                    if !state_info.lock().await.ffmpeg_id.is_empty() {
-                        let id = state_info.lock().await.ffmpeg_id.pop().unwrap();
-                        //println!("ID: {:?} \n",id);
-                        let sp = Command::new("kill").arg(id.to_string()).output();
-                        let _res = sp.unwrap();
-                    }
 
-                    if let Some(call_lock) = state.songbird.get(event.guild_id().unwrap()) {
-                        let mut call = call_lock.lock().await;
-                        let _ = call.stop();
-                        state_info.lock().await.set_is_playing(false);
-                    }
+            spawn(handle_command(
+                msg.0,
+                Arc::clone(&state),
+                Arc::clone(&state_info),
+                Arc::clone(&queue),
+            ));
+        }
+    }
 
-                    //  println!("leave !!!");
+    Ok(())
+}
 
-                    let _st1 = state.songbird.leave(event.guild_id().unwrap());
-                    let _st2 = state.songbird.remove(event.guild_id().unwrap());
-                    println!("{:?}", _st1.await);
-                    println!("{:?}", _st2.await);
+/// Runs the handler for the command in `msg`.
+async fn handle_command(
+    msg: Message,
+    state: State,
+    state_info: Arc<Mutex<StateInfo>>,
+    queue: Arc<Mutex<Queue1>>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    match msg_utils::get_command(&msg.content) {
+        "!join" => join(msg, state, state_info).await,
+        "!leave" => leave(msg, state, state_info, queue).await,
+        "!pause" => pause(msg, state, state_info, queue).await,
+        "!resume" => resume(msg, state, state_info).await,
+        "!play" => play(msg, state, state_info, queue).await,
+        "!local" => local(msg, state, state_info, queue).await,
+        "!help" => help(msg, state, state_info).await,
+        "!radiolist" => radiolist(msg, state, state_info).await,
+        "!radio" => radio_stations(msg, state).await,
+        "!radiosearch" => radiosearch(msg, state, state_info).await,
+        "!playlist" => playlist(msg, state, state_info, queue).await,
+        "!queue" => queue_command(msg, state, state_info, queue).await,
+        "!schedule" => schedule(msg, state).await,
+        "!history" => history(msg, state).await,
+        "!fade" | "!crossfade" => fade(msg, state).await,
+        "!filter" => filter(msg, state, state_info, queue).await,
+        "!normalize" => normalize(msg, state, state_info, queue).await,
+        "!autoplay" => autoplay(msg, state, state_info, queue).await,
+        "!previous" | "!back" => previous(msg, state, state_info, queue).await,
+        "!stop" => stop(msg, state, state_info, queue).await,
+        "!time" => time(msg, state, state_info).await,
+        "!add" => add(msg, state, state_info, queue).await,
+        "!list" => list(msg, state, state_info, queue).await,
+        "!desc" => description(msg, state, state_info).await,
+        "!lyrics" => lyrics(msg, state, state_info).await,
+        "!announce" => announce(msg, state, state_info).await,
+        "!volume" => volume(msg, state, state_info).await,
+        "!repeat" => time(msg, state, state_info).await,
+        "!reload" => {
+            exit(3);
+        }
+        // Built-in and saved stations all have `!radio...` commands.
+        command if command.starts_with("!radio") => radio(msg, state, state_info).await,
+        _ => Ok(()),
+    }
+}
 
-                    state_info.lock().await.set_is_joined(false);
+async fn leave_if_alone(
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+    state: State,
+    state_info: Arc<Mutex<StateInfo>>,
+    queue: Arc<Mutex<Queue1>>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let channel_id = match state.cache.voice_state(user_id, guild_id) {
+        Some(voice_state) => voice_state.channel_id(),
+        None => return Ok(()),
+    };
+    let info = state
+        .cache
+        .voice_channel_states(channel_id)
+        .map(|states| states.count())
+        .unwrap_or_default();
+    // println!("EVENT UPDATE : {:?} ", info);
+    // println!("INFO < 2 : {:?} ", info < 2);
+    //  println!("IS Joined : {:?} ", state_info.lock().await.is_joined);
+    if info < 2 {
+        disconnect(&state, &state_info, &queue, guild_id).await?;
+    }
 
-                    let activity = Activity::from(MinimalActivity {
-                        kind: ActivityType::Listening,
-                        name: "Nothing".to_owned(),
-                        url: None,
-                    });
-                    let request =
-                        UpdatePresence::new(Vec::from([activity]), false, None, Status::Online)?;
+    Ok(())
+}
 
-                    for shard in state.cluster.shards() {
-                        shard.command(&request).await?;
-                    }
-                }
-            }
-        }
+/// Ends playback in the guild: fades what's playing out over `fade_out`, stops it and
+/// kills its ffmpeg processes, and forgets the queue and the now playing track.
+async fn stop_playback(
+    state: &State,
+    state_info: &Arc<Mutex<StateInfo>>,
+    queue: &Arc<Mutex<Queue1>>,
+    guild_id: Id<GuildMarker>,
+    fade_out: Duration,
+) {
+    queue.lock().await.clear();
+    // Out of `trackdata` first, so its end doesn't start anything queued meanwhile.
+    let handle = state.trackdata.write().await.remove(&guild_id);
+    let ffmpeg_ids = {
+        let mut info = state_info.lock().await;
+        info.set_now_playing(None);
+        info.set_is_playing(false);
+        std::mem::take(&mut info.ffmpeg_id)
+    };
+    if let Some(handle) = handle {
+        fade_volume(handle.as_ref(), 0.0, fade_out).await;
+        let _ = handle.stop();
+    }
+    // Tracks still fading out from before go too, unless something new started meanwhile.
+    if !state.trackdata.read().await.contains_key(&guild_id) {
+        state.voice.stop(guild_id).await;
+    }
+    for id in ffmpeg_ids {
+        kill_process(id);
+    }
+}
 
-        if let Event::MessageCreate(msg) = event {
-            if msg.guild_id.is_none() || !msg.content.starts_with('!') {
-                continue;
-            }
+fn kill_process(id: u32) {
+    if let Err(why) = Command::new("kill").arg(id.to_string()).output() {
+        println!("{}", why);
+    }
+}
 
-            match msg.content.splitn(2, ' ').next() {
-                Some("!join") => spawn(join(msg.0, Arc::clone(&state), Arc::clone(&state_info))),
-                Some("!leave") => {
-                    spawn(leave(msg.0, Arc::clone(&state), Arc::clone(&state_info)));
-                }
-                Some("!pause") => spawn(pause(msg.0, Arc::clone(&state), Arc::clone(&state_info))),
-                Some("!play") => spawn(play(
-                    msg.0,
-                    Arc::clone(&state),
-                    Arc::clone(&state_info),
-                    Arc::clone(&queue),
-                )),
-                Some("!help") => spawn(help(msg.0, Arc::clone(&state), Arc::clone(&state_info))),
-                Some("!radiolist") => spawn(radiolist(
-                    msg.0,
-                    Arc::clone(&state),
-                    Arc::clone(&state_info),
-                )),
-                Some("!stop") => spawn(stop(msg.0, Arc::clone(&state), Arc::clone(&state_info))),
-                Some("!time") => spawn(time(msg.0, Arc::clone(&state), Arc::clone(&state_info))),
-                Some("!add") => spawn(add(
-                    msg.0,
-                    Arc::clone(&state),
-                    Arc::clone(&state_info),
-                    Arc::clone(&queue),
-                )),
-                Some("!list") => spawn(list(
-                    msg.0,
-                    Arc::clone(&state),
-                    Arc::clone(&state_info),
-                    Arc::clone(&queue),
-                )),
-                Some("!desc") => spawn(description(
-                    msg.0,
-                    Arc::clone(&state),
-                    Arc::clone(&state_info),
-                )),
-                Some("!radiozu") => {
-                    spawn(radiozu(msg.0, Arc::clone(&state), Arc::clone(&state_info)))
-                }
-                Some("!radiovirgin") => spawn(radiovirgin(
-                    msg.0,
-                    Arc::clone(&state),
-                    Arc::clone(&state_info),
-                )),
-                Some("!radio24house") => spawn(radio24house(
-                    msg.0,
-                    Arc::clone(&state),
-                    Arc::clone(&state_info),
-                )),
-                Some("!radioclubbers") => spawn(radioclubbers(
-                    msg.0,
-                    Arc::clone(&state),
-                    Arc::clone(&state_info),
-                )),
-                Some("!radiouv") => {
-                    spawn(radiouv(msg.0, Arc::clone(&state), Arc::clone(&state_info)))
-                }
-                Some("!radiohouse") => spawn(radiohouse(
-                    msg.0,
-                    Arc::clone(&state),
-                    Arc::clone(&state_info),
-                )),
-                Some("!radiodancefm") => spawn(radiodancefmro(
-                    msg.0,
-                    Arc::clone(&state),
-                    Arc::clone(&state_info),
-                )),
-                Some("!volume") => {
-                    spawn(volume(msg.0, Arc::clone(&state), Arc::clone(&state_info)))
-                }
-                Some("!repeat") => spawn(time(msg.0, Arc::clone(&state), Arc::clone(&state_info))),
-                Some("!reload") => {
-                    exit(3);
-                }
-                _ => continue,
-            }
+/// Moves the volume of `handle` to `volume` over `duration`, giving up if the track ends.
+async fn fade_volume(handle: &dyn PlayingTrack, volume: f32, duration: Duration) {
+    let from = match handle.get_info().await {
+        Ok(info) => info.volume,
+        Err(_) => return,
+    };
+    let start = tokio::time::Instant::now();
+    for (at, step) in fade::steps(from, volume, duration) {
+        tokio::time::sleep_until(start + at).await;
+        if handle.set_volume(step).is_err() {
+            return;
         }
     }
+}
 
-    Ok(())
+/// Makes way for another track: what the guild is playing fades out over `fade_out` and is
+/// then stopped together with the ffmpeg feeding it, in the background so the new track
+/// doesn't wait.
+async fn replace_playing(
+    state: &State,
+    state_info: &Arc<Mutex<StateInfo>>,
+    guild_id: Id<GuildMarker>,
+    fade_out: Duration,
+) {
+    let handle = state.trackdata.write().await.remove(&guild_id);
+    let ffmpeg_ids = {
+        let mut info = state_info.lock().await;
+        info.set_is_playing(false);
+        std::mem::take(&mut info.ffmpeg_id)
+    };
+    spawn(async move {
+        if let Some(handle) = handle {
+            fade_volume(handle.as_ref(), 0.0, fade_out).await;
+            let _ = handle.stop();
+        }
+        for id in ffmpeg_ids {
+            kill_process(id);
+        }
+        Ok(())
+    });
 }
 
 async fn join(
@@ -380,96 +602,96 @@ async fn join(
     let guild_id = msg.guild_id.ok_or("Can't join a non-guild channel.")?;
     let user_id = msg.author.id;
 
-    let channel_to_join: Option<u64>;
-
-    let user_ch = state.cache.voice_state(user_id, guild_id.clone());
-    match user_ch {
-        Some(test01) => {
-            channel_to_join = Some(test01.channel_id().get());
-        }
+    let user_ch = state
+        .cache
+        .voice_state(user_id, guild_id)
+        .map(|voice_state| voice_state.channel_id());
+    let channel_to_join = match user_ch {
+        Some(channel_id) => channel_id,
         None => {
             state
-                .http
-                .create_message(msg.channel_id)
-                .content("You're not in a voice channel?")?
+                .messenger
+                .send_message(msg.channel_id, "You're not in a voice channel?")
                 .await?;
             return Ok(());
         }
-    }
-    let (_handle, success) = state
-        .songbird
-        .join(
-            guild_id.into_nonzero(),
-            state
-                .cache
-                .voice_state(user_id, guild_id.clone())
-                .unwrap()
-                .value()
-                .channel_id(),
-        )
-        .await;
+    };
+    join_channel(
+        &state,
+        &state_info,
+        guild_id,
+        channel_to_join,
+        msg.channel_id,
+    )
+    .await?;
 
+    Ok(())
+}
+
+/// Joins `channel_to_join`, reporting how it went in `text_channel_id`.
+async fn join_channel(
+    state: &State,
+    state_info: &Arc<Mutex<StateInfo>>,
+    guild_id: Id<GuildMarker>,
+    channel_to_join: Id<ChannelMarker>,
+    text_channel_id: Id<ChannelMarker>,
+) -> Result<bool, Box<dyn Error + Send + Sync + 'static>> {
+    let success = state.voice.join(guild_id, channel_to_join).await;
+
+    let joined = success.is_ok();
     let content: String = match success {
         Ok(()) => {
             state_info.lock().await.set_is_joined(true);
-            format!("Joined <#{}>!", channel_to_join.unwrap_or_default())
+            format!("Joined <#{}>!", channel_to_join)
         }
 
-        Err(e) => format!(
-            "Failed to join <#{}>! Why: {:?}",
-            channel_to_join.unwrap_or_default(),
-            e
-        ),
+        Err(e) => format!("Failed to join <#{}>! Why: {:?}", channel_to_join, e),
     };
     state
-        .http
-        .create_message(msg.channel_id)
-        .content(&content)?
+        .messenger
+        .send_message(text_channel_id, &content)
         .await?;
 
-    Ok(())
+    Ok(joined)
 }
 async fn leave(
     msg: Message,
     state: State,
     state_info: Arc<Mutex<StateInfo>>,
+    queue: Arc<Mutex<Queue1>>,
 ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-    let guild_id = msg.guild_id.unwrap();
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
 
     if state_info.lock().await.is_joined {
-        if let Some(call_lock) = state.songbird.get(guild_id) {
-            let mut call = call_lock.lock().await;
-            let _ = call.stop();
-            state_info.lock().await.set_is_playing(false);
-        }
-        let activity = Activity::from(MinimalActivity {
-            kind: ActivityType::Listening,
-            name: "Nothing".to_string(),
-            url: None,
-        });
-        let request = UpdatePresence::new(Vec::from([activity]), false, None, Status::Online)?;
-
-        for shard in state.cluster.shards() {
-            shard.command(&request).await?;
-        }
-        state.songbird.leave(guild_id).await?;
-        state_info.lock().await.set_is_joined(false);
-        state.songbird.remove(guild_id).await?;
+        disconnect(&state, &state_info, &queue, guild_id).await?;
         state
-            .http
-            .create_message(msg.channel_id)
-            .content("Left the channel")?
+            .messenger
+            .send_message(msg.channel_id, "Left the channel")
             .await?;
     } else {
         state
-            .http
-            .create_message(msg.channel_id)
-            .content("Not in a channel")?
+            .messenger
+            .send_message(msg.channel_id, "Not in a channel")
             .await?;
     }
 
     Ok(())
 }
+/// Stops everything like `!stop`, without a fade, and leaves the voice channel.
+async fn disconnect(
+    state: &State,
+    state_info: &Arc<Mutex<StateInfo>>,
+    queue: &Arc<Mutex<Queue1>>,
+    guild_id: Id<GuildMarker>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    stop_playback(state, state_info, queue, guild_id, Duration::ZERO).await;
+    state.presence.set_listening("Nothing").await?;
+    state_info.lock().await.set_is_joined(false);
+    state.voice.leave(guild_id).await?;
+
+    Ok(())
+}
+
 async fn play(
     msg: Message,
     state: State,
@@ -491,248 +713,2502 @@ async fn play(
     }
 
     if state_info.lock().await.is_joined {
-        let b = msg.content.clone();
-        let index1 = b.find(" ");
-        let mut text: String;
-        if !index1.is_none() {
-            text = b.chars().skip(index1.unwrap()).collect();
-        } else {
-            text = "".to_string();
-        }
-        text = text.trim().replace(" ", "+");
-
-        let re = Regex::new(r"^(http(s)://)?((w){3}.)?youtu(be|.be)?(.com)?/.+").unwrap();
-        let re2 = Regex::new("^(http://)(.+)").unwrap();
-        let mut yt_link: String = String::from("https://www.youtube.com/watch?v=");
-        if re.is_match(&text) | re2.is_match(&text) {
-            yt_link = text.to_string().to_string();
-        } else if text.len() < 1 {
-            yt_link = String::from("http://astreaming.virginradio.ro:8000/virgin_aacp_64k");
-        } else {
-            let mut search_str: String =
-                String::from("https://www.youtube.com/results?search_query=");
-            search_str.push_str(&text);
-
-            let content = reqwest::get(search_str.to_string()).await?.text().await?;
-            yt_link.push_str(
-                &yt_utils::_extract_links(content.as_str())
-                    .iter()
-                    //  .skip(1)
-                    .next()
-                    .unwrap()
-                    .to_string(),
-            );
-            state_info
-                .lock()
-                .await
-                .set_current_song_link(yt_link.clone());
-        }
-
-        let guild_id = msg.guild_id.unwrap();
-
-        let mut que1 = queue.lock().await;
-        let queue_list = que1.queue.clone();
-        que1.remove_fist();
-
-        //   let queue_list = &queue.lock().await.queue;
-        let mut src;
-        if !queue_list.is_empty() {
-            src = queue_list[0].clone();
-        } else {
-            src = YoutubeDl::new(reqwest::Client::new(), yt_link);
+        let playlist = msg
+            .attachments
+            .iter()
+            .find(|attachment| playlist_file::is_playlist_file(&attachment.filename));
+        if let Some(attachment) = playlist {
+            return play_playlist(&msg, &state, &state_info, &queue, &attachment.url).await;
         }
-
-        if let Ok(metadata) = src.aux_metadata().await {
-            let content = format!(
-                "Playing **{:?}**",
-                metadata.title.as_ref().unwrap_or(&"<UNKNOWN>".to_string()),
-            );
-            state_info
-                .lock()
-                .await
-                .set_current_song_length(metadata.duration);
-
+        let attachment = msg
+            .attachments
+            .iter()
+            .find(|attachment| local_files::is_audio_file(&attachment.filename));
+        if let Some(attachment) = attachment {
+            let limit = local_files::max_attachment_size();
+            if attachment.size > limit {
+                let content = format!(
+                    "{} is too big to play, files can be up to {} MB",
+                    attachment.filename,
+                    limit / (1024 * 1024)
+                );
+                state
+                    .messenger
+                    .send_message(msg.channel_id, &content)
+                    .await?;
+                return Ok(());
+            }
+            let path = local_files::download_attachment(
+                state.fetcher.as_ref(),
+                &attachment.url,
+                attachment.id.get(),
+                &attachment.filename,
+                attachment.size,
+            )
+            .await?;
+            return play_file(&msg, &state, &state_info, &queue, path).await;
+        } else if !msg.attachments.is_empty() {
             state
-                .http
-                .create_message(msg.channel_id)
-                .content(&content)?
+                .messenger
+                .send_message(
+                    msg.channel_id,
+                    "Only mp3, ogg, flac and m4a files or m3u and pls playlists can be played",
+                )
                 .await?;
+            return Ok(());
+        }
 
-            if let Some(call_lock) = state.songbird.get(guild_id) {
-                if state_info.lock().await.is_playing {
-                    let mut call = call_lock.lock().await;
-                    let _ = call.stop();
-                    state_info.lock().await.set_is_playing(false);
-                }
-
-                let mut call = call_lock.lock().await;
-                let handle = call.play_input(src.into());
-                state_info.lock().await.set_is_playing(true);
-
-                let activity = Activity::from(MinimalActivity {
-                    kind: ActivityType::Listening,
-                    name: metadata.title.unwrap_or("<unknown>".to_string()),
-                    url: None,
-                });
-                let request =
-                    UpdatePresence::new(Vec::from([activity]), false, None, Status::Online)?;
-
-                for shard in state.cluster.shards() {
-                    shard.command(&request).await?;
-                }
-
-                let mut store = state.trackdata.write().await;
-                store.insert(guild_id, handle);
-
-                let gr = store.get_key_value(&guild_id);
-
-                let y = gr.unwrap().1;
-
-                let queue2 = &queue_list.clone();
+        let text = msg_utils::get_query(&msg.content);
 
-                let queue = queue2.clone();
+        let yt_link = match resolve_link(state.fetcher.as_ref(), &text).await? {
+            Some(yt_link) => yt_link,
+            None => {
+                state
+                    .messenger
+                    .send_message(msg.channel_id, "Didn't find any results")
+                    .await?;
+                return Ok(());
+            }
+        };
 
-                let _res = y.add_event(
-                    songbird::Event::Track(songbird::TrackEvent::End),
-                    Queue1 { queue },
-                );
+        let kind = match url_utils::resolve_url_kind(state.fetcher.as_ref(), &yt_link).await {
+            Ok(kind) => kind,
+            Err(why) => {
+                let content = format!("Can't play that link: {}", why);
+                state
+                    .messenger
+                    .send_message(msg.channel_id, &content)
+                    .await?;
+                return Ok(());
             }
-        } else {
-            state
-                .http
-                .create_message(msg.channel_id)
-                .content("Didn't find any results")?
-                .await?;
+        };
+        if kind == UrlKind::Playlist {
+            return play_playlist(&msg, &state, &state_info, &queue, &yt_link).await;
+        }
+        let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+        if !matches!(kind, UrlKind::Youtube | UrlKind::YtDlp) {
+            let track = Track::new(MediaItemType::DirectUrl, yt_link, &msg.author);
+            return play_url(
+                &state,
+                &state_info,
+                &queue,
+                guild_id,
+                msg.channel_id,
+                track,
+                kind,
+            )
+            .await;
         }
+
+        let mut que1 = queue.lock().await;
+        // A link asked for now beats a pick autoplay queued.
+        que1.drop_autoplay();
+        let queued = que1.take_next();
+        drop(que1);
+        let track = match queued {
+            Some(track) => track,
+            None => Track::new(MediaItemType::Youtube, yt_link, &msg.author),
+        };
+        play_queued(
+            &state,
+            &state_info,
+            guild_id,
+            msg.channel_id,
+            track,
+            &queue,
+            PlayOptions::default(),
+        )
+        .await?;
     }
     let elapsed = now.elapsed();
     println!("Elapsed Youtube: {:.2?}", elapsed);
     Ok(())
 }
 
-async fn pause(
-    msg: Message,
-    state: State,
-    _state_info: Arc<Mutex<StateInfo>>,
+/// Plays the first link of an M3U or PLS playlist and queues the others. HLS playlists are a
+/// single stream and play as one.
+async fn play_playlist(
+    msg: &Message,
+    state: &State,
+    state_info: &Arc<Mutex<StateInfo>>,
+    queue: &Arc<Mutex<Queue1>>,
+    url: &str,
 ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-    let guild_id = msg.guild_id.unwrap();
-
-    let store = state.trackdata.read().await;
-
-    let content = if let Some(handle) = store.get(&guild_id) {
-        let info = handle.get_info().await?;
-
-        let paused = match info.playing {
-            PlayMode::Play => {
-                let _success = handle.pause();
-                false
-            }
-            _ => {
-                let _success = handle.play();
-                true
-            }
-        };
-
-        let action = if paused { "Unpaused" } else { "Paused" };
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+    let text = state.fetcher.fetch_text(url).await?;
+    if playlist_file::is_hls(&text) {
+        let track = Track::new(MediaItemType::DirectUrl, url, &msg.author);
+        return play_url(
+            state,
+            state_info,
+            queue,
+            guild_id,
+            msg.channel_id,
+            track,
+            UrlKind::Hls,
+        )
+        .await;
+    }
 
-        format!("{} the track", action)
-    } else {
-        format!("No track to (un)pause!")
+    let name = url_utils::display_name(url);
+    let mut entries = playlist_file::parse(&text, Some(url)).into_iter();
+    let first = match entries.next() {
+        Some(first) => first,
+        None => {
+            state
+                .messenger
+                .send_message(msg.channel_id, "There are no links in that playlist")
+                .await?;
+            return Ok(());
+        }
     };
+    let tracks: Vec<Track> = entries
+        .map(|entry| entry.to_track(&name, msg.author.id, &msg.author.name))
+        .collect();
+    if !tracks.is_empty() {
+        let content = format!(
+            "Added {} tracks from **{}** to the queue",
+            tracks.len(),
+            name
+        );
+        queue.lock().await.add(tracks);
+        state
+            .messenger
+            .send_message(msg.channel_id, &content)
+            .await?;
+    }
 
-    state
-        .http
-        .create_message(msg.channel_id)
-        .content(&content)?
-        .await?;
+    play_queued(
+        state,
+        state_info,
+        guild_id,
+        msg.channel_id,
+        first.to_track(&name, msg.author.id, &msg.author.name),
+        queue,
+        PlayOptions::default(),
+    )
+    .await?;
 
     Ok(())
 }
 
-async fn stop(
-    msg: Message,
-    state: State,
-    state_info: Arc<Mutex<StateInfo>>,
-) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-    let guild_id = msg.guild_id.unwrap();
-
-    if let Some(call_lock) = state.songbird.get(guild_id.into_nonzero()) {
-        if !state_info.lock().await.ffmpeg_id.is_empty() {
-            let id = state_info.lock().await.ffmpeg_id.pop().unwrap();
-            //println!("ID: {:?} \n",id);
-            let sp = Command::new("kill").arg(id.to_string()).output();
-            let _res = sp.unwrap();
-        }
-        if state_info.lock().await.is_playing {
-            let mut call = call_lock.lock().await;
-            let _ = call.stop();
-            state_info.lock().await.set_is_playing(false);
-        }
-        let activity = Activity::from(MinimalActivity {
-            kind: ActivityType::Listening,
-            name: "Nothing".to_owned(),
-            url: None,
-        });
-        let request = UpdatePresence::new(Vec::from([activity]), false, None, Status::Online)?;
-
-        for shard in state.cluster.shards() {
-            shard.command(&request).await?;
-        }
+/// Whether `track` is known to be played through yt-dlp without asking its server.
+fn plays_through_yt_dlp(track: &Track) -> bool {
+    match track.kind {
+        MediaItemType::Youtube | MediaItemType::Autoplay => true,
+        _ => matches!(
+            url_utils::classify_url(&track.source_url),
+            Some(UrlKind::Youtube | UrlKind::YtDlp)
+        ),
     }
+}
+
+/// Plays a queued `track` the way `!play` plays its link: through yt-dlp, or straight from
+/// the server for audio files and streams. Returns the track as now playing, or `None` when
+/// it can't be played.
+async fn play_queued(
+    state: &State,
+    state_info: &Arc<Mutex<StateInfo>>,
+    guild_id: Id<GuildMarker>,
+    channel_id: Id<ChannelMarker>,
+    track: Track,
+    queue: &Arc<Mutex<Queue1>>,
+    options: PlayOptions,
+) -> Result<Option<Track>, Box<dyn Error + Send + Sync + 'static>> {
+    let kind = if plays_through_yt_dlp(&track) {
+        UrlKind::Youtube
+    } else {
+        match url_utils::resolve_url_kind(state.fetcher.as_ref(), &track.source_url).await {
+            Ok(kind) => kind,
+            Err(why) => {
+                let content = format!("Can't play **{}**: {}", track.display_title(), why);
+                state.messenger.send_message(channel_id, &content).await?;
+                return Ok(None);
+            }
+        }
+    };
+    match kind {
+        UrlKind::Youtube | UrlKind::YtDlp => {
+            play_youtube(
+                state, state_info, guild_id, channel_id, track, queue, options,
+            )
+            .await
+        }
+        UrlKind::Playlist => {
+            state
+                .messenger
+                .send_message(channel_id, "Playlists inside playlists aren't played")
+                .await?;
+            Ok(None)
+        }
+        kind => {
+            play_url(
+                state,
+                state_info,
+                queue,
+                guild_id,
+                channel_id,
+                track.clone(),
+                kind,
+            )
+            .await?;
+            Ok(Some(track))
+        }
+    }
+}
+
+/// yt-dlp's metadata for `url`, asking it only when the cache doesn't know the link.
+async fn resolve_metadata(
+    state: &State,
+    url: &str,
+) -> Result<AuxMetadata, Box<dyn Error + Send + Sync + 'static>> {
+    if let Some(metadata) = state.metadata.get(url).await.filter(Metadata::is_resolved) {
+        return Ok(metadata.to_aux());
+    }
+    let stream = state.streams.resolve(url).await?;
+    let metadata = state.metadata.insert(url, stream.metadata).await;
+    Ok(metadata.to_aux())
+}
+
+/// The watch page details of a YouTube link, like its description, fetched once until
+/// they expire from the cache.
+async fn video_details(
+    fetcher: &dyn Fetcher,
+    metadata: &MetadataCache,
+    url: &str,
+) -> Result<Metadata, Box<dyn Error + Send + Sync + 'static>> {
+    if let Some(details) = metadata
+        .get(url)
+        .await
+        .filter(|details| details.description.is_some())
+    {
+        return Ok(details);
+    }
+    let info = yt_utils::get_info(fetcher, url).await?;
+    Ok(metadata.insert(url, Metadata::from_yt_info(&info)).await)
+}
+
+/// How `play_youtube` starts a track.
+#[derive(Debug, Default, Clone, Copy)]
+struct PlayOptions {
+    /// Fade in over this while the track before fades out.
+    crossfade: Duration,
+    /// Play the track that's playing again from here, after its filters changed.
+    resume_at: Option<Duration>,
+}
+
+/// Plays `track` through yt-dlp, with `queue` left to play after it, and through ffmpeg
+/// when the guild has filters on. Returns the track as now playing, or `None` when yt-dlp
+/// found nothing.
+async fn play_youtube(
+    state: &State,
+    state_info: &Arc<Mutex<StateInfo>>,
+    guild_id: Id<GuildMarker>,
+    channel_id: Id<ChannelMarker>,
+    track: Track,
+    queue: &Arc<Mutex<Queue1>>,
+    options: PlayOptions,
+) -> Result<Option<Track>, Box<dyn Error + Send + Sync + 'static>> {
+    let src = YoutubeDl::new(reqwest::Client::new(), track.source_url.clone());
+
+    let metadata = match resolve_metadata(state, &track.source_url).await {
+        Ok(metadata) => metadata,
+        Err(_) => {
+            state
+                .messenger
+                .send_message(channel_id, "Didn't find any results")
+                .await?;
+            return Ok(None);
+        }
+    };
+    let guild = state.guild_store.get(guild_id).await?;
+    let effects = youtube_effects(state, &guild, &track.source_url).await;
+    let mut content = format!(
+        "Playing **{:?}**",
+        metadata.title.as_ref().unwrap_or(&"<UNKNOWN>".to_string()),
+    );
+    if !effects.is_empty() {
+        content.push_str(&format!(" with {}", effects.describe()));
+    }
+    let track = track.with_metadata(&metadata);
+    // A restarted track may have picked up details since it started; now playing has to
+    // match the track its end is reported for, or the queue stops there.
+    state_info.lock().await.set_now_playing(Some(track.clone()));
+    if options.resume_at.is_none() {
+        record_history(state, guild_id, &track).await;
+
+        state.messenger.send_message(channel_id, &content).await?;
+    }
+
+    let prefetched = queue.lock().await.take_prefetched(&track);
+    let crossfade = options.crossfade;
+    let fade = guild.fade;
+
+    if state.voice.is_connected(guild_id) {
+        let volume = guild.volume.gain();
+        let initial_volume = if crossfade.is_zero() { volume } else { 0.0 };
+        let fade_out = if !crossfade.is_zero() {
+            crossfade
+        } else if options.resume_at.is_some() {
+            Duration::ZERO
+        } else {
+            fade.fade_out
+        };
+        replace_playing(state, state_info, guild_id, fade_out).await;
+
+        let start = options.resume_at.unwrap_or_default();
+        let input = if effects.is_empty() && start.is_zero() {
+            match prefetched {
+                Some(stream) => ready_input(state.streams.as_ref(), stream, src).await,
+                None => src.into(),
+            }
+        } else {
+            ffmpeg_input(
+                state.streams.as_ref(),
+                state_info,
+                prefetched,
+                &track.source_url,
+                &effects,
+                start,
+            )
+            .await?
+        };
+        state_info
+            .lock()
+            .await
+            .set_timeline(effects.timeline(start));
+
+        let played = state
+            .voice
+            .play(guild_id, AudioTrack::new(input).volume(initial_volume))
+            .await;
+        if let Some(handle) = played {
+            state_info.lock().await.set_is_playing(true);
+            if !crossfade.is_zero() {
+                let fading = Arc::clone(&handle);
+                spawn(async move {
+                    fade_volume(fading.as_ref(), volume, crossfade).await;
+                    Ok(())
+                });
+            }
+
+            let name = metadata.title.as_deref().unwrap_or("<unknown>");
+            state.presence.set_listening(name).await?;
+
+            let ending = TrackEnd {
+                state: Arc::clone(state),
+                state_info: Arc::clone(state_info),
+                queue: Arc::clone(queue),
+                guild_id,
+                channel_id,
+                track: track.clone(),
+            };
+            if !fade.crossfade.is_zero() && !track.is_live() {
+                spawn(crossfade_next(
+                    ending.clone(),
+                    Arc::clone(&handle),
+                    fade.crossfade,
+                ));
+            }
+            let _res = handle.add_event(songbird::TrackEvent::End, Arc::new(ending));
+
+            let mut store = state.trackdata.write().await;
+            store.insert(guild_id, handle);
+        }
+    }
+    let (next_state, next_info, next_queue) =
+        (Arc::clone(state), Arc::clone(state_info), Arc::clone(queue));
+    let playing = track.clone();
+    spawn(async move {
+        queue_autoplay(
+            Arc::clone(&next_state),
+            Arc::clone(&next_queue),
+            guild_id,
+            playing,
+        )
+        .await?;
+        prefetch_next(next_state, next_info, next_queue).await
+    });
+
+    Ok(Some(track))
+}
+
+/// Starts the next queued track `crossfade` before the one in `ending` is over, so the two
+/// overlap. Does nothing once the track is stopped or replaced.
+async fn crossfade_next(
+    ending: TrackEnd,
+    handle: Arc<dyn PlayingTrack>,
+    crossfade: Duration,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let length = match ending.track.duration {
+        Some(length) if length > crossfade * 2 => length,
+        _ => return Ok(()),
+    };
+    loop {
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        let timeline = {
+            let info = ending.state_info.lock().await;
+            if info.now_playing.as_ref() != Some(&ending.track) {
+                return Ok(());
+            }
+            info.timeline
+        };
+        let info = match handle.get_info().await {
+            Ok(info) => info,
+            Err(_) => return Ok(()),
+        };
+        match info.playing {
+            PlayMode::Play if timeline.remaining(length, info.position) <= crossfade => break,
+            PlayMode::Play | PlayMode::Pause => {}
+            _ => return Ok(()),
+        }
+    }
+
+    let next = ending.queue.lock().await.take_next();
+    if let Some(track) = next {
+        play_queued(
+            &ending.state,
+            &ending.state_info,
+            ending.guild_id,
+            ending.channel_id,
+            track,
+            &ending.queue,
+            PlayOptions {
+                crossfade,
+                ..PlayOptions::default()
+            },
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// The guild's effects for a YouTube link, leveled by the loudness YouTube measured for it
+/// when `!normalize` is on and the watch page says.
+async fn youtube_effects(state: &State, guild: &GuildData, url: &str) -> Effects {
+    if !guild.normalize || yt_utils::video_id(url).is_none() {
+        return guild.effects(None);
+    }
+    let loudness = match video_details(state.fetcher.as_ref(), &state.metadata, url).await {
+        Ok(details) => details.loudness_db,
+        Err(why) => {
+            println!("No loudness for {}: {}", url, why);
+            None
+        }
+    };
+    guild.effects(loudness)
+}
+
+/// The stream yt-dlp finds for `source_url` through ffmpeg with `effects`, starting `start`
+/// into the track. `stream` is used when it was prefetched and is still good.
+async fn ffmpeg_input(
+    streams: &dyn StreamResolver,
+    state_info: &Arc<Mutex<StateInfo>>,
+    stream: Option<ResolvedStream>,
+    source_url: &str,
+    effects: &Effects,
+    start: Duration,
+) -> Result<Input, Box<dyn Error + Send + Sync + 'static>> {
+    let stream = match stream.filter(|stream| stream.is_fresh(SystemTime::now())) {
+        Some(stream) => stream,
+        None => streams.resolve(source_url).await?,
+    };
+    let mut input_args = seek_args(start);
+    input_args.extend(stream.ffmpeg_args());
+    let ch = spawn_ffmpeg(&stream.stream_url, &input_args, effects.chain().as_deref())?;
+    state_info.lock().await.set_ffmpeg_id(ch.id());
+    Ok(ChildContainer::from(ch).into())
+}
+
+/// The prefetched stream as an input, resolved again when its link has expired in the
+/// meantime. If that fails `src` resolves it at play time as usual.
+async fn ready_input(
+    streams: &dyn StreamResolver,
+    stream: ResolvedStream,
+    src: YoutubeDl,
+) -> Input {
+    let stream = if stream.is_fresh(SystemTime::now()) {
+        stream
+    } else {
+        println!("Prefetched stream of {} expired", stream.source_url);
+        match streams.resolve(&stream.source_url).await {
+            Ok(stream) => stream,
+            Err(why) => {
+                println!("{}", why);
+                return src.into();
+            }
+        }
+    };
+    HttpRequest::new_with_headers(
+        reqwest::Client::new(),
+        stream.stream_url.clone(),
+        stream.header_map(),
+    )
+    .into()
+}
+
+/// Resolves the first queued track while another one plays, so it starts right away
+/// instead of after yt-dlp once the current track ends.
+async fn prefetch_next(
+    state: State,
+    state_info: Arc<Mutex<StateInfo>>,
+    queue: Arc<Mutex<Queue1>>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    if !state_info.lock().await.is_playing {
+        return Ok(());
+    }
+    let next = {
+        let queue = queue.lock().await;
+        let next = match queue.queue.first() {
+            // Files and streams are played straight from their server.
+            Some(track) if plays_through_yt_dlp(track) => track.source_url.clone(),
+            _ => return Ok(()),
+        };
+        let done = queue
+            .prefetched
+            .as_ref()
+            .map(|stream| stream.source_url == next && stream.is_fresh(SystemTime::now()))
+            .unwrap_or(false);
+        if done {
+            return Ok(());
+        }
+        next
+    };
+
+    let stream = state.streams.resolve(&next).await?;
+    state.metadata.insert(&next, stream.metadata.clone()).await;
+    let mut queue = queue.lock().await;
+    // The queue may have changed while yt-dlp ran.
+    if queue.queue.first().map(|track| &track.source_url) == Some(&next) {
+        println!("Prefetched {}", next);
+        queue.prefetched = Some(stream);
+    }
+
+    Ok(())
+}
+
+/// Plays what's queued after `ended`, or an autoplay pick when the queue has run out.
+async fn play_next(
+    state: State,
+    state_info: Arc<Mutex<StateInfo>>,
+    queue: Arc<Mutex<Queue1>>,
+    guild_id: Id<GuildMarker>,
+    channel_id: Id<ChannelMarker>,
+    ended: Track,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    // Stopping, leaving or playing something else ends the track too.
+    if state_info.lock().await.now_playing.as_ref() != Some(&ended) {
+        return Ok(());
+    }
+    state_info.lock().await.set_is_playing(false);
+
+    let mut next = queue.lock().await.take_next();
+    if next.is_none() && state.guild_store.get(guild_id).await?.autoplay {
+        next = autoplay_pick(&state, guild_id, &ended).await?;
+    }
+    match next {
+        Some(track) => {
+            play_queued(
+                &state,
+                &state_info,
+                guild_id,
+                channel_id,
+                track,
+                &queue,
+                PlayOptions::default(),
+            )
+            .await?;
+        }
+        None => {
+            // Nothing left: let go of the ended track rather than keep pointing at it.
+            let ffmpeg_ids = {
+                let mut info = state_info.lock().await;
+                if info.now_playing.as_ref() != Some(&ended) {
+                    return Ok(());
+                }
+                info.set_now_playing(None);
+                std::mem::take(&mut info.ffmpeg_id)
+            };
+            state.trackdata.write().await.remove(&guild_id);
+            for id in ffmpeg_ids {
+                kill_process(id);
+            }
+            state.presence.set_listening("Nothing").await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// How many of the latest played tracks autoplay won't pick again.
+const AUTOPLAY_MEMORY: usize = 20;
+
+/// A video YouTube suggests next to `track` that the guild hasn't played lately.
+async fn autoplay_pick(
+    state: &State,
+    guild_id: Id<GuildMarker>,
+    track: &Track,
+) -> Result<Option<Track>, Box<dyn Error + Send + Sync + 'static>> {
+    if yt_utils::video_id(&track.source_url).is_none() {
+        return Ok(None);
+    }
+    let played: Vec<String> = state
+        .guild_store
+        .get(guild_id)
+        .await?
+        .history
+        .iter()
+        .rev()
+        .take(AUTOPLAY_MEMORY)
+        .filter_map(|entry| yt_utils::video_id(&entry.track.source_url))
+        .collect();
+    let related = yt_utils::get_related(state.fetcher.as_ref(), &track.source_url).await?;
+
+    Ok(related
+        .into_iter()
+        .find(|url| !matches!(yt_utils::video_id(url), Some(id) if played.contains(&id)))
+        .map(|url| {
+            Track::requested_by(
+                MediaItemType::Autoplay,
+                url,
+                track.requester_id,
+                &track.requester,
+            )
+        }))
+}
+
+/// Queues an autoplay pick for `playing` when autoplay is on and nothing else is queued,
+/// so it shows up in `!list` ahead of time.
+async fn queue_autoplay(
+    state: State,
+    queue: Arc<Mutex<Queue1>>,
+    guild_id: Id<GuildMarker>,
+    playing: Track,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    if !queue.lock().await.queue.is_empty() || !state.guild_store.get(guild_id).await?.autoplay {
+        return Ok(());
+    }
+    if let Some(pick) = autoplay_pick(&state, guild_id, &playing).await? {
+        let mut queue = queue.lock().await;
+        // Something may have been queued while YouTube was asked.
+        if queue.queue.is_empty() {
+            queue.queue.push(pick);
+        }
+    }
+
+    Ok(())
+}
+
+async fn local(
+    msg: Message,
+    state: State,
+    state_info: Arc<Mutex<StateInfo>>,
+    queue: Arc<Mutex<Queue1>>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    if !state_info.lock().await.is_joined {
+        let res = join(msg.clone(), state.clone(), state_info.clone())
+            .await
+            .ok();
+
+        match res {
+            Some(result) => println!("{:?}", result),
+            None => println!("ERR"),
+        }
+    }
+    if state_info.lock().await.is_joined {
+        let query = msg_utils::get_args(&msg.content).to_string();
+        if query.is_empty() {
+            state
+                .messenger
+                .send_message(msg.channel_id, "Use !local <path or search>")
+                .await?;
+            return Ok(());
+        }
+
+        let dir = state.music_dir.clone();
+        let found =
+            tokio::task::spawn_blocking(move || local_files::find_local(&dir, &query)).await?;
+
+        match found {
+            Ok(Some(path)) => play_file(&msg, &state, &state_info, &queue, path).await?,
+            Ok(None) => {
+                state
+                    .messenger
+                    .send_message(msg.channel_id, "No local file matches that")
+                    .await?;
+            }
+            Err(why) => {
+                let content = format!("Music directory unavailable: {}", why);
+                state
+                    .messenger
+                    .send_message(msg.channel_id, &content)
+                    .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn play_file(
+    msg: &Message,
+    state: &State,
+    state_info: &Arc<Mutex<StateInfo>>,
+    queue: &Arc<Mutex<Queue1>>,
+    path: PathBuf,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+
+    let tags = local_files::probe_tags(&path).await.unwrap_or_default();
+    let title: String = match (&tags.artist, &tags.title) {
+        (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+        (None, Some(title)) => title.clone(),
+        _ => path
+            .file_stem()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    }
+    .chars()
+    .take(256)
+    .collect();
+    let track = Track::new(
+        MediaItemType::LocalFile,
+        path.to_string_lossy(),
+        &msg.author,
+    )
+    .with_tags(&tags);
+    record_history(state, guild_id, &track).await;
+    state_info.lock().await.set_now_playing(Some(track.clone()));
+
+    let mut embed_builder = EmbedBuilder::new()
+        .title(&title)
+        .field(EmbedFieldBuilder::new("Requestor", &msg.author.name).inline());
+    if let Some(album) = &tags.album {
+        embed_builder = embed_builder.field(EmbedFieldBuilder::new("Album", album).inline());
+    }
+    if let Some(duration) = tags.duration {
+        embed_builder = embed_builder.field(
+            EmbedFieldBuilder::new("Duration", msg_utils::format_duration(duration)).inline(),
+        );
+    }
+    let guild = state.guild_store.get(guild_id).await?;
+    let effects = guild.effects(None);
+    if !effects.is_empty() {
+        embed_builder =
+            embed_builder.field(EmbedFieldBuilder::new("Filters", effects.describe()).inline());
+    }
+    let embed = embed_builder.validate()?.build();
+
+    state
+        .messenger
+        .send_embeds(msg.channel_id, &[embed])
+        .await?;
+
+    if state.voice.is_connected(guild_id) {
+        replace_playing(state, state_info, guild_id, guild.fade.fade_out).await;
+
+        let input: Input = if effects.is_empty() {
+            FileInput::new(path).into()
+        } else {
+            let ch = spawn_ffmpeg(&path.to_string_lossy(), &[], effects.chain().as_deref())?;
+            state_info.lock().await.set_ffmpeg_id(ch.id());
+            ChildContainer::from(ch).into()
+        };
+        state_info
+            .lock()
+            .await
+            .set_timeline(effects.timeline(Duration::ZERO));
+
+        let played = state
+            .voice
+            .play(guild_id, AudioTrack::new(input).volume(guild.volume.gain()))
+            .await;
+        if let Some(handle) = played {
+            state_info.lock().await.set_is_playing(true);
+            state.presence.set_listening(&title).await?;
+
+            let ending = TrackEnd {
+                state: Arc::clone(state),
+                state_info: Arc::clone(state_info),
+                queue: Arc::clone(queue),
+                guild_id,
+                channel_id: msg.channel_id,
+                track,
+            };
+            let _res = handle.add_event(songbird::TrackEvent::End, Arc::new(ending));
+
+            let mut store = state.trackdata.write().await;
+            store.insert(guild_id, handle);
+        }
+    }
+
+    Ok(())
+}
+
+/// Plays `track`, a direct audio file over HTTP or an HLS/Icecast stream through ffmpeg,
+/// with `queue` left to play after it.
+async fn play_url(
+    state: &State,
+    state_info: &Arc<Mutex<StateInfo>>,
+    queue: &Arc<Mutex<Queue1>>,
+    guild_id: Id<GuildMarker>,
+    channel_id: Id<ChannelMarker>,
+    track: Track,
+    kind: UrlKind,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let url = track.source_url.clone();
+    record_history(state, guild_id, &track).await;
+    state_info.lock().await.set_now_playing(Some(track.clone()));
+    let guild = state.guild_store.get(guild_id).await?;
+    let effects = guild.effects(None);
+    replace_playing(state, state_info, guild_id, guild.fade.fade_out).await;
+    let input: Input = match kind {
+        UrlKind::DirectFile if effects.is_empty() => {
+            HttpRequest::new(reqwest::Client::new(), url.clone()).into()
+        }
+        _ => {
+            let ch = spawn_ffmpeg(&url, &[], effects.chain().as_deref())?;
+            state_info.lock().await.set_ffmpeg_id(ch.id());
+            ChildContainer::from(ch).into()
+        }
+    };
+    state_info
+        .lock()
+        .await
+        .set_timeline(effects.timeline(Duration::ZERO));
+    if kind != UrlKind::DirectFile {
+        watch_stream_titles(state, state_info, guild_id, channel_id).await;
+    }
+
+    let title: String = track.display_title().chars().take(256).collect();
+    let kind_name = match kind {
+        UrlKind::DirectFile => "File",
+        UrlKind::Hls => "HLS stream",
+        _ => "Live stream",
+    };
+    let mut embed_builder = EmbedBuilder::new()
+        .title(&title)
+        .url(&url)
+        .field(EmbedFieldBuilder::new("Requestor", &track.requester).inline())
+        .field(EmbedFieldBuilder::new("Type", kind_name).inline());
+    if !effects.is_empty() {
+        embed_builder =
+            embed_builder.field(EmbedFieldBuilder::new("Filters", effects.describe()).inline());
+    }
+    let embed = embed_builder.validate()?.build();
+
+    state.messenger.send_embeds(channel_id, &[embed]).await?;
+
+    let played = state
+        .voice
+        .play(guild_id, AudioTrack::new(input).volume(guild.volume.gain()))
+        .await;
+    if let Some(handle) = played {
+        state_info.lock().await.set_is_playing(true);
+        state.presence.set_listening(&title).await?;
+
+        let ending = TrackEnd {
+            state: Arc::clone(state),
+            state_info: Arc::clone(state_info),
+            queue: Arc::clone(queue),
+            guild_id,
+            channel_id,
+            track,
+        };
+        let _res = handle.add_event(songbird::TrackEvent::End, Arc::new(ending));
+
+        let mut store = state.trackdata.write().await;
+        store.insert(guild_id, handle);
+    }
+
+    Ok(())
+}
+
+async fn radio(
+    msg: Message,
+    state: State,
+    state_info: Arc<Mutex<StateInfo>>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+    let stations = guild_stations(&state, guild_id).await?;
+    let station = match stations::find_station(&stations, msg_utils::get_command(&msg.content)) {
+        Some(station) => station,
+        None => return Ok(()),
+    };
+
+    if !state_info.lock().await.is_joined {
+        let res = join(msg.clone(), state.clone(), state_info.clone())
+            .await
+            .ok();
+
+        match res {
+            Some(result) => println!("{:?}", result),
+            None => println!("ERR"),
+        }
+    }
+    if state_info.lock().await.is_joined {
+        start_station(
+            &state,
+            &state_info,
+            guild_id,
+            msg.channel_id,
+            Track::new(MediaItemType::Radio, "", &msg.author),
+            station,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// The built-in stations followed by the ones saved in the guild.
+async fn guild_stations(
+    state: &State,
+    guild_id: Id<GuildMarker>,
+) -> Result<Vec<Station>, Box<dyn Error + Send + Sync + 'static>> {
+    let mut stations = stations::builtin_stations();
+    stations.extend(state.guild_store.get(guild_id).await?.stations);
+    Ok(stations)
+}
+
+/// Announces `station` and starts playing its healthiest stream. `track` says who asked
+/// for it; the started track is returned.
+async fn start_station(
+    state: &State,
+    state_info: &Arc<Mutex<StateInfo>>,
+    guild_id: Id<GuildMarker>,
+    channel_id: Id<ChannelMarker>,
+    track: Track,
+    station: Station,
+) -> Result<Track, Box<dyn Error + Send + Sync + 'static>> {
+    let source = ImageSource::url(&station.image)?;
+
+    let embed = EmbedBuilder::new()
+        .title(&station.name)
+        .field(EmbedFieldBuilder::new("Requestor", &track.requester).inline())
+        .image(source)
+        .validate()?
+        .build();
+
+    state.messenger.send_embeds(channel_id, &[embed]).await?;
+
+    let urls = station.urls_by_health(&*state.station_health.read().await);
+    let track = Track {
+        title: Some(station.name.clone()),
+        thumbnail: Some(station.image.clone()),
+        ..track
+    };
+    play_station(
+        state,
+        state_info,
+        guild_id,
+        channel_id,
+        station,
+        urls,
+        track.clone(),
+        0,
+    )
+    .await?;
+
+    Ok(track)
+}
+
+/// `!radio add <alias> <url> [logo]`, `!radio remove <alias>` and `!radio list` for the
+/// stations saved in the guild.
+async fn radio_stations(
+    msg: Message,
+    state: State,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+    let args: Vec<&str> = msg_utils::get_args(&msg.content)
+        .split_whitespace()
+        .collect();
+    let usage = "Use !radio add *alias* *stream or .pls/.m3u url* [*logo url*] (or attach the playlist), !radio remove *alias* or !radio list";
+
+    let content = match args.as_slice() {
+        ["list"] => {
+            let saved = state.guild_store.get(guild_id).await?.stations;
+            if saved.is_empty() {
+                "No stations saved for this server yet".to_string()
+            } else {
+                let health = state.station_health.read().await;
+                let lines: Vec<String> = saved
+                    .iter()
+                    .map(|station| station.radiolist_line(&health))
+                    .collect();
+                drop(health);
+                send_lines(
+                    state.messenger.as_ref(),
+                    msg.channel_id,
+                    "This server's stations:",
+                    &lines,
+                )
+                .await?;
+                return Ok(());
+            }
+        }
+        ["add" | "remove", ..] if !can_manage_guild(&state, &msg) => {
+            "Only members who can manage the server can change its stations".to_string()
+        }
+        ["add", alias] if !msg.attachments.is_empty() => {
+            let url = msg.attachments[0].url.clone();
+            add_station(&state, guild_id, alias, &url, None).await?
+        }
+        ["add", alias, url] | ["add", alias, url, _] => {
+            add_station(&state, guild_id, alias, url, args.get(3).copied()).await?
+        }
+        ["remove", alias] => match stations::alias_command(alias) {
+            None => format!("There is no station called `{}`", alias),
+            Some(command) => {
+                let removed = state
+                    .guild_store
+                    .update(guild_id, |data| {
+                        let before = data.stations.len();
+                        data.stations.retain(|station| station.command != command);
+                        data.stations.len() < before
+                    })
+                    .await?;
+                if removed {
+                    format!("Removed `!{}`", command)
+                } else if stations::find_station(&stations::builtin_stations(), &command).is_some()
+                {
+                    format!("`!{}` is built in and can't be removed", command)
+                } else {
+                    format!("This server has no `!{}` station", command)
+                }
+            }
+        },
+        _ => usage.to_string(),
+    };
+    state
+        .messenger
+        .send_message(msg.channel_id, &content)
+        .await?;
+
+    Ok(())
+}
+
+/// Saves a station in the guild once its stream answers, replying with what happened. A
+/// playlist link saves every working stream it lists, later ones as fallbacks.
+async fn add_station(
+    state: &State,
+    guild_id: Id<GuildMarker>,
+    alias: &str,
+    url: &str,
+    logo: Option<&str>,
+) -> Result<String, Box<dyn Error + Send + Sync + 'static>> {
+    let command = match stations::alias_command(alias) {
+        Some(command) => command,
+        None => {
+            return Ok(format!(
+                "`{}` can't be used, pick letters and digits that aren't a bot command",
+                alias
+            ))
+        }
+    };
+    let exists = format!("`!{}` already exists, remove it first", command);
+    if stations::find_station(&guild_stations(state, guild_id).await?, &command).is_some() {
+        return Ok(exists);
+    }
+    let urls = match stations::station_streams(state.fetcher.as_ref(), url).await {
+        Ok(urls) => urls,
+        Err(why) => return Ok(format!("Can't add that station: {}", why)),
+    };
+
+    let station = stations::custom_station(command, alias, urls, logo);
+    let added = state
+        .guild_store
+        .update(guild_id, |data| {
+            // Another add may have taken the command while the stream was probed.
+            if stations::find_station(&data.stations, &station.command).is_some() {
+                return false;
+            }
+            data.stations.push(station.clone());
+            true
+        })
+        .await?;
+    if !added {
+        return Ok(exists);
+    }
+    let mut health = state.station_health.write().await;
+    for url in &station.urls {
+        health.insert(url.clone(), true);
+    }
+    drop(health);
+
+    Ok(match station.urls.len() {
+        1 => format!("Added **{}**, play it with `!{}`", alias, station.command),
+        streams => format!(
+            "Added **{}** with {} streams, play it with `!{}`",
+            alias, streams, station.command
+        ),
+    })
+}
+
+/// `!schedule <HH:MM or cron> <voice channel> <station> [duration]`, `!schedule list` and
+/// `!schedule remove <id>`.
+async fn schedule(
+    msg: Message,
+    state: State,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+    let args = msg_utils::get_args(&msg.content);
+    let words: Vec<&str> = args.split_whitespace().collect();
+
+    let content = match words.as_slice() {
+        [] | ["list"] => {
+            let schedules = state.guild_store.get(guild_id).await?.schedules;
+            if schedules.is_empty() {
+                "Nothing is scheduled. Use !schedule *HH:MM or cron* *voice channel* *station* [*duration*]".to_string()
+            } else {
+                let now = Local::now().naive_local();
+                let lines: Vec<String> = schedules
+                    .iter()
+                    .map(|schedule| {
+                        match schedule.spec().ok().and_then(|spec| spec.next_after(&now)) {
+                            Some(next) => format!(
+                                "{}, next {}",
+                                schedule.list_line(),
+                                next.format("%a %d %b %H:%M")
+                            ),
+                            None => schedule.list_line(),
+                        }
+                    })
+                    .collect();
+                send_lines(
+                    state.messenger.as_ref(),
+                    msg.channel_id,
+                    "Schedules:",
+                    &lines,
+                )
+                .await?;
+                return Ok(());
+            }
+        }
+        _ if !can_manage_guild(&state, &msg) => {
+            "Only members who can manage the server can change its schedules".to_string()
+        }
+        ["remove", id] => match id.trim_start_matches('#').parse::<u32>() {
+            Ok(id) => {
+                let removed = state
+                    .guild_store
+                    .update(guild_id, |data| {
+                        let before = data.schedules.len();
+                        data.schedules.retain(|schedule| schedule.id != id);
+                        data.schedules.len() < before
+                    })
+                    .await?;
+                if removed {
+                    format!("Removed schedule `#{}`", id)
+                } else {
+                    format!("There is no schedule `#{}`", id)
+                }
+            }
+            Err(_) => "Use !schedule remove *id*, the ids are in !schedule list".to_string(),
+        },
+        _ => add_schedule(&state, &msg, guild_id, args).await?,
+    };
+    state
+        .messenger
+        .send_message(msg.channel_id, &content)
+        .await?;
+
+    Ok(())
+}
+
+/// Saves a schedule for the guild, replying with what happened.
+async fn add_schedule(
+    state: &State,
+    msg: &Message,
+    guild_id: Id<GuildMarker>,
+    args: &str,
+) -> Result<String, Box<dyn Error + Send + Sync + 'static>> {
+    let request = match schedule::parse_request(args) {
+        Ok(request) => request,
+        Err(why) => {
+            return Ok(format!(
+                "{}. Use !schedule *HH:MM or cron* *voice channel* *station* [*duration*]",
+                why
+            ))
+        }
+    };
+    let voice_channel_id = match find_voice_channel(state, guild_id, &request.channel) {
+        Some(channel_id) => channel_id,
+        None => return Ok(format!("There is no voice channel `{}`", request.channel)),
+    };
+    let target = match request.target.strip_prefix("playlist:") {
+        Some(name) => {
+            let saved = state.guild_store.get(guild_id).await?.playlists;
+            match PlaylistName::parse(name).and_then(|name| name.find(&saved, msg.author.id)) {
+                Some(_) => ScheduleTarget::Playlist(name.to_string()),
+                None => return Ok(format!("There is no playlist `{}`, see !playlist", name)),
+            }
+        }
+        None => {
+            match stations::find_station(&guild_stations(state, guild_id).await?, &request.target) {
+                Some(station) => ScheduleTarget::Station(station.command),
+                None => {
+                    return Ok(format!(
+                        "There is no station `{}`, see !radiolist",
+                        request.target
+                    ))
+                }
+            }
+        }
+    };
+
+    let next = CronSpec::parse(&request.cron)?.next_after(&Local::now().naive_local());
+    let schedule = state
+        .guild_store
+        .update(guild_id, |data| {
+            let schedule = Schedule {
+                id: data
+                    .schedules
+                    .iter()
+                    .map(|schedule| schedule.id)
+                    .max()
+                    .unwrap_or(0)
+                    + 1,
+                cron: request.cron,
+                voice_channel_id,
+                text_channel_id: msg.channel_id,
+                target,
+                leave_after: request.leave_after,
+                requester_id: msg.author.id,
+                requester: msg.author.name.clone(),
+            };
+            data.schedules.push(schedule.clone());
+            schedule
+        })
+        .await?;
+
+    Ok(match next {
+        Some(next) => format!(
+            "Scheduled {}, first on {}",
+            schedule.list_line(),
+            next.format("%a %d %b %H:%M")
+        ),
+        None => format!(
+            "Scheduled {}, but that time never comes",
+            schedule.list_line()
+        ),
+    })
+}
+
+/// A voice channel of the guild given as a mention, an id or its name.
+fn find_voice_channel(
+    state: &State,
+    guild_id: Id<GuildMarker>,
+    text: &str,
+) -> Option<Id<ChannelMarker>> {
+    let text = text.trim_start_matches("<#").trim_end_matches('>');
+    let channel_ids = state.cache.guild_channels(guild_id)?;
+    let found = channel_ids.iter().copied().find(|channel_id| {
+        let channel = match state.cache.channel(*channel_id) {
+            Some(channel) => channel,
+            None => return false,
+        };
+        let is_voice = matches!(
+            channel.kind,
+            ChannelType::GuildVoice | ChannelType::GuildStageVoice
+        );
+        let is_named = matches!(&channel.name, Some(name) if name.eq_ignore_ascii_case(text));
+        is_voice && (channel_id.to_string() == text || is_named)
+    });
+    found
+}
+
+/// Checks the saved schedules once a minute and starts the ones that are due.
+async fn run_schedules(state: State, state_info: Arc<Mutex<StateInfo>>, queue: Arc<Mutex<Queue1>>) {
+    let mut checked = this_minute();
+    loop {
+        let second = u64::from(Local::now().second());
+        tokio::time::sleep(Duration::from_secs(60 - second.min(59))).await;
+
+        let now = this_minute();
+        // Catch up on minutes a slow tick skipped, but not on hours the host was asleep.
+        let mut minute =
+            (checked + chrono::Duration::minutes(1)).max(now - chrono::Duration::minutes(5));
+        while minute <= now {
+            if let Err(why) = start_due_schedules(&state, &state_info, &queue, &minute).await {
+                println!("Couldn't check schedules: {}", why);
+            }
+            minute += chrono::Duration::minutes(1);
+        }
+        checked = checked.max(now);
+    }
+}
+
+fn this_minute() -> NaiveDateTime {
+    let now = Local::now().naive_local();
+    now.with_second(0)
+        .and_then(|time| time.with_nanosecond(0))
+        .unwrap_or(now)
+}
+
+async fn start_due_schedules(
+    state: &State,
+    state_info: &Arc<Mutex<StateInfo>>,
+    queue: &Arc<Mutex<Queue1>>,
+    minute: &NaiveDateTime,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    for guild_id in state.guild_store.guild_ids().await? {
+        for schedule in state.guild_store.get(guild_id).await?.schedules {
+            match schedule.spec() {
+                Ok(spec) if spec.matches(minute) => spawn(run_schedule(
+                    Arc::clone(state),
+                    Arc::clone(state_info),
+                    Arc::clone(queue),
+                    guild_id,
+                    schedule,
+                )),
+                Ok(_) => {}
+                Err(why) => println!(
+                    "Schedule #{} in {} is broken: {}",
+                    schedule.id, guild_id, why
+                ),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Joins the scheduled channel, plays the station or playlist and leaves again after `leave_after`
+/// unless something else was started meanwhile.
+async fn run_schedule(
+    state: State,
+    state_info: Arc<Mutex<StateInfo>>,
+    queue: Arc<Mutex<Queue1>>,
+    guild_id: Id<GuildMarker>,
+    schedule: Schedule,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let (station, mut tracks) = match &schedule.target {
+        ScheduleTarget::Station(command) => (
+            stations::find_station(&guild_stations(&state, guild_id).await?, command),
+            Vec::new(),
+        ),
+        ScheduleTarget::Playlist(name) => (
+            None,
+            playlist_tracks(
+                &state,
+                guild_id,
+                name,
+                schedule.requester_id,
+                &schedule.requester,
+            )
+            .await?,
+        ),
+    };
+    if station.is_none() && tracks.is_empty() {
+        let content = format!(
+            "Schedule `#{}` wants {}, which doesn't exist anymore",
+            schedule.id,
+            schedule.target_label()
+        );
+        state
+            .messenger
+            .send_message(schedule.text_channel_id, &content)
+            .await?;
+        return Ok(());
+    }
+
+    let joined = join_channel(
+        &state,
+        &state_info,
+        guild_id,
+        schedule.voice_channel_id,
+        schedule.text_channel_id,
+    )
+    .await?;
+    if !joined {
+        return Ok(());
+    }
+    let track = match station {
+        Some(station) => {
+            let requested = Track::requested_by(
+                MediaItemType::Radio,
+                "",
+                schedule.requester_id,
+                &schedule.requester,
+            );
+            start_station(
+                &state,
+                &state_info,
+                guild_id,
+                schedule.text_channel_id,
+                requested,
+                station,
+            )
+            .await?
+        }
+        None => {
+            let first = tracks.remove(0);
+            queue.lock().await.add(tracks);
+            let playing = play_queued(
+                &state,
+                &state_info,
+                guild_id,
+                schedule.text_channel_id,
+                first,
+                &queue,
+                PlayOptions::default(),
+            )
+            .await?;
+            match playing {
+                Some(track) => track,
+                None => return Ok(()),
+            }
+        }
+    };
+
+    if let Some(leave_after) = schedule.leave_after {
+        tokio::time::sleep(leave_after).await;
+        // Reconnects keep the track's `added_at`, anything started since doesn't.
+        let still_playing = state_info
+            .lock()
+            .await
+            .now_playing
+            .as_ref()
+            .map(|playing| playing.added_at)
+            == Some(track.added_at);
+        if still_playing {
+            disconnect(&state, &state_info, &queue, guild_id).await?;
+            let content = format!("Schedule `#{}` is over, see you next time", schedule.id);
+            state
+                .messenger
+                .send_message(schedule.text_channel_id, &content)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// `!playlist save/load/show/delete/export <name>`, `!playlist add <name> <link or search>`,
+/// `!playlist remove <name> <number>` and `!playlist import <name>` with an attachment or
+/// link. Names are the author's playlists, or the guild's as `server:<name>`.
+async fn playlist(
+    msg: Message,
+    state: State,
+    state_info: Arc<Mutex<StateInfo>>,
+    queue: Arc<Mutex<Queue1>>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+    let user_id = msg.author.id;
+    let words: Vec<&str> = msg_utils::get_args(&msg.content)
+        .split_whitespace()
+        .collect();
+    let usage = "Use !playlist save/load/show/delete/export *name*, !playlist add *name* *link or search*, !playlist remove *name* *number* or !playlist import *name* with a .txt, .m3u or .pls file";
+
+    let (command, name, rest) = match words.as_slice() {
+        [] | ["list"] => return list_playlists(&state, &msg, guild_id).await,
+        [command, name, rest @ ..] => (*command, *name, rest),
+        _ => {
+            state.messenger.send_message(msg.channel_id, usage).await?;
+            return Ok(());
+        }
+    };
+    let name = match PlaylistName::parse(name) {
+        Some(name) => name,
+        None => {
+            let content = "Playlist names are letters, digits, - and _, with server: in front for this server's playlists";
+            state
+                .messenger
+                .send_message(msg.channel_id, content)
+                .await?;
+            return Ok(());
+        }
+    };
+    let changes = matches!(command, "save" | "add" | "remove" | "delete" | "import");
+    if changes && name.shared && !can_manage_guild(&state, &msg) {
+        state
+            .messenger
+            .send_message(
+                msg.channel_id,
+                "Only members who can manage the server can change its playlists",
+            )
+            .await?;
+        return Ok(());
+    }
+    let label = if name.shared {
+        format!("server:{}", name.name)
+    } else {
+        name.name.clone()
+    };
+
+    let content = match (command, rest) {
+        ("save", []) => {
+            let mut tracks: Vec<Track> = state_info
+                .lock()
+                .await
+                .now_playing
+                .iter()
+                .cloned()
+                .collect();
+            tracks.extend(queue.lock().await.queue.iter().cloned());
+            let entries: Vec<PlaylistEntry> = tracks
+                .iter()
+                .filter_map(PlaylistEntry::from_track)
+                .take(playlists::MAX_ENTRIES)
+                .collect();
+            if entries.is_empty() {
+                "There's nothing in the queue that can be saved".to_string()
+            } else {
+                let count = entries.len();
+                save_playlist(&state, guild_id, user_id, &name, |playlist| {
+                    playlist.entries = entries;
+                })
+                .await?;
+                format!("Saved **{}** with {} tracks", label, count)
+            }
+        }
+        ("load", []) => {
+            let tracks =
+                playlist_tracks(&state, guild_id, &label, user_id, &msg.author.name).await?;
+            if tracks.is_empty() {
+                format!("There is no playlist **{}** or it's empty", label)
+            } else {
+                let count = tracks.len();
+                queue.lock().await.add(tracks);
+                spawn(prefetch_next(
+                    Arc::clone(&state),
+                    Arc::clone(&state_info),
+                    Arc::clone(&queue),
+                ));
+                format!("Added {} tracks from **{}** to the queue", count, label)
+            }
+        }
+        ("show", []) => {
+            let saved = state.guild_store.get(guild_id).await?.playlists;
+            match name.find(&saved, user_id) {
+                Some(playlist) if !playlist.entries.is_empty() => {
+                    let lines: Vec<String> = playlist
+                        .entries
+                        .iter()
+                        .map(|entry| match entry.duration {
+                            Some(duration) => format!(
+                                "{} ({})",
+                                entry.display_title(),
+                                msg_utils::format_duration(duration)
+                            ),
+                            None => entry.display_title(),
+                        })
+                        .collect();
+                    let description = format!("{} - {} tracks", playlist.label(), lines.len());
+                    send_lines(
+                        state.messenger.as_ref(),
+                        msg.channel_id,
+                        &description,
+                        &lines,
+                    )
+                    .await?;
+                    return Ok(());
+                }
+                Some(playlist) => format!("**{}** is empty", playlist.label()),
+                None => format!("There is no playlist **{}**", label),
+            }
+        }
+        ("delete", []) => {
+            let owner = name.owner(user_id);
+            let deleted = state
+                .guild_store
+                .update(guild_id, |data| {
+                    let before = data.playlists.len();
+                    data.playlists.retain(|playlist| {
+                        !(playlist.name == name.name && playlist.owner == owner)
+                    });
+                    data.playlists.len() < before
+                })
+                .await?;
+            if deleted {
+                format!("Deleted **{}**", label)
+            } else {
+                format!("You have no playlist **{}**", label)
+            }
+        }
+        ("add", query) if !query.is_empty() => {
+            match resolve_link(state.fetcher.as_ref(), &query.join("+")).await? {
+                Some(url) => {
+                    let metadata = resolve_metadata(&state, &url).await.ok();
+                    let entry = PlaylistEntry {
+                        title: metadata
+                            .as_ref()
+                            .and_then(|metadata| metadata.title.clone()),
+                        duration: metadata.as_ref().and_then(|metadata| metadata.duration),
+                        url,
+                    };
+                    let title = entry.display_title();
+                    let added = save_playlist(&state, guild_id, user_id, &name, |playlist| {
+                        if playlist.entries.len() < playlists::MAX_ENTRIES {
+                            playlist.entries.push(entry);
+                            true
+                        } else {
+                            false
+                        }
+                    })
+                    .await?;
+                    if added {
+                        format!("Added **{}** to **{}**", title, label)
+                    } else {
+                        format!("**{}** is full", label)
+                    }
+                }
+                None => "Didn't find any results".to_string(),
+            }
+        }
+        ("remove", [number]) => match number.parse::<usize>() {
+            Ok(index) => {
+                let removed = state
+                    .guild_store
+                    .update(guild_id, |data| {
+                        let playlist = name.find_mut(&mut data.playlists, user_id)?;
+                        (index < playlist.entries.len()).then(|| playlist.entries.remove(index))
+                    })
+                    .await?;
+                match removed {
+                    Some(entry) => {
+                        format!("Removed **{}** from **{}**", entry.display_title(), label)
+                    }
+                    None => format!(
+                        "**{}** has no track {}, the numbers are in !playlist show",
+                        label, index
+                    ),
+                }
+            }
+            Err(_) => format!("Use !playlist remove {} *number*", label),
+        },
+        ("export", []) | ("export", ["m3u" | "pls" | "txt"]) => {
+            let saved = state.guild_store.get(guild_id).await?.playlists;
+            match name.find(&saved, user_id) {
+                Some(playlist) => {
+                    let (extension, content) = match rest {
+                        ["txt"] => ("txt", playlist_file::to_text(&playlist.entries)),
+                        ["pls"] => ("pls", playlist_file::to_pls(&playlist.entries)),
+                        _ => ("m3u", playlist_file::to_m3u(&playlist.entries)),
+                    };
+                    let filename = format!("{}.{}", playlist.name, extension);
+                    state
+                        .messenger
+                        .send_file(msg.channel_id, &filename, content.as_bytes())
+                        .await?;
+                    return Ok(());
+                }
+                None => format!("There is no playlist **{}**", label),
+            }
+        }
+        ("import", rest) => {
+            let link = match (msg.attachments.first(), rest) {
+                (Some(attachment), []) => Some(attachment.url.clone()),
+                (None, [url]) if url_utils::is_url(url) => Some(url.to_string()),
+                _ => None,
+            };
+            match link {
+                Some(link) => {
+                    let entries = playlist_file::fetch(state.fetcher.as_ref(), &link).await?;
+                    if entries.is_empty() {
+                        "There are no links in that playlist".to_string()
+                    } else {
+                        let count = entries.len();
+                        save_playlist(&state, guild_id, user_id, &name, |playlist| {
+                            playlist.entries = entries;
+                        })
+                        .await?;
+                        format!("Imported {} tracks into **{}**", count, label)
+                    }
+                }
+                None => "Attach a .txt, .m3u or .pls playlist, or give a link to one".to_string(),
+            }
+        }
+        _ => usage.to_string(),
+    };
+    state
+        .messenger
+        .send_message(msg.channel_id, &content)
+        .await?;
+
+    Ok(())
+}
+
+/// The author's playlists and the guild's.
+async fn list_playlists(
+    state: &State,
+    msg: &Message,
+    guild_id: Id<GuildMarker>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let lines: Vec<String> = state
+        .guild_store
+        .get(guild_id)
+        .await?
+        .playlists
+        .iter()
+        .filter(|playlist| playlist.owner.is_none() || playlist.owner == Some(msg.author.id))
+        .map(|playlist| format!("{} - {} tracks", playlist.label(), playlist.entries.len()))
+        .collect();
+    if lines.is_empty() {
+        state
+            .messenger
+            .send_message(
+                msg.channel_id,
+                "No playlists yet, save the queue with !playlist save *name*",
+            )
+            .await?;
+        return Ok(());
+    }
+    send_lines(
+        state.messenger.as_ref(),
+        msg.channel_id,
+        "Playlists:",
+        &lines,
+    )
+    .await
+}
+
+/// Changes the playlist `name` stands for, creating it first when needed.
+async fn save_playlist<T>(
+    state: &State,
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+    name: &PlaylistName,
+    change: impl FnOnce(&mut Playlist) -> T,
+) -> Result<T, Box<dyn Error + Send + Sync + 'static>> {
+    state
+        .guild_store
+        .update(guild_id, |data| {
+            if name.find_mut(&mut data.playlists, user_id).is_none() {
+                data.playlists.push(Playlist {
+                    name: name.name.clone(),
+                    owner: name.owner(user_id),
+                    entries: Vec::new(),
+                });
+            }
+            match name.find_mut(&mut data.playlists, user_id) {
+                Some(playlist) => Ok(change(playlist)),
+                None => Err("Playlist went missing"),
+            }
+        })
+        .await?
+        .map_err(Into::into)
+}
+
+/// The tracks of the playlist `name` as `requester` would mean it, empty when there is none.
+async fn playlist_tracks(
+    state: &State,
+    guild_id: Id<GuildMarker>,
+    name: &str,
+    requester_id: Id<UserMarker>,
+    requester: &str,
+) -> Result<Vec<Track>, Box<dyn Error + Send + Sync + 'static>> {
+    let saved = state.guild_store.get(guild_id).await?.playlists;
+    let playlist =
+        match PlaylistName::parse(name).and_then(|name| name.find(&saved, requester_id).cloned()) {
+            Some(playlist) => playlist,
+            None => return Ok(Vec::new()),
+        };
+    Ok(playlist
+        .entries
+        .iter()
+        .map(|entry| entry.to_track(&playlist.label(), requester_id, requester))
+        .collect())
+}
+
+/// Whether the author of `msg` has Manage Server in the guild, as far as the cache knows.
+fn can_manage_guild(state: &State, msg: &Message) -> bool {
+    match state
+        .cache
+        .permissions()
+        .in_channel(msg.author.id, msg.channel_id)
+    {
+        Ok(permissions) => permissions.contains(Permissions::MANAGE_GUILD),
+        Err(why) => {
+            println!("Couldn't work out permissions: {}", why);
+            false
+        }
+    }
+}
+
+/// Searches radio-browser for stations, then plays or saves the one picked by number.
+async fn radiosearch(
+    msg: Message,
+    state: State,
+    state_info: Arc<Mutex<StateInfo>>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+    let query = match RadioQuery::parse(msg_utils::get_args(&msg.content)) {
+        Some(query) => query,
+        None => {
+            state
+                .messenger
+                .send_message(
+                    msg.channel_id,
+                    "Use !radiosearch *name*, tag:*genre* or country:*country*",
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let found = match state.radio_directory.search(&query).await {
+        Ok(found) => found,
+        Err(why) => {
+            println!("Radio search failed: {}", why);
+            state
+                .messenger
+                .send_message(
+                    msg.channel_id,
+                    "The radio directory can't be reached right now",
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+    if found.is_empty() {
+        state
+            .messenger
+            .send_message(msg.channel_id, "No stations found")
+            .await?;
+        return Ok(());
+    }
+
+    let lines: Vec<String> = found
+        .iter()
+        .map(|station| format!("**{}** - {}", station.name.trim(), station.summary()))
+        .collect();
+    send_lines(
+        state.messenger.as_ref(),
+        msg.channel_id,
+        "Reply with a number to play it, `save <number>` to add it to this server's stations, or `cancel`",
+        &lines,
+    )
+    .await?;
+
+    let author_id = msg.author.id;
+    let reply = tokio::time::timeout(
+        Duration::from_secs(60),
+        state
+            .standby
+            .wait_for_message(msg.channel_id, move |event: &MessageCreate| {
+                event.author.id == author_id
+            }),
+    )
+    .await;
+    let reply = match reply {
+        Ok(Ok(reply)) => reply.0,
+        _ => {
+            state
+                .messenger
+                .send_message(msg.channel_id, "No station was picked within a minute")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let answer = reply.content.trim().to_lowercase();
+    if answer == "cancel" {
+        state
+            .messenger
+            .send_message(msg.channel_id, "Search cancelled")
+            .await?;
+        return Ok(());
+    }
+    let (save, pick) = match answer.strip_prefix("save") {
+        Some(pick) => (true, pick.trim()),
+        None => (false, answer.as_str()),
+    };
+    let picked = match pick
+        .parse::<usize>()
+        .ok()
+        .and_then(|index| found.get(index))
+    {
+        Some(picked) => picked,
+        None => {
+            let content = format!(
+                "That isn't a listed station, search again and pick 0 to {}",
+                found.len() - 1
+            );
+            state
+                .messenger
+                .send_message(msg.channel_id, &content)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    if save {
+        if !can_manage_guild(&state, &msg) {
+            state
+                .messenger
+                .send_message(
+                    msg.channel_id,
+                    "Only members who can manage the server can change its stations",
+                )
+                .await?;
+            return Ok(());
+        }
+        let builtin = stations::builtin_stations();
+        let station = state
+            .guild_store
+            .update(guild_id, |data| {
+                let taken: Vec<Station> = builtin.iter().chain(&data.stations).cloned().collect();
+                let station = picked.to_station(&taken);
+                // Saving the same stream twice keeps the first command.
+                match data
+                    .stations
+                    .iter()
+                    .find(|saved| saved.urls == station.urls)
+                {
+                    Some(saved) => saved.clone(),
+                    None => {
+                        data.stations.push(station.clone());
+                        station
+                    }
+                }
+            })
+            .await?;
+        let content = format!(
+            "**{}** is saved, play it with `!{}`",
+            station.name, station.command
+        );
+        state
+            .messenger
+            .send_message(msg.channel_id, &content)
+            .await?;
+    } else {
+        if !state_info.lock().await.is_joined {
+            join(msg.clone(), state.clone(), state_info.clone()).await?;
+            // `join` has already said why it couldn't.
+            if !state_info.lock().await.is_joined {
+                return Ok(());
+            }
+        }
+        start_station(
+            &state,
+            &state_info,
+            guild_id,
+            msg.channel_id,
+            Track::new(MediaItemType::Radio, "", &reply.author),
+            picked.to_station(&[]),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Plays the first of `urls`, leaving the rest as fallbacks for when the stream dies.
+/// `attempt` counts the reconnects that led here.
+#[allow(clippy::too_many_arguments)]
+async fn play_station(
+    state: &State,
+    state_info: &Arc<Mutex<StateInfo>>,
+    guild_id: Id<GuildMarker>,
+    channel_id: Id<ChannelMarker>,
+    station: Station,
+    urls: Vec<String>,
+    track: Track,
+    attempt: u32,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let url = urls.first().ok_or("Station has no streams")?.clone();
+    let track = Track {
+        source_url: url.clone(),
+        ..track
+    };
+
+    state_info.lock().await.set_now_playing(Some(track.clone()));
+    let guild = state.guild_store.get(guild_id).await?;
+    let effects = guild.effects(None);
+    replace_playing(state, state_info, guild_id, guild.fade.fade_out).await;
+    // ffmpeg gives up on a stream that sends nothing for this long, which ends the track.
+    let stall_timeout = stations::stall_timeout().as_micros().to_string();
+    let mut input_args = station.input_args.clone();
+    input_args.extend(["-rw_timeout".to_string(), stall_timeout]);
+    let ch = spawn_ffmpeg(&url, &input_args, effects.chain().as_deref())?;
+    state_info.lock().await.set_ffmpeg_id(ch.id());
+    state_info
+        .lock()
+        .await
+        .set_timeline(effects.timeline(Duration::ZERO));
+    let input: Input = ChildContainer::from(ch).into();
+
+    let played = state
+        .voice
+        .play(guild_id, AudioTrack::new(input).volume(guild.volume.gain()))
+        .await;
+    if let Some(handle) = played {
+        state_info.lock().await.set_is_playing(true);
+
+        let name = station.name.clone();
+        let failover = Arc::new(StreamFailover {
+            state: Arc::clone(state),
+            state_info: Arc::clone(state_info),
+            guild_id,
+            channel_id,
+            station,
+            urls,
+            track,
+            attempt,
+            started: Instant::now(),
+            handled: Arc::new(AtomicBool::new(false)),
+        });
+        handle.add_event(songbird::TrackEvent::End, failover.clone())?;
+        handle.add_event(songbird::TrackEvent::Error, failover)?;
+
+        state.presence.set_listening(&name).await?;
+
+        let mut store = state.trackdata.write().await;
+        store.insert(guild_id, handle);
+    }
+    watch_stream_titles(state, state_info, guild_id, channel_id).await;
+
+    Ok(())
+}
+
+/// Reconnects a station, or moves it on to its next stream, when the playing one ends
+/// by itself.
+#[derive(Clone)]
+struct StreamFailover {
+    state: State,
+    state_info: Arc<Mutex<StateInfo>>,
+    guild_id: Id<GuildMarker>,
+    channel_id: Id<ChannelMarker>,
+    station: Station,
+    /// The playing stream first, then its fallbacks.
+    urls: Vec<String>,
+    track: Track,
+    attempt: u32,
+    started: Instant,
+    /// End and Error can both fire for the same stream.
+    handled: Arc<AtomicBool>,
+}
+
+#[async_trait]
+impl TrackEventHandler for StreamFailover {
+    async fn act(&self, _track_id: u128) {
+        if self.handled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        spawn(failover(self.clone()));
+    }
+}
+
+async fn failover(stream: StreamFailover) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    {
+        // Stopping or switching replaces the track first, so only a dead stream gets here.
+        let mut info = stream.state_info.lock().await;
+        if info.now_playing.as_ref() != Some(&stream.track) {
+            return Ok(());
+        }
+        info.set_is_playing(false);
+    }
+
+    let mut urls = stream.urls;
+    let played_for = stream.started.elapsed();
+    let attempt = if played_for >= stations::HEALTHY_PLAY {
+        // It played fine for a while and dropped, so try the same stream again.
+        1
+    } else {
+        // It never got going: note it as down and move on to the next stream.
+        stream
+            .state
+            .station_health
+            .write()
+            .await
+            .insert(stream.track.source_url.clone(), false);
+        urls.rotate_left(1);
+        stream.attempt + 1
+    };
+
+    let max_attempts = stations::reconnect_attempts();
+    if attempt > max_attempts {
+        // Giving up: let go of the dead stream the same way an emptied queue does.
+        let ffmpeg_ids = {
+            let mut info = stream.state_info.lock().await;
+            if info.now_playing.as_ref() != Some(&stream.track) {
+                return Ok(());
+            }
+            info.set_now_playing(None);
+            info.set_is_playing(false);
+            std::mem::take(&mut info.ffmpeg_id)
+        };
+        stream
+            .state
+            .trackdata
+            .write()
+            .await
+            .remove(&stream.guild_id);
+        for id in ffmpeg_ids {
+            kill_process(id);
+        }
+        stream.state.presence.set_listening("Nothing").await?;
+        let content = format!(
+            "**{}** couldn't be reconnected after {} attempts. Check !radiolist for stations that are up.",
+            stream.station.name, max_attempts
+        );
+        stream
+            .state
+            .messenger
+            .send_message(stream.channel_id, &content)
+            .await?;
+        return Ok(());
+    }
+
+    if attempt == 1 {
+        let content = format!(
+            "**{}** stream dropped, reconnecting...",
+            stream.station.name
+        );
+        stream
+            .state
+            .messenger
+            .send_message(stream.channel_id, &content)
+            .await?;
+    }
+
+    tokio::time::sleep(stations::reconnect_delay(attempt)).await;
+    if stream.state_info.lock().await.now_playing.as_ref() != Some(&stream.track) {
+        return Ok(());
+    }
+    println!(
+        "Reconnecting {} to {} (attempt {}/{})",
+        stream.station.name, urls[0], attempt, max_attempts
+    );
+
+    play_station(
+        &stream.state,
+        &stream.state_info,
+        stream.guild_id,
+        stream.channel_id,
+        stream.station,
+        urls,
+        stream.track,
+        attempt,
+    )
+    .await
+}
+
+/// Probes the built-in stations at startup and then every half hour.
+async fn check_station_health(state: State) {
+    loop {
+        let health =
+            stations::check_stations(state.fetcher.as_ref(), &stations::builtin_stations()).await;
+        state.station_health.write().await.extend(health);
+        tokio::time::sleep(Duration::from_secs(30 * 60)).await;
+    }
+}
+
+/// Follows the song titles announced by the live stream that is now playing, showing
+/// them in the presence and, with `!announce on`, in the channel it was started from.
+async fn watch_stream_titles(
+    state: &State,
+    state_info: &Arc<Mutex<StateInfo>>,
+    guild_id: Id<GuildMarker>,
+    channel_id: Id<ChannelMarker>,
+) {
+    let track = match &state_info.lock().await.now_playing {
+        Some(track) if track.is_live() => track.clone(),
+        _ => return,
+    };
+    let station = track.display_title();
+
+    let (tx, mut rx) = mpsc::channel(4);
+    let fetcher = Arc::clone(&state.fetcher);
+    let url = track.source_url.clone();
+    let watcher = tokio::spawn(async move {
+        if let Err(why) = radio_meta::watch_titles(fetcher.as_ref(), &url, tx).await {
+            println!("No stream titles for {}: {}", url, why);
+        }
+    });
+    state_info.lock().await.set_title_watcher(watcher);
+
+    let state = Arc::clone(state);
+    let state_info = Arc::clone(state_info);
+    spawn(async move {
+        while let Some(title) = rx.recv().await {
+            let presence = {
+                let mut info = state_info.lock().await;
+                if info.now_playing.as_ref() != Some(&track) {
+                    break;
+                }
+                info.set_stream_title(Some(title.clone()));
+                info.presence()
+            };
+
+            state.presence.set_listening(&presence).await?;
+            if state.guild_store.get(guild_id).await?.announce_titles {
+                let content = format!("Now playing on {}: **{}**", station, title);
+                state.messenger.send_message(channel_id, &content).await?;
+            }
+        }
+        Ok(())
+    });
+}
 
+async fn announce(
+    msg: Message,
+    state: State,
+    state_info: Arc<Mutex<StateInfo>>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+    let content = match msg_utils::get_args(&msg.content) {
+        "on" => {
+            state
+                .guild_store
+                .update(guild_id, |data| data.announce_titles = true)
+                .await?;
+            "Song changes on radio streams will be announced here".to_string()
+        }
+        "off" => {
+            state
+                .guild_store
+                .update(guild_id, |data| data.announce_titles = false)
+                .await?;
+            "Song changes won't be announced anymore".to_string()
+        }
+        _ => match &state_info.lock().await.stream_title {
+            Some(title) => format!("Now playing: **{}**", title),
+            None => "Use !announce on/off".to_string(),
+        },
+    };
     state
-        .http
-        .create_message(msg.channel_id)
-        .content("Stopped the track")?
+        .messenger
+        .send_message(msg.channel_id, &content)
         .await?;
 
     Ok(())
 }
 
-async fn volume(
+/// Decodes `url` to 48kHz stereo PCM on stdout.
+fn spawn_ffmpeg(
+    url: &str,
+    input_args: &[String],
+    filter_chain: Option<&str>,
+) -> std::io::Result<process::Child> {
+    let mut command = Command::new("ffmpeg");
+    command.args(input_args).arg("-i").arg(url);
+    if let Some(filter_chain) = filter_chain {
+        command.arg("-af").arg(filter_chain);
+    }
+    command
+        .arg("-f")
+        .arg("wav")
+        .arg("-ac")
+        .arg("2")
+        .arg("-acodec")
+        .arg("pcm_s16le")
+        .arg("-ar")
+        .arg("48000")
+        .arg("-")
+        .stdout(Stdio::piped())
+        .spawn()
+}
+
+/// `-ss` to start `start` into the input.
+fn seek_args(start: Duration) -> Vec<String> {
+    if start.is_zero() {
+        Vec::new()
+    } else {
+        vec!["-ss".to_string(), format!("{:.3}", start.as_secs_f64())]
+    }
+}
+
+async fn resolve_link(
+    fetcher: &dyn Fetcher,
+    text: &str,
+) -> Result<Option<String>, Box<dyn Error + Send + Sync + 'static>> {
+    let re = Regex::new(r"^(http(s)://)?((w){3}.)?youtu(be|.be)?(.com)?/.+").unwrap();
+    if re.is_match(text) || url_utils::is_url(text) {
+        Ok(Some(text.to_string()))
+    } else if text.is_empty() {
+        Ok(Some(String::from(
+            "http://astreaming.virginradio.ro:8000/virgin_aacp_64k",
+        )))
+    } else {
+        yt_utils::search(fetcher, text).await
+    }
+}
+
+/// `!pause`: pauses what's playing, and does nothing when it already is. The bot leaves
+/// once it has been paused for `pause_timeout`.
+async fn pause(
     msg: Message,
     state: State,
-    _state_info: Arc<Mutex<StateInfo>>,
+    state_info: Arc<Mutex<StateInfo>>,
+    queue: Arc<Mutex<Queue1>>,
 ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-    let guild_id = msg.guild_id.unwrap();
-    let mut content = msg.content.clone();
-    content = content[7..].to_string().to_string();
-    content.retain(|f| !f.is_whitespace());
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+    let handle = state.trackdata.read().await.get(&guild_id).cloned();
+    let playing = match &handle {
+        Some(handle) => handle.get_info().await.ok().map(|info| info.playing),
+        None => None,
+    };
 
-    if content.to_string().is_empty() {
-        state
-            .http
-            .create_message(msg.channel_id)
-            .content("Use !volume <value>")?
-            .await?;
-    } else {
-        let volume = content.parse::<f32>()?;
+    let content = match (handle, playing) {
+        (Some(handle), Some(PlayMode::Play)) => {
+            handle.pause()?;
+            let paused_at = Instant::now();
+            let presence = {
+                let mut info = state_info.lock().await;
+                info.set_paused_at(Some(paused_at));
+                info.presence()
+            };
+            state.presence.set_listening(&presence).await?;
+            spawn(leave_after_pause(
+                Arc::clone(&state),
+                Arc::clone(&state_info),
+                Arc::clone(&queue),
+                guild_id,
+                msg.channel_id,
+                paused_at,
+            ));
+            "Paused the track, !resume to carry on"
+        }
+        (Some(_), Some(PlayMode::Pause)) => "Already paused",
+        _ => "No track to pause!",
+    };
+    state
+        .messenger
+        .send_message(msg.channel_id, content)
+        .await?;
 
-        if !volume.is_finite() || volume > 10.0 || volume < 0.0 {
-            state
-                .http
-                .create_message(msg.channel_id)
-                .content("Invalid volume!")?
-                .await?;
+    Ok(())
+}
+
+/// `!resume`: carries on with a paused track, and does nothing when it's playing.
+async fn resume(
+    msg: Message,
+    state: State,
+    state_info: Arc<Mutex<StateInfo>>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+    let handle = state.trackdata.read().await.get(&guild_id).cloned();
+    let playing = match &handle {
+        Some(handle) => handle.get_info().await.ok().map(|info| info.playing),
+        None => None,
+    };
+
+    let content = match (handle, playing) {
+        (Some(handle), Some(PlayMode::Pause)) => {
+            handle.play()?;
+            let presence = {
+                let mut info = state_info.lock().await;
+                info.set_paused_at(None);
+                info.presence()
+            };
+            state.presence.set_listening(&presence).await?;
+            "Resumed the track"
+        }
+        (Some(_), Some(PlayMode::Play)) => "Already playing",
+        _ => "No track to resume!",
+    };
+    state
+        .messenger
+        .send_message(msg.channel_id, content)
+        .await?;
 
+    Ok(())
+}
+
+/// How long a track stays paused before the bot leaves: `AURRAS_PAUSE_TIMEOUT_MINS`
+/// or 30 minutes.
+fn pause_timeout() -> Duration {
+    env::var("AURRAS_PAUSE_TIMEOUT_MINS")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .filter(|mins| *mins > 0)
+        .map(|mins: u64| Duration::from_secs(mins * 60))
+        .unwrap_or(Duration::from_secs(30 * 60))
+}
+
+/// Leaves the channel when the pause from `paused_at` is still going after
+/// `pause_timeout`. Resuming, pausing again or anything new playing resets it.
+async fn leave_after_pause(
+    state: State,
+    state_info: Arc<Mutex<StateInfo>>,
+    queue: Arc<Mutex<Queue1>>,
+    guild_id: Id<GuildMarker>,
+    channel_id: Id<ChannelMarker>,
+    paused_at: Instant,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let timeout = pause_timeout();
+    tokio::time::sleep(timeout).await;
+    {
+        let info = state_info.lock().await;
+        if info.paused_at != Some(paused_at) || !info.is_joined {
             return Ok(());
         }
+    }
+    disconnect(&state, &state_info, &queue, guild_id).await?;
+    let content = format!(
+        "Left after being paused for {} minutes",
+        timeout.as_secs() / 60
+    );
+    state.messenger.send_message(channel_id, &content).await?;
 
-        let store = state.trackdata.read().await;
+    Ok(())
+}
 
-        let content = if let Some(handle) = store.get(&guild_id) {
-            let _success = handle.set_volume(volume as f32);
-            format!("Set the volume to {}", volume)
-        } else {
-            format!("No track to change volume!")
-        };
+/// `!stop`: ends playback, fading out, and clears the queue. The bot stays in the channel
+/// until `!leave`.
+async fn stop(
+    msg: Message,
+    state: State,
+    state_info: Arc<Mutex<StateInfo>>,
+    queue: Arc<Mutex<Queue1>>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+
+    let fade_out = state.guild_store.get(guild_id).await?.fade.fade_out;
+    stop_playback(&state, &state_info, &queue, guild_id, fade_out).await;
+    state.presence.set_listening("Nothing").await?;
+
+    state
+        .messenger
+        .send_message(msg.channel_id, "Stopped and cleared the queue")
+        .await?;
+
+    Ok(())
+}
+
+/// `!volume [0-100]` shows or sets the guild's volume in percent, which the playing track
+/// changes to right away and every later one starts at.
+async fn volume(
+    msg: Message,
+    state: State,
+    _state_info: Arc<Mutex<StateInfo>>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+    let content = msg_utils::get_args(&msg.content);
+
+    let content = if content.is_empty() {
+        let volume = state.guild_store.get(guild_id).await?.volume;
+        format!("Volume: **{}**", volume)
+    } else {
+        let volume = match msg_utils::parse_volume(content) {
+            Ok(volume) => volume,
+            Err(_) => {
+                state
+                    .messenger
+                    .send_message(msg.channel_id, "Use !volume [0-100]")
+                    .await?;
 
+                return Ok(());
+            }
+        };
         state
-            .http
-            .create_message(msg.channel_id)
-            .content(&content)?
+            .guild_store
+            .update(guild_id, |data| data.volume = volume)
             .await?;
-    }
+
+        if let Some(handle) = state.trackdata.read().await.get(&guild_id) {
+            let _success = handle.set_volume(volume.gain());
+        }
+        format!("Set the volume to **{}**", volume)
+    };
+    state
+        .messenger
+        .send_message(msg.channel_id, &content)
+        .await?;
 
     Ok(())
 }
@@ -753,28 +3229,13 @@ async fn help(
         }
     }
     if state_info.lock().await.is_joined {
-        let path = fs::canonicalize("./help.txt");
-        let file = File::open(path.unwrap()).expect("err");
-        let reader = BufReader::new(file);
-
-        let mut embed_builder = EmbedBuilder::new();
-        embed_builder = embed_builder.description("Commands:");
-
-        for (index, line) in reader.lines().enumerate() {
-            let data = line.unwrap();
-
-            let f1 = EmbedFieldBuilder::new(String::from(index.to_string()), data)
-                .inline()
-                .build();
-            embed_builder = embed_builder.field(f1);
-        }
-
-        let embed = embed_builder.validate()?.build();
-        state
-            .http
-            .create_message(msg.channel_id)
-            .embeds(&[embed])?
-            .await?;
+        send_text_file(
+            state.messenger.as_ref(),
+            msg.channel_id,
+            "Commands:",
+            "./help.txt",
+        )
+        .await?;
     }
 
     Ok(())
@@ -796,29 +3257,65 @@ async fn radiolist(
         }
     }
     if state_info.lock().await.is_joined {
-        let path = fs::canonicalize("./radiolist.txt");
-        let file = File::open(path.unwrap()).expect("err");
-        let reader = BufReader::new(file);
+        let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+        let stations = guild_stations(&state, guild_id).await?;
+        let health = state.station_health.read().await;
+        let lines: Vec<String> = stations
+            .iter()
+            .map(|station| station.radiolist_line(&health))
+            .collect();
+        drop(health);
+
+        send_lines(
+            state.messenger.as_ref(),
+            msg.channel_id,
+            "Radio List:",
+            &lines,
+        )
+        .await?;
+    }
 
-        let mut embed_builder = EmbedBuilder::new();
-        embed_builder = embed_builder.description("Radio List:");
+    Ok(())
+}
 
-        for (index, line) in reader.lines().enumerate() {
-            let data = line.unwrap();
+async fn send_text_file(
+    messenger: &dyn Messenger,
+    channel_id: Id<ChannelMarker>,
+    description: &str,
+    path: &str,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let file = File::open(fs::canonicalize(path)?)?;
+    let reader = BufReader::new(file);
+    let lines = reader.lines().collect::<Result<Vec<String>, _>>()?;
 
-            let f1 = EmbedFieldBuilder::new(String::from(index.to_string()), data)
-                .inline()
-                .build();
-            embed_builder = embed_builder.field(f1);
-        }
+    send_lines(messenger, channel_id, description, &lines).await
+}
 
-        let embed = embed_builder.validate()?.build();
-        state
-            .http
-            .create_message(msg.channel_id)
-            .embeds(&[embed])?
-            .await?;
+/// One inline field per line, split over as many embeds as needed.
+async fn send_lines(
+    messenger: &dyn Messenger,
+    channel_id: Id<ChannelMarker>,
+    description: &str,
+    lines: &[String],
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let mut embeds = Vec::new();
+    let mut embed_builder = EmbedBuilder::new();
+    embed_builder = embed_builder.description(description);
+
+    for (index, data) in lines.iter().enumerate() {
+        // Discord allows at most 25 fields per embed.
+        if index > 0 && index % 25 == 0 {
+            embeds.push(embed_builder.validate()?.build());
+            embed_builder = EmbedBuilder::new();
+        }
+        let f1 = EmbedFieldBuilder::new(String::from(index.to_string()), data)
+            .inline()
+            .build();
+        embed_builder = embed_builder.field(f1);
     }
+    embeds.push(embed_builder.validate()?.build());
+
+    messenger.send_embeds(channel_id, &embeds).await?;
 
     Ok(())
 }
@@ -839,21 +3336,23 @@ async fn time(
         }
     }
     if state_info.lock().await.is_joined {
-        let guild_id = msg.guild_id.unwrap();
+        let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
 
         let store = state.trackdata.read().await;
 
         let content = if let Some(handle) = store.get(&guild_id) {
             let info: TrackState = handle.get_info().await?;
 
-            let time_elapsed = info.position;
+            let time_elapsed = state_info.lock().await.timeline.position(info.position);
             let time_elapsed_hours = (time_elapsed.as_secs() / 60) / 60;
             let time_elapsed_minutes = (time_elapsed.as_secs() / 60) % 60;
             let time_elapsed_seconds = time_elapsed.as_secs() % 60;
             let total_time = state_info
                 .lock()
                 .await
-                .current_song_length
+                .now_playing
+                .as_ref()
+                .and_then(|track| track.duration)
                 .unwrap_or(Duration::default());
             let total_time_hours = (total_time.as_secs() / 60) / 60;
             let total_time_minutes = (total_time.as_secs() / 60) % 60;
@@ -869,375 +3368,417 @@ async fn time(
         part1.push_str(&content);
         part1.push_str("`");
 
-        state
-            .http
-            .create_message(msg.channel_id)
-            .content(&part1)?
-            .await?;
+        state.messenger.send_message(msg.channel_id, &part1).await?;
     }
 
     Ok(())
 }
 
-async fn list(
-    msg: Message,
-    state: State,
-    state_info: Arc<Mutex<StateInfo>>,
-    queue: Arc<Mutex<Queue1>>,
-) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-    if !state_info.lock().await.is_joined {
-        let res = join(msg.clone(), state.clone(), state_info.clone())
-            .await
-            .ok();
-
-        match res {
-            Some(result) => println!("{:?}", result),
-            None => println!("ERR"),
-        }
+/// Adds `track` to the guild's history as it starts playing.
+async fn record_history(state: &State, guild_id: Id<GuildMarker>, track: &Track) {
+    let recorded = state
+        .guild_store
+        .update(guild_id, |data| {
+            history::record(&mut data.history, track.clone(), SystemTime::now())
+        })
+        .await;
+    if let Err(why) = recorded {
+        println!("Couldn't save the history of {}: {}", guild_id, why);
     }
-    let mut counter: i16 = 0;
-    if state_info.lock().await.is_joined {
-        let list = &queue.lock().await.queue;
-
-        if list.is_empty() {
-            state
-                .http
-                .create_message(msg.channel_id)
-                .content(&"No songs in queue!")?
-                .await?;
-        } else {
-            for item in list {
-                counter = counter + 1;
-                let dat = item.clone().aux_metadata().await;
-                let title = dat
-                    .unwrap_or_default()
-                    .title
-                    .unwrap_or("UNKNOWN".to_string());
-
-                let mut content = String::from("*");
-                content.push_str(&counter.to_string());
-                content.push_str("* - ");
-                content.push_str(&title);
+}
 
-                state
-                    .http
-                    .create_message(msg.channel_id)
-                    .content(&content)?
-                    .await?;
-            }
-        }
+async fn history(msg: Message, state: State) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+    let now = SystemTime::now();
+    let lines: Vec<String> = state
+        .guild_store
+        .get(guild_id)
+        .await?
+        .history
+        .iter()
+        .rev()
+        .take(25)
+        .map(|entry| entry.line(now))
+        .collect();
+    if lines.is_empty() {
+        state
+            .messenger
+            .send_message(msg.channel_id, "Nothing has been played yet")
+            .await?;
+        return Ok(());
     }
-
-    Ok(())
+    send_lines(
+        state.messenger.as_ref(),
+        msg.channel_id,
+        "Recently played, newest first:",
+        &lines,
+    )
+    .await
 }
 
-async fn add(
+/// `!autoplay [on|off]`: keeps playing related YouTube videos once the queue runs out.
+async fn autoplay(
     msg: Message,
     state: State,
     state_info: Arc<Mutex<StateInfo>>,
     queue: Arc<Mutex<Queue1>>,
 ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-    if !state_info.lock().await.is_joined {
-        let res = join(msg.clone(), state.clone(), state_info.clone())
-            .await
-            .ok();
-
-        match res {
-            Some(result) => println!("{:?}", result),
-            None => println!("ERR"),
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+    let enabled = match msg_utils::get_args(&msg.content) {
+        "on" => Some(true),
+        "off" => Some(false),
+        "" => None,
+        _ => {
+            state
+                .messenger
+                .send_message(msg.channel_id, "Use !autoplay [on/off]")
+                .await?;
+            return Ok(());
         }
-    }
-    if state_info.lock().await.is_joined {
-        let guild_id = msg.guild_id.unwrap();
+    };
+    let enabled = state
+        .guild_store
+        .update(guild_id, |data| {
+            data.autoplay = enabled.unwrap_or(!data.autoplay);
+            data.autoplay
+        })
+        .await?;
 
-        let b = msg.content.clone();
-        let index1 = b.find(" ");
-        let mut text: String;
-        if !index1.is_none() {
-            text = b.chars().skip(index1.unwrap()).collect();
-        } else {
-            text = "".to_string();
+    if enabled {
+        let playing = state_info.lock().await.now_playing.clone();
+        if let Some(playing) = playing {
+            spawn(queue_autoplay(
+                Arc::clone(&state),
+                Arc::clone(&queue),
+                guild_id,
+                playing,
+            ));
         }
-        text = text.trim().replace(" ", "+");
-
-        if let Some(_call_lock) = state.songbird.get(guild_id.into_nonzero()) {
-            // let mut call = call_lock.lock().await;
-            // let queue = queue.queues.entry(songbird::id::GuildId(guild_id.get()))
-
-            // let queue_q = queue.lock().unwrap().queues.entry(songbird::id::GuildId(guild_id.get())).or_default();
-
-            println!("{:?}", text);
-            /*let source = ytdl(text)
-            .await
-              .expect("This might fail: handle this error!");*/
+    } else {
+        queue.lock().await.drop_autoplay();
+    }
+    let content = if enabled {
+        "Autoplay is on, related videos play once the queue runs out"
+    } else {
+        "Autoplay is off"
+    };
+    state
+        .messenger
+        .send_message(msg.channel_id, content)
+        .await?;
 
-            let mut source = YoutubeDl::new(reqwest::Client::new(), text);
+    Ok(())
+}
 
-            //   let title =  source.metadata.title.as_ref().unwrap().clone();
-            let mut title = "".to_string();
-            if let Ok(metadata) = source.aux_metadata().await {
+/// `!fade [seconds]` and `!crossfade [seconds]`: shows or sets the guild's fade-out and
+/// crossfade, where 0 turns them off.
+async fn fade(msg: Message, state: State) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+    let crossfade = msg.content.starts_with("!crossfade");
+    let args = msg_utils::get_args(&msg.content);
+    let duration = if args.is_empty() {
+        None
+    } else {
+        match fade::parse_seconds(args) {
+            Some(duration) => Some(duration),
+            None => {
                 let content = format!(
-                    "**{:?}** added !",
-                    metadata.title.as_ref().unwrap_or(&"<UNKNOWN>".to_string()),
+                    "Use {} [seconds], from 0 (off) to {}",
+                    if crossfade { "!crossfade" } else { "!fade" },
+                    fade::MAX_FADE.as_secs()
                 );
-                title = content.clone();
+                state
+                    .messenger
+                    .send_message(msg.channel_id, &content)
+                    .await?;
+                return Ok(());
             }
-
-            // Queueing a track is this easy!
-            //let hnd = queue.add_source(source.into(), &mut call);
-            queue.lock().await.queue.push(source);
-
-            state
-                .http
-                .create_message(msg.channel_id)
-                .content(&title)?
-                .await?;
         }
-    }
+    };
+
+    let settings = state
+        .guild_store
+        .update(guild_id, |data| {
+            if let Some(duration) = duration {
+                if crossfade {
+                    data.fade.crossfade = duration;
+                } else {
+                    data.fade.fade_out = duration;
+                }
+            }
+            data.fade
+        })
+        .await?;
+    let content = format!(
+        "Fade-out: **{}**, crossfade: **{}** (not after live streams)",
+        fade::describe(settings.fade_out),
+        fade::describe(settings.crossfade)
+    );
+    state
+        .messenger
+        .send_message(msg.channel_id, &content)
+        .await?;
 
     Ok(())
 }
 
-async fn description(
-    msg: Message,
-    state: State,
-    state_info: Arc<Mutex<StateInfo>>,
-) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-    let song_link = state_info.lock().await.current_song_link.clone();
-
-    if !state_info.lock().await.is_joined {
-        let res = join(msg.clone(), state.clone(), state_info.clone())
-            .await
-            .ok();
-
-        match res {
-            Some(result) => println!("{:?}", result),
-            None => println!("ERR"),
-        }
-    }
-    if state_info.lock().await.is_joined {
-        let guild_id = msg.guild_id.unwrap();
-        let store = state.trackdata.read().await;
-        if let Some(handle) = store.get(&guild_id) {
-            let h = handle.get_info().await;
-            if h.is_ok() {
-                let content = reqwest::get(&song_link).await?.text().await?;
-
-                let yt_struct = &yt_utils::get_link_content(content.as_str(), song_link.clone());
-
-                state_info
-                    .lock()
-                    .await
-                    .set_current_song_desc(yt_struct.get_yt_desc());
-
-                let to_split = yt_struct.get_yt_desc();
-                let re = Regex::new(r"\\n").unwrap();
-                let result = re.replace_all(&to_split, "\n");
-                let re2 = Regex::new(r"(https://)|(http://)").unwrap();
-                let result2 = re2.replace_all(&result, "[http][//]");
-
-                let re3 = Regex::new(r"\n\n").unwrap();
-                let result3 = re3.replace_all(&result2, "\n");
-                let result_final: String = result3.chars().take(1999).collect();
-                state
-                    .http
-                    .create_message(msg.channel_id)
-                    .content(&result_final)?
-                    .await?;
-            } else {
+/// `!filter [preset]` switches an ffmpeg filter preset on or off for the guild and
+/// `!filter clear` removes them all. What's playing restarts where it was with the new
+/// filters, except live streams, which get them when they next start.
+async fn filter(
+    msg: Message,
+    state: State,
+    state_info: Arc<Mutex<StateInfo>>,
+    queue: Arc<Mutex<Queue1>>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+    let change = match msg_utils::get_args(&msg.content) {
+        "" => None,
+        "clear" | "off" => Some(None),
+        name => match Filter::parse(name) {
+            Some(filter) => Some(Some(filter)),
+            None => {
+                let presets: Vec<&str> = Filter::ALL.iter().map(|filter| filter.name()).collect();
+                let content = format!("Use !filter [{}] or !filter clear", presets.join("/"));
                 state
-                    .http
-                    .create_message(msg.channel_id)
-                    .content("`No song is currently playing!`")?
+                    .messenger
+                    .send_message(msg.channel_id, &content)
                     .await?;
+                return Ok(());
             }
+        },
+    };
+
+    let (before, active) = state
+        .guild_store
+        .update(guild_id, |data| {
+            let before = data.filters.clone();
+            match change {
+                Some(Some(filter)) => data.filters = filters::toggle(&data.filters, filter),
+                Some(None) => data.filters.clear(),
+                None => {}
+            }
+            (before, data.filters.clone())
+        })
+        .await?;
+    let mut content = format!("Filters: **{}**", filters::describe(&active));
+    if active != before {
+        if let Some(note) =
+            restart_playing(&state, &state_info, &queue, guild_id, msg.channel_id).await?
+        {
+            content.push_str(note);
         }
     }
+    state
+        .messenger
+        .send_message(msg.channel_id, &content)
+        .await?;
 
     Ok(())
 }
 
-async fn radiozu(
+/// `!normalize [on|off]` levels every track the guild plays to the same loudness, with
+/// YouTube's own measurement where there is one and ffmpeg's `loudnorm` otherwise.
+async fn normalize(
     msg: Message,
     state: State,
     state_info: Arc<Mutex<StateInfo>>,
+    queue: Arc<Mutex<Queue1>>,
 ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-    if !state_info.lock().await.is_joined {
-        let res = join(msg.clone(), state.clone(), state_info.clone())
-            .await
-            .ok();
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+    let enabled = match msg_utils::get_args(&msg.content) {
+        "on" => Some(true),
+        "off" => Some(false),
+        "" => None,
+        _ => {
+            state
+                .messenger
+                .send_message(msg.channel_id, "Use !normalize [on/off]")
+                .await?;
+            return Ok(());
+        }
+    };
+    let (before, enabled) = state
+        .guild_store
+        .update(guild_id, |data| {
+            let before = data.normalize;
+            data.normalize = enabled.unwrap_or(!data.normalize);
+            (before, data.normalize)
+        })
+        .await?;
 
-        match res {
-            Some(result) => println!("{:?}", result),
-            None => println!("ERR"),
+    let mut content = if enabled {
+        "Loudness normalization is on".to_string()
+    } else {
+        "Loudness normalization is off".to_string()
+    };
+    if enabled != before {
+        if let Some(note) =
+            restart_playing(&state, &state_info, &queue, guild_id, msg.channel_id).await?
+        {
+            content.push_str(note);
         }
     }
-    if state_info.lock().await.is_joined {
-        let a = Command::new("ffmpeg")
-            .arg("-i")
-            .arg("https://live4ro.antenaplay.ro/radiozu/radiozu-48000.m3u8")
-            .arg("-f")
-            .arg("wav")
-            .arg("-ac")
-            .arg("2")
-            .arg("-acodec")
-            .arg("pcm_s16le")
-            .arg("-ar")
-            .arg("48000")
-            .arg("-")
-            .stdout(Stdio::piped())
-            .spawn();
-        if !state_info.lock().await.ffmpeg_id.is_empty() {
-            let id = state_info.lock().await.ffmpeg_id.pop().unwrap();
-
-            let sp = Command::new("kill").arg(id.to_string()).output();
-            let _res = sp.unwrap();
-        }
-
-        let ch = a.unwrap();
-        let id = &ch.id();
-        state_info.lock().await.set_ffmpeg_id(*id);
-
-        let test = ChildContainer::from(ch).into();
-        let guild_id = msg.guild_id.unwrap();
-        let source = ImageSource::url(
-            "https://static.tuneyou.com/images/logos/500_500/33/3133/RadioZU.jpg",
-        )?;
-
-        let embed = EmbedBuilder::new()
-            .title("RadioZU Romania")
-            .field(EmbedFieldBuilder::new("Requestor", msg.author.name).inline())
-            .image(source)
-            .validate()?
-            .build();
+    state
+        .messenger
+        .send_message(msg.channel_id, &content)
+        .await?;
 
-        state
-            .http
-            .create_message(msg.channel_id)
-            .embeds(&[embed])?
-            .await?;
-        if let Some(call_lock) = state.songbird.get(guild_id) {
-            if state_info.lock().await.is_playing {
-                let mut call = call_lock.lock().await;
-                let _ = call.stop();
-                state_info.lock().await.set_is_playing(false);
-            }
+    Ok(())
+}
 
-            let mut call = call_lock.lock().await;
-            let handle = call.play_input(test);
-            state_info.lock().await.set_is_playing(true);
+/// Starts what's playing again where it was, after the guild's filters or leveling
+/// changed. Live streams and links keep playing as they are, which the returned note says.
+async fn restart_playing(
+    state: &State,
+    state_info: &Arc<Mutex<StateInfo>>,
+    queue: &Arc<Mutex<Queue1>>,
+    guild_id: Id<GuildMarker>,
+    channel_id: Id<ChannelMarker>,
+) -> Result<Option<&'static str>, Box<dyn Error + Send + Sync + 'static>> {
+    let playing = state_info.lock().await.now_playing.clone();
+    let handle = state.trackdata.read().await.get(&guild_id).cloned();
+    let played = match handle {
+        Some(handle) => handle.get_info().await.ok().map(|info| info.position),
+        None => None,
+    };
+    let (track, played) = match (playing, played) {
+        (Some(track), Some(played)) => (track, played),
+        _ => return Ok(None),
+    };
+    let start = state_info.lock().await.timeline.position(played);
+    match track.kind {
+        MediaItemType::Radio | MediaItemType::DirectUrl => {
+            return Ok(Some(", from the next stream or track"));
+        }
+        // Playlist links that aren't for yt-dlp played through `play_url` as well.
+        MediaItemType::PlaylistEntry { .. } if !plays_through_yt_dlp(&track) => {
+            return Ok(Some(", from the next stream or track"));
+        }
+        MediaItemType::LocalFile => {
+            let effects = state.guild_store.get(guild_id).await?.effects(None);
+            restart_with_filters(
+                state, state_info, queue, guild_id, channel_id, track, &effects, start,
+            )
+            .await?;
+        }
+        _ => {
+            play_youtube(
+                state,
+                state_info,
+                guild_id,
+                channel_id,
+                track,
+                queue,
+                PlayOptions {
+                    resume_at: Some(start),
+                    ..PlayOptions::default()
+                },
+            )
+            .await?;
+        }
+    }
 
-            let activity = Activity::from(MinimalActivity {
-                kind: ActivityType::Listening,
-                name: "RadioZU Romania".to_owned(),
-                url: None,
-            });
-            let request = UpdatePresence::new(Vec::from([activity]), false, None, Status::Online)?;
+    Ok(None)
+}
 
-            for shard in state.cluster.shards() {
-                shard.command(&request).await?;
-            }
+/// Plays the local file of `track` again from `start`, through ffmpeg with `effects`, with
+/// `queue` still to play after it.
+#[allow(clippy::too_many_arguments)]
+async fn restart_with_filters(
+    state: &State,
+    state_info: &Arc<Mutex<StateInfo>>,
+    queue: &Arc<Mutex<Queue1>>,
+    guild_id: Id<GuildMarker>,
+    channel_id: Id<ChannelMarker>,
+    track: Track,
+    effects: &Effects,
+    start: Duration,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    if !state.voice.is_connected(guild_id) {
+        return Ok(());
+    }
+    let volume = state.guild_store.get(guild_id).await?.volume.gain();
+    replace_playing(state, state_info, guild_id, Duration::ZERO).await;
+
+    let ch = spawn_ffmpeg(
+        &track.source_url,
+        &seek_args(start),
+        effects.chain().as_deref(),
+    )?;
+    {
+        let mut info = state_info.lock().await;
+        info.set_ffmpeg_id(ch.id());
+        info.set_timeline(effects.timeline(start));
+    }
+    let input: Input = ChildContainer::from(ch).into();
+    if let Some(handle) = state
+        .voice
+        .play(guild_id, AudioTrack::new(input).volume(volume))
+        .await
+    {
+        state_info.lock().await.set_is_playing(true);
+
+        let ending = TrackEnd {
+            state: Arc::clone(state),
+            state_info: Arc::clone(state_info),
+            queue: Arc::clone(queue),
+            guild_id,
+            channel_id,
+            track,
+        };
+        let _res = handle.add_event(songbird::TrackEvent::End, Arc::new(ending));
 
-            let mut store = state.trackdata.write().await;
-            store.insert(guild_id, handle);
-        }
+        let mut store = state.trackdata.write().await;
+        store.insert(guild_id, handle);
     }
 
     Ok(())
 }
-async fn radio24house(
+
+/// `!previous`/`!back`: puts the last played track at the front of the queue.
+async fn previous(
     msg: Message,
     state: State,
     state_info: Arc<Mutex<StateInfo>>,
+    queue: Arc<Mutex<Queue1>>,
 ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-    if !state_info.lock().await.is_joined {
-        let res = join(msg.clone(), state.clone(), state_info.clone())
-            .await
-            .ok();
-
-        match res {
-            Some(result) => println!("{:?}", result),
-            None => println!("ERR"),
-        }
-    }
-    if state_info.lock().await.is_joined {
-        let a = Command::new("ffmpeg")
-            .arg("-i")
-            .arg("https://24houseradio-adradio.radioca.st/128")
-            .arg("-f")
-            .arg("wav")
-            .arg("-ac")
-            .arg("2")
-            .arg("-acodec")
-            .arg("pcm_s16le")
-            .arg("-ar")
-            .arg("48000")
-            .arg("-")
-            .stdout(Stdio::piped())
-            .spawn();
-        if !state_info.lock().await.ffmpeg_id.is_empty() {
-            let id = state_info.lock().await.ffmpeg_id.pop().unwrap();
-
-            let sp = Command::new("kill").arg(id.to_string()).output();
-            let _res = sp.unwrap();
-        }
-
-        let ch = a.unwrap();
-        let id = &ch.id();
-        state_info.lock().await.set_ffmpeg_id(*id);
-
-        let test = ChildContainer::from(ch).into();
-        let guild_id = msg.guild_id.unwrap();
-        let source = ImageSource::url(
-            "https://cdn2.vectorstock.com/i/1000x1000/01/16/radio-music-neon-logo-night-neon-vector-21420116.jpg",
-        )?;
-
-        let embed = EmbedBuilder::new()
-            .title("Radio 24 House")
-            .field(EmbedFieldBuilder::new("Requestor", msg.author.name).inline())
-            .image(source)
-            .validate()?
-            .build();
-
-        state
-            .http
-            .create_message(msg.channel_id)
-            .embeds(&[embed])?
-            .await?;
-        if let Some(call_lock) = state.songbird.get(guild_id) {
-            if state_info.lock().await.is_playing {
-                let mut call = call_lock.lock().await;
-                let _ = call.stop();
-                state_info.lock().await.set_is_playing(false);
-            }
-
-            let mut call = call_lock.lock().await;
-            let handle = call.play_input(test);
-            state_info.lock().await.set_is_playing(true);
-
-            let activity = Activity::from(MinimalActivity {
-                kind: ActivityType::Listening,
-                name: "Radio 24 House".to_owned(),
-                url: None,
-            });
-            let request = UpdatePresence::new(Vec::from([activity]), false, None, Status::Online)?;
-
-            for shard in state.cluster.shards() {
-                shard.command(&request).await?;
-            }
-
-            let mut store = state.trackdata.write().await;
-            store.insert(guild_id, handle);
+    let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+    let played = state.guild_store.get(guild_id).await?.history;
+    let current = state_info.lock().await.now_playing.clone();
+
+    let content = match history::previous(&played, current.as_ref()) {
+        Some(track) => {
+            let track = Track {
+                requester_id: msg.author.id,
+                requester: msg.author.name.clone(),
+                added_at: SystemTime::now(),
+                ..track.clone()
+            };
+            let content = format!("**{}** is next in the queue", track.display_title());
+            queue.lock().await.queue.insert(0, track);
+            spawn(prefetch_next(
+                Arc::clone(&state),
+                Arc::clone(&state_info),
+                Arc::clone(&queue),
+            ));
+            content
         }
-    }
+        None => "There's no earlier track to go back to".to_string(),
+    };
+    state
+        .messenger
+        .send_message(msg.channel_id, &content)
+        .await?;
 
     Ok(())
 }
 
-async fn radioclubbers(
+async fn list(
     msg: Message,
     state: State,
     state_info: Arc<Mutex<StateInfo>>,
+    queue: Arc<Mutex<Queue1>>,
 ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
     if !state_info.lock().await.is_joined {
         let res = join(msg.clone(), state.clone(), state_info.clone())
@@ -1250,171 +3791,162 @@ async fn radioclubbers(
         }
     }
     if state_info.lock().await.is_joined {
-        let a = Command::new("ffmpeg")
-            .arg("-i")
-            .arg("https://s3.slotex.pl/shoutcast/7300/stream?sid=1")
-            .arg("-f")
-            .arg("wav")
-            .arg("-ac")
-            .arg("2")
-            .arg("-acodec")
-            .arg("pcm_s16le")
-            .arg("-ar")
-            .arg("48000")
-            .arg("-")
-            .stdout(Stdio::piped())
-            .spawn();
-        if !state_info.lock().await.ffmpeg_id.is_empty() {
-            let id = state_info.lock().await.ffmpeg_id.pop().unwrap();
-
-            let sp = Command::new("kill").arg(id.to_string()).output();
-            let _res = sp.unwrap();
-        }
-
-        let ch = a.unwrap();
-        let id = &ch.id();
-        state_info.lock().await.set_ffmpeg_id(*id);
-
-        let test = ChildContainer::from(ch).into();
-        let guild_id = msg.guild_id.unwrap();
-        let source = ImageSource::url(
-            "https://cdn2.vectorstock.com/i/1000x1000/01/16/radio-music-neon-logo-night-neon-vector-21420116.jpg",
-        )?;
-
-        let embed = EmbedBuilder::new()
-            .title("Radio Clubbers")
-            .field(EmbedFieldBuilder::new("Requestor", msg.author.name).inline())
-            .image(source)
-            .validate()?
-            .build();
-
-        state
-            .http
-            .create_message(msg.channel_id)
-            .embeds(&[embed])?
-            .await?;
-        if let Some(call_lock) = state.songbird.get(guild_id) {
-            if state_info.lock().await.is_playing {
-                let mut call = call_lock.lock().await;
-                let _ = call.stop();
-                state_info.lock().await.set_is_playing(false);
-            }
-
-            let mut call = call_lock.lock().await;
-            let handle = call.play_input(test);
-            state_info.lock().await.set_is_playing(true);
-
-            let activity = Activity::from(MinimalActivity {
-                kind: ActivityType::Listening,
-                name: "Radio Clubbers".to_owned(),
-                url: None,
-            });
-            let request = UpdatePresence::new(Vec::from([activity]), false, None, Status::Online)?;
+        let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+        let queued = queue.lock().await.queue.len();
+        if queued == 0 && state_info.lock().await.now_playing.is_none() {
+            state
+                .messenger
+                .send_message(msg.channel_id, "No songs in queue!")
+                .await?;
+            return Ok(());
+        }
 
-            for shard in state.cluster.shards() {
-                shard.command(&request).await?;
-            }
+        let mut page = 0;
+        let embed = queue_embed(&state_info, &queue, page).await?;
+        if queue_page::page_count(queued) == 1 {
+            state
+                .messenger
+                .send_embeds(msg.channel_id, &[embed])
+                .await?;
+            return Ok(());
+        }
+        let message_id = state.messenger.send_embed(msg.channel_id, &embed).await?;
+        for emoji in [PREVIOUS_PAGE, NEXT_PAGE] {
+            state
+                .messenger
+                .add_reaction(msg.channel_id, message_id, emoji)
+                .await?;
+        }
 
-            let mut store = state.trackdata.write().await;
-            store.insert(guild_id, handle);
+        let author_id = msg.author.id;
+        loop {
+            let turned = tokio::time::timeout(
+                Duration::from_secs(120),
+                state.standby.wait_for(guild_id, move |event: &Event| {
+                    page_turn(event, message_id, author_id).is_some()
+                }),
+            )
+            .await;
+            let forward = match turned {
+                Ok(Ok(event)) => page_turn(&event, message_id, author_id).unwrap_or(true),
+                _ => break,
+            };
+            let pages = queue_page::page_count(queue.lock().await.queue.len());
+            page = page.min(pages - 1);
+            page = if forward {
+                (page + 1) % pages
+            } else {
+                (page + pages - 1) % pages
+            };
+            let embed = queue_embed(&state_info, &queue, page).await?;
+            state
+                .messenger
+                .edit_embed(msg.channel_id, message_id, &embed)
+                .await?;
         }
     }
 
     Ok(())
 }
 
-async fn radiouv(
+/// `!queue export`: what's playing and queued, as an M3U attachment.
+async fn queue_command(
     msg: Message,
     state: State,
     state_info: Arc<Mutex<StateInfo>>,
+    queue: Arc<Mutex<Queue1>>,
 ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-    if !state_info.lock().await.is_joined {
-        let res = join(msg.clone(), state.clone(), state_info.clone())
-            .await
-            .ok();
-
-        match res {
-            Some(result) => println!("{:?}", result),
-            None => println!("ERR"),
-        }
+    if msg_utils::get_args(&msg.content).trim() != "export" {
+        state
+            .messenger
+            .send_message(msg.channel_id, "Use !queue export")
+            .await?;
+        return Ok(());
     }
-    if state_info.lock().await.is_joined {
-        let a = Command::new("ffmpeg")
-            .arg("-i")
-            .arg("https://stream-21.zeno.fm/s98kga59qnruv?zs=y9vZRej5RV69g4Ld8fD7QQ")
-            .arg("-f")
-            .arg("wav")
-            .arg("-ac")
-            .arg("2")
-            .arg("-acodec")
-            .arg("pcm_s16le")
-            .arg("-ar")
-            .arg("48000")
-            .arg("-")
-            .stdout(Stdio::piped())
-            .spawn();
-        if !state_info.lock().await.ffmpeg_id.is_empty() {
-            let id = state_info.lock().await.ffmpeg_id.pop().unwrap();
-
-            let sp = Command::new("kill").arg(id.to_string()).output();
-            let _res = sp.unwrap();
-        }
-
-        let ch = a.unwrap();
-        let id = &ch.id();
-        state_info.lock().await.set_ffmpeg_id(*id);
-
-        let test = ChildContainer::from(ch).into();
-        let guild_id = msg.guild_id.unwrap();
-        let source = ImageSource::url(
-            "https://cdn2.vectorstock.com/i/1000x1000/01/16/radio-music-neon-logo-night-neon-vector-21420116.jpg",
-        )?;
-
-        let embed = EmbedBuilder::new()
-            .title("Radio Underground Vibe")
-            .field(EmbedFieldBuilder::new("Requestor", msg.author.name).inline())
-            .image(source)
-            .validate()?
-            .build();
 
+    let mut tracks: Vec<Track> = state_info
+        .lock()
+        .await
+        .now_playing
+        .iter()
+        .cloned()
+        .collect();
+    tracks.extend(queue.lock().await.queue.iter().cloned());
+    let entries: Vec<PlaylistEntry> = tracks
+        .iter()
+        .filter_map(PlaylistEntry::from_track)
+        .collect();
+    if entries.is_empty() {
         state
-            .http
-            .create_message(msg.channel_id)
-            .embeds(&[embed])?
+            .messenger
+            .send_message(msg.channel_id, "There's nothing in the queue to export")
             .await?;
-        if let Some(call_lock) = state.songbird.get(guild_id) {
-            if state_info.lock().await.is_playing {
-                let mut call = call_lock.lock().await;
-                let _ = call.stop();
-                state_info.lock().await.set_is_playing(false);
-            }
-
-            let mut call = call_lock.lock().await;
-            let handle = call.play_input(test);
-            state_info.lock().await.set_is_playing(true);
-            let activity = Activity::from(MinimalActivity {
-                kind: ActivityType::Listening,
-                name: "Radio Underground Vibe".to_owned(),
-                url: None,
-            });
-            let request = UpdatePresence::new(Vec::from([activity]), false, None, Status::Online)?;
-
-            for shard in state.cluster.shards() {
-                shard.command(&request).await?;
-            }
+        return Ok(());
+    }
+    state
+        .messenger
+        .send_file(
+            msg.channel_id,
+            "queue.m3u",
+            playlist_file::to_m3u(&entries).as_bytes(),
+        )
+        .await
+}
 
-            let mut store = state.trackdata.write().await;
-            store.insert(guild_id, handle);
-        }
+const PREVIOUS_PAGE: &str = "⬅️";
+const NEXT_PAGE: &str = "➡️";
+
+/// Whether `event` is `author_id` turning the pages of `message_id` forward or back. Taking
+/// a reaction away turns the page too, so nobody has to remove one before clicking again.
+fn page_turn(
+    event: &Event,
+    message_id: Id<MessageMarker>,
+    author_id: Id<UserMarker>,
+) -> Option<bool> {
+    let reaction = match event {
+        Event::ReactionAdd(reaction) => &reaction.0,
+        Event::ReactionRemove(reaction) => &reaction.0,
+        _ => return None,
+    };
+    if reaction.message_id != message_id || reaction.user_id != author_id {
+        return None;
+    }
+    match &reaction.emoji {
+        ReactionType::Unicode { name } if name == NEXT_PAGE => Some(true),
+        ReactionType::Unicode { name } if name == PREVIOUS_PAGE => Some(false),
+        _ => None,
     }
+}
 
-    Ok(())
+/// Page `page` of `!list`, read from the queue as it is now.
+async fn queue_embed(
+    state_info: &Arc<Mutex<StateInfo>>,
+    queue: &Arc<Mutex<Queue1>>,
+    page: usize,
+) -> Result<Embed, Box<dyn Error + Send + Sync + 'static>> {
+    let (playing, paused) = {
+        let info = state_info.lock().await;
+        (info.now_playing.clone(), info.paused_at.is_some())
+    };
+    let queued = queue.lock().await.queue.clone();
+    Ok(EmbedBuilder::new()
+        .title("Queue")
+        .description(queue_page::describe(
+            playing.as_ref(),
+            paused,
+            &queued,
+            page,
+        ))
+        .footer(EmbedFooterBuilder::new(queue_page::footer(&queued, page)))
+        .validate()?
+        .build())
 }
 
-async fn radiodancefmro(
+async fn add(
     msg: Message,
     state: State,
     state_info: Arc<Mutex<StateInfo>>,
+    queue: Arc<Mutex<Queue1>>,
 ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
     if !state_info.lock().await.is_joined {
         let res = join(msg.clone(), state.clone(), state_info.clone())
@@ -1427,80 +3959,50 @@ async fn radiodancefmro(
         }
     }
     if state_info.lock().await.is_joined {
-        let a = Command::new("ffmpeg")
-            .arg("-i")
-            .arg("https://edge126.rcs-rds.ro/profm/dancefm.mp3")
-            .arg("-f")
-            .arg("wav")
-            .arg("-ac")
-            .arg("2")
-            .arg("-acodec")
-            .arg("pcm_s16le")
-            .arg("-ar")
-            .arg("48000")
-            .arg("-")
-            .stdout(Stdio::piped())
-            .spawn();
-        if !state_info.lock().await.ffmpeg_id.is_empty() {
-            let id = state_info.lock().await.ffmpeg_id.pop().unwrap();
-
-            let sp = Command::new("kill").arg(id.to_string()).output();
-            let _res = sp.unwrap();
-        }
-
-        let ch = a.unwrap();
-        let id = &ch.id();
-        state_info.lock().await.set_ffmpeg_id(*id);
-
-        let test = ChildContainer::from(ch).into();
-        let guild_id = msg.guild_id.unwrap();
-        let source = ImageSource::url(
-            "https://cdn2.vectorstock.com/i/1000x1000/01/16/radio-music-neon-logo-night-neon-vector-21420116.jpg",
-        )?;
-
-        let embed = EmbedBuilder::new()
-            .title("DanceFM.RO")
-            .field(EmbedFieldBuilder::new("Requestor", msg.author.name).inline())
-            .image(source)
-            .validate()?
-            .build();
+        let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
 
-        state
-            .http
-            .create_message(msg.channel_id)
-            .embeds(&[embed])?
-            .await?;
-        if let Some(call_lock) = state.songbird.get(guild_id) {
-            if state_info.lock().await.is_playing {
-                let mut call = call_lock.lock().await;
-                let _ = call.stop();
-                state_info.lock().await.set_is_playing(false);
-            }
+        let text = msg_utils::get_query(&msg.content);
 
-            let mut call = call_lock.lock().await;
-            let handle = call.play_input(test);
-            state_info.lock().await.set_is_playing(true);
+        if state.voice.is_connected(guild_id) {
+            // let mut call = call_lock.lock().await;
+            // let queue = queue.queues.entry(songbird::id::GuildId(guild_id.get()))
 
-            let activity = Activity::from(MinimalActivity {
-                kind: ActivityType::Listening,
-                name: "DanceFM.ro".to_owned(),
-                url: None,
-            });
-            let request = UpdatePresence::new(Vec::from([activity]), false, None, Status::Online)?;
+            // let queue_q = queue.lock().unwrap().queues.entry(songbird::id::GuildId(guild_id.get())).or_default();
+
+            println!("{:?}", text);
+            /*let source = ytdl(text)
+            .await
+              .expect("This might fail: handle this error!");*/
 
-            for shard in state.cluster.shards() {
-                shard.command(&request).await?;
+            //   let title =  source.metadata.title.as_ref().unwrap().clone();
+            let mut title = "".to_string();
+            let mut track = Track::new(MediaItemType::Youtube, text.clone(), &msg.author);
+            if let Ok(metadata) = resolve_metadata(&state, &text).await {
+                let content = format!(
+                    "**{:?}** added !",
+                    metadata.title.as_ref().unwrap_or(&"<UNKNOWN>".to_string()),
+                );
+                title = content.clone();
+                track = track.with_metadata(&metadata);
             }
 
-            let mut store = state.trackdata.write().await;
-            store.insert(guild_id, handle);
+            // Queueing a track is this easy!
+            //let hnd = queue.add_source(source.into(), &mut call);
+            queue.lock().await.add([track]);
+            spawn(prefetch_next(
+                Arc::clone(&state),
+                Arc::clone(&state_info),
+                Arc::clone(&queue),
+            ));
+
+            state.messenger.send_message(msg.channel_id, &title).await?;
         }
     }
 
     Ok(())
 }
 
-async fn radiohouse(
+async fn description(
     msg: Message,
     state: State,
     state_info: Arc<Mutex<StateInfo>>,
@@ -1516,196 +4018,200 @@ async fn radiohouse(
         }
     }
     if state_info.lock().await.is_joined {
-        let a = Command::new("ffmpeg")
-            .arg("-thread_queue_size")
-            .arg("3")
-            .arg("-i")
-            .arg("https://deephouseradio.radioca.st/deep?type=http&nocache=16")
-            .arg("-f")
-            .arg("wav")
-            .arg("-ac")
-            .arg("2")
-            .arg("-acodec")
-            .arg("pcm_s16le")
-            .arg("-ar")
-            .arg("48000")
-            .arg("-")
-            .stdout(Stdio::piped())
-            .spawn();
-        if !state_info.lock().await.ffmpeg_id.is_empty() {
-            let id = state_info.lock().await.ffmpeg_id.pop().unwrap();
-
-            let sp = Command::new("kill").arg(id.to_string()).output();
-            let _res = sp.unwrap();
-        }
-
-        let ch = a.unwrap();
-        let id = &ch.id();
-        state_info.lock().await.set_ffmpeg_id(*id);
-
-        let test = ChildContainer::from(ch).into();
-        let guild_id = msg.guild_id.unwrap();
-        let source = ImageSource::url(
-            "https://cdn2.vectorstock.com/i/1000x1000/01/16/radio-music-neon-logo-night-neon-vector-21420116.jpg",
-        )?;
-
-        let embed = EmbedBuilder::new()
-            .title("Radio House Santa Monica")
-            .field(EmbedFieldBuilder::new("Requestor", msg.author.name).inline())
-            .image(source)
-            .validate()?
-            .build();
-
-        state
-            .http
-            .create_message(msg.channel_id)
-            .embeds(&[embed])?
-            .await?;
-        if let Some(call_lock) = state.songbird.get(guild_id) {
-            if state_info.lock().await.is_playing {
-                let mut call = call_lock.lock().await;
-                let _ = call.stop();
-                state_info.lock().await.set_is_playing(false);
-            }
-
-            let mut call = call_lock.lock().await;
-            let handle = call.play_input(test);
-            state_info.lock().await.set_is_playing(true);
-            let activity = Activity::from(MinimalActivity {
-                kind: ActivityType::Listening,
-                name: "Radio House Santa monica".to_owned(),
-                url: None,
-            });
-            let request = UpdatePresence::new(Vec::from([activity]), false, None, Status::Online)?;
-
-            for shard in state.cluster.shards() {
-                shard.command(&request).await?;
+        let guild_id = msg.guild_id.ok_or("Not in a guild channel.")?;
+        let store = state.trackdata.read().await;
+        if let Some(handle) = store.get(&guild_id) {
+            let h = handle.get_info().await;
+            if h.is_ok() {
+                send_description(
+                    state.messenger.as_ref(),
+                    state.fetcher.as_ref(),
+                    &state.metadata,
+                    &state_info,
+                    msg.channel_id,
+                )
+                .await?;
+            } else {
+                state
+                    .messenger
+                    .send_message(msg.channel_id, "`No song is currently playing!`")
+                    .await?;
             }
-
-            let mut store = state.trackdata.write().await;
-            store.insert(guild_id, handle);
         }
     }
 
     Ok(())
 }
 
-async fn radiovirgin(
+/// `!lyrics [artist - title]`, for the current track when no song is given.
+async fn lyrics(
     msg: Message,
     state: State,
     state_info: Arc<Mutex<StateInfo>>,
 ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-    let now = Instant::now();
-    if !state_info.lock().await.is_joined {
-        let res = join(msg.clone(), state.clone(), state_info.clone())
-            .await
-            .ok();
-
-        match res {
-            Some(result) => println!("{:?}", result),
-            None => println!("ERR"),
+    let query = msg_utils::get_args(&msg.content);
+    let song = if query.is_empty() {
+        let playing = state_info.lock().await.now_playing.clone();
+        match playing {
+            Some(track) => song_for(&state, &track).await,
+            None => {
+                state
+                    .messenger
+                    .send_message(
+                        msg.channel_id,
+                        "Nothing is playing, use !lyrics *artist - title*",
+                    )
+                    .await?;
+                return Ok(());
+            }
         }
-    }
+    } else {
+        SongQuery::parse(query)
+    };
+    let song = match song {
+        Some(song) => song,
+        None => {
+            state
+                .messenger
+                .send_message(
+                    msg.channel_id,
+                    "Can't tell which song that is, use !lyrics *artist - title*",
+                )
+                .await?;
+            return Ok(());
+        }
+    };
 
-    if state_info.lock().await.is_joined {
-        let a = Command::new("ffmpeg")
-            .arg("-i")
-            .arg("https://astreaming.edi.ro:8443/VirginRadio_aac")
-            .arg("-f")
-            .arg("wav")
-            .arg("-ac")
-            .arg("2")
-            .arg("-acodec")
-            .arg("pcm_s16le")
-            .arg("-ar")
-            .arg("48000")
-            .arg("-")
-            .stdout(Stdio::piped())
-            .spawn();
-
-        if !state_info.lock().await.ffmpeg_id.is_empty() {
-            let id = state_info.lock().await.ffmpeg_id.pop().unwrap();
-
-            let sp = Command::new("kill").arg(id.to_string()).output();
-            let _res = sp.unwrap();
-        }
-
-        let ch = a.unwrap();
-        let id = &ch.id();
-        state_info.lock().await.set_ffmpeg_id(*id);
-
-        let test = ChildContainer::from(ch).into();
-
-        let guild_id = msg.guild_id.unwrap();
-
-        let source = ImageSource::url(
-            "https://virginradio.ro/wp-content/uploads/2019/06/VR_ROMANIA_WHITE-STAR-LOGO_RGB_ONLINE_1600x1600.png",
-        )?;
-        let embed = EmbedBuilder::new()
-            .title("Virgin Radio Romania")
-            .field(EmbedFieldBuilder::new("Requestor", msg.author.name).inline())
-            .image(source)
-            .validate()?
-            .build();
+    let found = match state.lyrics.lyrics(&song).await {
+        Ok(found) => found,
+        Err(why) => {
+            println!("Lyrics lookup for {} failed: {}", song.label(), why);
+            None
+        }
+    };
+    let found = match found {
+        Some(found) => found,
+        None => {
+            let content = format!("No lyrics found for **{}**", song.label());
+            state
+                .messenger
+                .send_message(msg.channel_id, &content)
+                .await?;
+            return Ok(());
+        }
+    };
 
+    let title: String = if found.artist.is_empty() {
+        found.title.clone()
+    } else {
+        format!("{} - {}", found.artist, found.title)
+    }
+    .chars()
+    .take(256)
+    .collect();
+    let pages = lyrics::pages(&found.text, lyrics::PAGE_LENGTH);
+    let count = pages.len();
+    for (index, page) in pages.into_iter().enumerate() {
+        let mut embed = EmbedBuilder::new().description(page);
+        if index == 0 {
+            embed = embed.title(&title);
+        }
+        if count > 1 {
+            embed = embed.footer(EmbedFooterBuilder::new(format!("{}/{}", index + 1, count)));
+        }
+        // A message's embeds share a 6000 character limit, so each page goes on its own.
         state
-            .http
-            .create_message(msg.channel_id)
-            .embeds(&[embed])?
+            .messenger
+            .send_embeds(msg.channel_id, &[embed.validate()?.build()])
             .await?;
+    }
 
-        if let Some(call_lock) = state.songbird.get(guild_id) {
-            if state_info.lock().await.is_playing {
-                let mut call = call_lock.lock().await;
-                let _ = call.stop();
-                state_info.lock().await.set_is_playing(false);
-            }
-
-            let mut call = call_lock.lock().await;
-            let handle = call.play_input(test);
-            state_info.lock().await.set_is_playing(true);
-
-            let activity = Activity::from(MinimalActivity {
-                kind: ActivityType::Listening,
-                name: "Virgin Radio Romania".to_owned(),
-                url: None,
-            });
-            let request = UpdatePresence::new(Vec::from([activity]), false, None, Status::Online)?;
+    Ok(())
+}
 
-            for shard in state.cluster.shards() {
-                shard.command(&request).await?;
+/// The song a track is, from the YouTube watch page for videos and tags otherwise.
+async fn song_for(state: &State, track: &Track) -> Option<SongQuery> {
+    if yt_utils::video_id(&track.source_url).is_some() {
+        if let Ok(details) =
+            video_details(state.fetcher.as_ref(), &state.metadata, &track.source_url).await
+        {
+            if let (Some(title), Some(author)) = (&details.title, &details.author) {
+                if let Some(song) = SongQuery::from_video(title, author) {
+                    return Some(song);
+                }
             }
+        }
+    }
+    let title = track.title.as_deref()?;
+    match &track.artist {
+        Some(artist) => SongQuery::from_video(title, artist),
+        None => SongQuery::parse(title),
+    }
+}
 
-            let mut store = state.trackdata.write().await;
-            store.insert(guild_id, handle);
+async fn send_description(
+    messenger: &dyn Messenger,
+    fetcher: &dyn Fetcher,
+    metadata: &MetadataCache,
+    state_info: &Mutex<StateInfo>,
+    channel_id: Id<ChannelMarker>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let song_link = state_info
+        .lock()
+        .await
+        .now_playing
+        .as_ref()
+        .filter(|track| track.kind == MediaItemType::Youtube)
+        .map(|track| track.source_url.clone());
+    let song_link = match song_link {
+        Some(song_link) => song_link,
+        None => {
+            messenger
+                .send_message(channel_id, "`Only YouTube videos have a description`")
+                .await?;
+            return Ok(());
         }
+    };
+    let details = video_details(fetcher, metadata, &song_link).await?;
+    let to_split = details.description.unwrap_or_default();
 
-        let elapsed = now.elapsed();
-        println!("Elapsed Radio: {:.2?}", elapsed);
-    }
+    state_info
+        .lock()
+        .await
+        .set_current_song_desc(to_split.clone());
+
+    let re = Regex::new(r"\\n").unwrap();
+    let result = re.replace_all(&to_split, "\n");
+    let re2 = Regex::new(r"(https://)|(http://)").unwrap();
+    let result2 = re2.replace_all(&result, "[http][//]");
+
+    let re3 = Regex::new(r"\n\n").unwrap();
+    let result3 = re3.replace_all(&result2, "\n");
+    let result_final: String = result3.chars().take(1999).collect();
+    messenger.send_message(channel_id, &result_final).await?;
 
     Ok(())
 }
 
 fn get_discord_token() -> String {
     let mut return_string: String = String::default();
-    let path = fs::canonicalize("./token.txt").unwrap();
+    let path = Path::new("./token.txt");
     if path.exists() {
-        return_string = read_to_string(path)
-            .expect("Unable to open file")
-            .trim()
-            .to_string();
+        match read_to_string(path) {
+            Ok(token) => return_string = token.trim().to_string(),
+            Err(why) => println!("Unable to open file: {}", why),
+        }
     } else {
-        let mut file1 = OpenOptions::new()
+        let written = OpenOptions::new()
             .create(true)
             .write(true)
             .append(true)
             .read(true)
-            .open(fs::canonicalize("./token.txt").unwrap())
-            .unwrap();
-        file1
-            .write_all("<Insert discord token here>".as_bytes())
-            .expect("err");
+            .open(path)
+            .and_then(|mut file1| file1.write_all("<Insert discord token here>".as_bytes()));
+        if let Err(why) = written {
+            println!("Unable to create token.txt: {}", why);
+        }
     }
     return_string
 }
@@ -1,4 +1,16 @@
-enum MediaItemType {
+use serde::{Deserialize, Serialize};
+
+/// Where a track comes from, which decides how it is turned back into audio.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MediaItemType {
     Radio,
+    /// YouTube or any other site resolved through yt-dlp.
     Youtube,
+    LocalFile,
+    DirectUrl,
+    PlaylistEntry {
+        playlist: String,
+    },
+    /// Queued by `!autoplay` from the videos YouTube suggests next to the last track.
+    Autoplay,
 }
@@ -0,0 +1,132 @@
+use async_trait::async_trait;
+use std::{error::Error, fmt::Debug};
+use twilight_http::{request::channel::reaction::RequestReactionType, Client as HttpClient};
+use twilight_model::{
+    channel::embed::Embed,
+    http::attachment::Attachment,
+    id::{
+        marker::{ChannelMarker, MessageMarker},
+        Id,
+    },
+};
+
+/// Everything the handlers send back to Discord goes through here, so it can be
+/// captured instead of posted when running offline.
+#[async_trait]
+pub trait Messenger: Debug + Send + Sync {
+    async fn send_message(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        content: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>>;
+
+    async fn send_embeds(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        embeds: &[Embed],
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>>;
+
+    async fn send_file(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        filename: &str,
+        content: &[u8],
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>>;
+
+    /// Posts a single embed and returns its message, to be edited later.
+    async fn send_embed(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        embed: &Embed,
+    ) -> Result<Id<MessageMarker>, Box<dyn Error + Send + Sync + 'static>>;
+
+    async fn edit_embed(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        message_id: Id<MessageMarker>,
+        embed: &Embed,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>>;
+
+    /// Reacts to a message with a unicode emoji.
+    async fn add_reaction(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        message_id: Id<MessageMarker>,
+        emoji: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>>;
+}
+
+#[async_trait]
+impl Messenger for HttpClient {
+    async fn send_message(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        content: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.create_message(channel_id).content(content)?.await?;
+        Ok(())
+    }
+
+    async fn send_embeds(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        embeds: &[Embed],
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.create_message(channel_id).embeds(embeds)?.await?;
+        Ok(())
+    }
+
+    async fn send_file(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        filename: &str,
+        content: &[u8],
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        let attachment = Attachment::from_bytes(filename.to_string(), content.to_vec(), 0);
+        self.create_message(channel_id)
+            .attachments(&[attachment])?
+            .await?;
+        Ok(())
+    }
+
+    async fn send_embed(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        embed: &Embed,
+    ) -> Result<Id<MessageMarker>, Box<dyn Error + Send + Sync + 'static>> {
+        let message = self
+            .create_message(channel_id)
+            .embeds(&[embed.clone()])?
+            .await?
+            .model()
+            .await?;
+        Ok(message.id)
+    }
+
+    async fn edit_embed(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        message_id: Id<MessageMarker>,
+        embed: &Embed,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.update_message(channel_id, message_id)
+            .embeds(Some(&[embed.clone()]))?
+            .await?;
+        Ok(())
+    }
+
+    async fn add_reaction(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        message_id: Id<MessageMarker>,
+        emoji: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.create_reaction(
+            channel_id,
+            message_id,
+            &RequestReactionType::Unicode { name: emoji },
+        )
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,346 @@
+use crate::yt_utils::{self, YtInfo};
+use serde::{Deserialize, Serialize};
+use songbird::input::AuxMetadata;
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+use tokio::sync::Mutex;
+
+pub const DEFAULT_CAPACITY: usize = 500;
+pub const DEFAULT_TTL: Duration = Duration::from_secs(6 * 3600);
+
+/// What yt-dlp and the watch page told us about a link.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub duration: Option<Duration>,
+    pub thumbnail: Option<String>,
+    pub description: Option<String>,
+    /// From the watch page, see `YtInfo::get_loudness_db`.
+    #[serde(default)]
+    pub loudness_db: Option<f32>,
+}
+
+fn non_empty(text: String) -> Option<String> {
+    Some(text).filter(|text| !text.is_empty())
+}
+
+impl Metadata {
+    pub fn from_yt_info(info: &YtInfo) -> Self {
+        Metadata {
+            title: non_empty(info.get_yt_title()),
+            author: non_empty(info.get_yt_author()),
+            description: non_empty(info.get_yt_desc()),
+            loudness_db: info.get_loudness_db(),
+            ..Metadata::default()
+        }
+    }
+
+    pub fn to_aux(&self) -> AuxMetadata {
+        AuxMetadata {
+            title: self.title.clone(),
+            artist: self.author.clone(),
+            duration: self.duration,
+            thumbnail: self.thumbnail.clone(),
+            ..AuxMetadata::default()
+        }
+    }
+
+    /// Whether yt-dlp has been asked; a watch page alone has no duration or thumbnail.
+    pub fn is_resolved(&self) -> bool {
+        self.title.is_some() && (self.duration.is_some() || self.thumbnail.is_some())
+    }
+
+    /// `self`, with the gaps filled from `newer`. yt-dlp's fields beat the watch page's
+    /// loose regex matches whichever arrived first; `loudness_db` only comes from the
+    /// watch page, so the newest one wins.
+    fn merged(self, newer: Metadata) -> Metadata {
+        let loudness_db = newer.loudness_db.or(self.loudness_db);
+        let (first, second) = if newer.is_resolved() && !self.is_resolved() {
+            (newer, self)
+        } else {
+            (self, newer)
+        };
+        Metadata {
+            title: first.title.or(second.title),
+            author: first.author.or(second.author),
+            duration: first.duration.or(second.duration),
+            thumbnail: first.thumbnail.or(second.thumbnail),
+            description: first.description.or(second.description),
+            loudness_db,
+        }
+    }
+}
+
+/// The same video under any of its links shares one entry.
+pub fn cache_key(url: &str) -> String {
+    match yt_utils::video_id(url) {
+        Some(id) => format!("youtube:{}", id),
+        None => url.trim().to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    metadata: Metadata,
+    fetched_at: SystemTime,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    loaded: bool,
+    map: HashMap<String, Entry>,
+}
+
+/// Metadata by link, kept for `ttl` and at most `capacity` links, oldest dropped first.
+/// With a file it is read on first use and written after every change.
+#[derive(Debug)]
+pub struct MetadataCache {
+    file: Option<PathBuf>,
+    capacity: usize,
+    ttl: Duration,
+    entries: Mutex<Entries>,
+}
+
+impl MetadataCache {
+    pub fn new(file: Option<PathBuf>, capacity: usize, ttl: Duration) -> Self {
+        MetadataCache {
+            file,
+            capacity,
+            ttl,
+            entries: Mutex::default(),
+        }
+    }
+
+    fn is_fresh(&self, entry: &Entry, now: SystemTime) -> bool {
+        now.duration_since(entry.fetched_at)
+            .map(|age| age < self.ttl)
+            .unwrap_or(true)
+    }
+
+    async fn read(&self, entries: &mut Entries) {
+        if entries.loaded {
+            return;
+        }
+        entries.loaded = true;
+        let file = match &self.file {
+            Some(file) => file,
+            None => return,
+        };
+        match tokio::fs::read(file).await {
+            Ok(bytes) => match serde_json::from_slice(&bytes) {
+                Ok(map) => entries.map = map,
+                Err(why) => println!("Ignoring metadata cache {:?}: {}", file, why),
+            },
+            Err(why) if why.kind() == std::io::ErrorKind::NotFound => {}
+            Err(why) => println!("Can't read metadata cache {:?}: {}", file, why),
+        }
+    }
+
+    async fn write(&self, entries: &Entries) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        if let Some(dir) = file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let temp = file.with_extension("json.tmp");
+        tokio::fs::write(&temp, serde_json::to_vec(&entries.map)?).await?;
+        tokio::fs::rename(&temp, file).await?;
+        Ok(())
+    }
+
+    pub async fn get(&self, url: &str) -> Option<Metadata> {
+        self.get_at(url, SystemTime::now()).await
+    }
+
+    async fn get_at(&self, url: &str, now: SystemTime) -> Option<Metadata> {
+        let mut entries = self.entries.lock().await;
+        self.read(&mut entries).await;
+        entries
+            .map
+            .get(&cache_key(url))
+            .filter(|entry| self.is_fresh(entry, now))
+            .map(|entry| entry.metadata.clone())
+    }
+
+    /// Stores `metadata` for `url`, keeping what an earlier, still fresh entry knew that
+    /// this one doesn't. Returns the merged metadata.
+    pub async fn insert(&self, url: &str, metadata: Metadata) -> Metadata {
+        self.insert_at(url, metadata, SystemTime::now()).await
+    }
+
+    async fn insert_at(&self, url: &str, metadata: Metadata, now: SystemTime) -> Metadata {
+        let mut entries = self.entries.lock().await;
+        self.read(&mut entries).await;
+        let key = cache_key(url);
+        let metadata = match entries.map.remove(&key) {
+            Some(entry) if self.is_fresh(&entry, now) => entry.metadata.merged(metadata),
+            _ => metadata,
+        };
+        entries.map.retain(|_, entry| self.is_fresh(entry, now));
+        while entries.map.len() >= self.capacity.max(1) {
+            let oldest = entries
+                .map
+                .iter()
+                .min_by_key(|(_, entry)| entry.fetched_at)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => entries.map.remove(&oldest),
+                None => break,
+            };
+        }
+        entries.map.insert(
+            key,
+            Entry {
+                metadata: metadata.clone(),
+                fetched_at: now,
+            },
+        );
+        if let Err(why) = self.write(&entries).await {
+            println!("Can't save metadata cache: {}", why);
+        }
+        metadata
+    }
+}
+
+/// `AURRAS_METADATA_CACHE_SIZE` links for `AURRAS_METADATA_TTL_SECS`, saved to
+/// `AURRAS_METADATA_CACHE_FILE` when that is set.
+pub fn cache_from_env() -> MetadataCache {
+    let capacity = env::var("AURRAS_METADATA_CACHE_SIZE")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_CAPACITY);
+    let ttl = env::var("AURRAS_METADATA_TTL_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TTL);
+    let file = env::var_os("AURRAS_METADATA_CACHE_FILE").map(PathBuf::from);
+    MetadataCache::new(file, capacity, ttl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titled(title: &str) -> Metadata {
+        Metadata {
+            title: Some(title.to_string()),
+            duration: Some(Duration::from_secs(212)),
+            ..Metadata::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn entries_expire_and_make_room() {
+        let cache = MetadataCache::new(None, 2, Duration::from_secs(60));
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let watch = "https://www.youtube.com/watch?v=dQw4w9WgXcQ";
+
+        cache.insert_at(watch, titled("First"), start).await;
+        assert_eq!(
+            cache.get_at("https://youtu.be/dQw4w9WgXcQ", start).await,
+            Some(titled("First"))
+        );
+        assert_eq!(
+            cache.get_at(watch, start + Duration::from_secs(60)).await,
+            None
+        );
+
+        let later = start + Duration::from_secs(10);
+        cache
+            .insert_at("https://a.example.com/1.mp3", titled("One"), later)
+            .await;
+        cache
+            .insert_at(
+                "https://a.example.com/2.mp3",
+                titled("Two"),
+                later + Duration::from_secs(1),
+            )
+            .await;
+        assert_eq!(cache.get_at(watch, later).await, None);
+        assert!(cache
+            .get_at("https://a.example.com/2.mp3", later)
+            .await
+            .is_some());
+    }
+
+    fn watch_page(title: &str, loudness_db: f32) -> Metadata {
+        Metadata {
+            title: Some(title.to_string()),
+            author: Some("Watch page author".to_string()),
+            description: Some("From the watch page".to_string()),
+            loudness_db: Some(loudness_db),
+            ..Metadata::default()
+        }
+    }
+
+    #[test]
+    fn yt_dlp_fields_are_kept_over_the_watch_page() {
+        let merged = titled("yt-dlp title").merged(watch_page("regex title", -3.0));
+        assert_eq!(merged.title.as_deref(), Some("yt-dlp title"));
+        assert_eq!(merged.duration, Some(Duration::from_secs(212)));
+        // What yt-dlp didn't know is filled in.
+        assert_eq!(merged.author.as_deref(), Some("Watch page author"));
+        assert_eq!(merged.description.as_deref(), Some("From the watch page"));
+        assert_eq!(merged.loudness_db, Some(-3.0));
+    }
+
+    #[test]
+    fn yt_dlp_fields_replace_an_earlier_watch_page() {
+        let merged = watch_page("regex title", -3.0).merged(titled("yt-dlp title"));
+        assert_eq!(merged.title.as_deref(), Some("yt-dlp title"));
+        assert_eq!(merged.author.as_deref(), Some("Watch page author"));
+        assert_eq!(merged.description.as_deref(), Some("From the watch page"));
+        assert_eq!(merged.loudness_db, Some(-3.0));
+    }
+
+    #[test]
+    fn later_fetches_only_fill_gaps() {
+        let merged = titled("First yt-dlp title").merged(titled("Second yt-dlp title"));
+        assert_eq!(merged.title.as_deref(), Some("First yt-dlp title"));
+
+        let merged = watch_page("First page", -3.0).merged(watch_page("Second page", 1.5));
+        assert_eq!(merged.title.as_deref(), Some("First page"));
+        assert_eq!(merged.loudness_db, Some(1.5));
+
+        let merged = watch_page("Page", -3.0).merged(Metadata::default());
+        assert_eq!(merged.loudness_db, Some(-3.0));
+    }
+
+    #[tokio::test]
+    async fn descriptions_are_merged_and_saved() {
+        let file = env::temp_dir()
+            .join(format!("aurras-metadata-{}", std::process::id()))
+            .join("metadata.json");
+        let url = "https://www.youtube.com/watch?v=dQw4w9WgXcQ";
+        let cache = MetadataCache::new(Some(file.clone()), 10, DEFAULT_TTL);
+
+        cache.insert(url, titled("Never Gonna Give You Up")).await;
+        let merged = cache
+            .insert(
+                url,
+                Metadata {
+                    author: Some("Rick Astley".to_string()),
+                    description: Some("The official video".to_string()),
+                    ..Metadata::default()
+                },
+            )
+            .await;
+        assert!(merged.is_resolved());
+        assert_eq!(merged.title.as_deref(), Some("Never Gonna Give You Up"));
+        assert_eq!(merged.description.as_deref(), Some("The official video"));
+
+        let reopened = MetadataCache::new(Some(file.clone()), 10, DEFAULT_TTL);
+        assert_eq!(reopened.get(url).await, Some(merged));
+
+        std::fs::remove_dir_all(file.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,87 @@
+use crate::volume::Volume;
+use std::{error::Error, time::Duration};
+
+/// The command word (`!play`, `!volume`, ...) the message starts with.
+pub fn get_command(content: &str) -> &str {
+    content.split_whitespace().next().unwrap_or_default()
+}
+
+/// Everything after the command word, trimmed. Empty when there are no arguments.
+pub fn get_args(content: &str) -> &str {
+    match content.trim().split_once(char::is_whitespace) {
+        Some((_, args)) => args.trim(),
+        None => "",
+    }
+}
+
+/// Arguments joined with `+`, ready to be used as a YouTube search query.
+pub fn get_query(content: &str) -> String {
+    get_args(content)
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join("+")
+}
+
+/// `50` or `50%`, from 0 to 100.
+pub fn parse_volume(args: &str) -> Result<Volume, Box<dyn Error + Send + Sync + 'static>> {
+    let percent = args.trim().trim_end_matches('%').parse::<u8>()?;
+    Volume::new(percent).ok_or_else(|| "Invalid volume!".into())
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}H:{}m:{}s", secs / 3600, (secs / 60) % 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn args_after_command() {
+        assert_eq!(get_command("!play  never gonna"), "!play");
+        assert_eq!(get_command(""), "");
+        assert_eq!(get_args("!play  never gonna  "), "never gonna");
+        assert_eq!(get_args("!play"), "");
+        assert_eq!(get_args("!volume\t5"), "5");
+        assert_eq!(get_query("!play never   gonna give"), "never+gonna+give");
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(format_duration(Duration::from_secs(212)), "0H:3m:32s");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1H:2m:3s");
+    }
+
+    #[test]
+    fn volume_bounds() {
+        assert_eq!(parse_volume(" 40 ").ok(), Volume::new(40));
+        assert_eq!(parse_volume("100%").ok(), Some(Volume::default()));
+        assert!(parse_volume("").is_err());
+        assert!(parse_volume("101").is_err());
+        assert!(parse_volume("2.5").is_err());
+        assert!(parse_volume("-1").is_err());
+        assert!(parse_volume("NaN").is_err());
+        assert!(parse_volume("inf").is_err());
+        assert!(parse_volume("ăș").is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn args_never_panic(content in "\\PC*") {
+            let command = get_command(&content);
+            prop_assert!(content.contains(command));
+            let args = get_args(&content);
+            prop_assert!(content.contains(args));
+            let _ = get_query(&content);
+        }
+
+        #[test]
+        fn volume_never_panics_and_stays_in_range(content in "\\PC*") {
+            if let Ok(volume) = parse_volume(get_args(&content)) {
+                prop_assert!(volume.percent() <= Volume::MAX);
+            }
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use crate::{
+    fetcher::Fetcher,
+    playlists::{PlaylistEntry, MAX_ENTRIES},
+    radio_meta, url_utils,
+};
+use std::{collections::BTreeMap, error::Error, time::Duration};
+
+const PLAYLIST_EXTENSIONS: [&str; 3] = ["m3u", "m3u8", "pls"];
+
+/// Whether an attachment called `filename` is a playlist rather than audio.
+pub fn is_playlist_file(filename: &str) -> bool {
+    filename
+        .rsplit_once('.')
+        .map(|(_, ext)| PLAYLIST_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// An HLS media or master playlist, which is a stream to hand to ffmpeg rather than a
+/// list of links.
+pub fn is_hls(text: &str) -> bool {
+    text.lines()
+        .any(|line| line.trim_start().starts_with("#EXT-X-"))
+}
+
+fn is_pls(text: &str) -> bool {
+    text.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(|line| line.eq_ignore_ascii_case("[playlist]"))
+        .unwrap_or(false)
+}
+
+/// One link per line.
+pub fn to_text(entries: &[PlaylistEntry]) -> String {
+    entries
+        .iter()
+        .map(|entry| format!("{}\n", entry.url))
+        .collect()
+}
+
+fn seconds(entry: &PlaylistEntry) -> i64 {
+    entry
+        .duration
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(-1)
+}
+
+/// An extended M3U playlist.
+pub fn to_m3u(entries: &[PlaylistEntry]) -> String {
+    let mut m3u = String::from("#EXTM3U\n");
+    for entry in entries {
+        m3u.push_str(&format!(
+            "#EXTINF:{},{}\n{}\n",
+            seconds(entry),
+            entry.display_title(),
+            entry.url
+        ));
+    }
+    m3u
+}
+
+/// A version 2 PLS playlist.
+pub fn to_pls(entries: &[PlaylistEntry]) -> String {
+    let mut pls = String::from("[playlist]\n");
+    for (index, entry) in entries.iter().enumerate() {
+        let number = index + 1;
+        pls.push_str(&format!(
+            "File{n}={}\nTitle{n}={}\nLength{n}={}\n",
+            entry.url,
+            entry.display_title(),
+            seconds(entry),
+            n = number
+        ));
+    }
+    pls.push_str(&format!("NumberOfEntries={}\nVersion=2\n", entries.len()));
+    pls
+}
+
+/// Links from a plain text list, an M3U or extended M3U playlist or a PLS playlist. Paths in
+/// M3U and PLS files are taken relative to `base`, the link the playlist came from.
+pub fn parse(text: &str, base: Option<&str>) -> Vec<PlaylistEntry> {
+    let mut entries = if is_pls(text) {
+        parse_pls(text, base)
+    } else {
+        parse_m3u(text, base)
+    };
+    entries.truncate(MAX_ENTRIES);
+    entries
+}
+
+fn parse_duration(seconds: &str) -> Option<Duration> {
+    seconds.trim().parse::<u64>().ok().map(Duration::from_secs)
+}
+
+fn parse_title(title: &str) -> Option<String> {
+    Some(title.trim().to_string()).filter(|title| !title.is_empty())
+}
+
+fn link(line: &str, base: Option<&str>) -> Option<String> {
+    if url_utils::is_url(line) {
+        return Some(line.to_string());
+    }
+    match base {
+        Some(base) if !line.is_empty() && !line.contains(char::is_whitespace) => {
+            Some(radio_meta::resolve_uri(base, line))
+        }
+        _ => None,
+    }
+}
+
+/// `#EXTINF` titles are kept. Outside an extended M3U any other line that isn't a link is
+/// skipped, so prose around the links doesn't matter.
+fn parse_m3u(text: &str, base: Option<&str>) -> Vec<PlaylistEntry> {
+    let extended = text.trim_start().starts_with("#EXTM3U");
+    let base = base.filter(|_| extended);
+    let mut entries = Vec::new();
+    let mut info: Option<(Option<Duration>, Option<String>)> = None;
+    for line in text.lines().map(str::trim) {
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            let (seconds, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+            info = Some((parse_duration(seconds), parse_title(title)));
+        } else if line.starts_with('#') {
+            continue;
+        } else if let Some(url) = link(line, base) {
+            let (duration, title) = info.take().unwrap_or_default();
+            entries.push(PlaylistEntry {
+                url,
+                title,
+                duration,
+            });
+        }
+    }
+    entries
+}
+
+/// `FileN`, `TitleN` and `LengthN` keys, in the order of their numbers.
+fn parse_pls(text: &str, base: Option<&str>) -> Vec<PlaylistEntry> {
+    let mut files: BTreeMap<u32, PlaylistEntry> = BTreeMap::new();
+    for line in text.lines().map(str::trim) {
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+            None => continue,
+        };
+        let field = ["file", "title", "length"]
+            .into_iter()
+            .find_map(|field| Some((field, key.strip_prefix(field)?.parse::<u32>().ok()?)));
+        let (field, number) = match field {
+            Some(field) => field,
+            None => continue,
+        };
+        let file = files.entry(number).or_insert_with(|| PlaylistEntry {
+            url: String::new(),
+            title: None,
+            duration: None,
+        });
+        match field {
+            "file" => file.url = link(value, base).unwrap_or_default(),
+            "title" => file.title = parse_title(value),
+            _ => file.duration = parse_duration(value),
+        }
+    }
+    files
+        .into_values()
+        .filter(|file| !file.url.is_empty())
+        .collect()
+}
+
+/// Downloads and parses the playlist at `url`.
+pub async fn fetch(
+    fetcher: &dyn Fetcher,
+    url: &str,
+) -> Result<Vec<PlaylistEntry>, Box<dyn Error + Send + Sync + 'static>> {
+    let text = fetcher.fetch_text(url).await?;
+    Ok(parse(&text, Some(url)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(url: &str, title: Option<&str>, secs: Option<u64>) -> PlaylistEntry {
+        PlaylistEntry {
+            url: url.to_string(),
+            title: title.map(str::to_string),
+            duration: secs.map(Duration::from_secs),
+        }
+    }
+
+    #[test]
+    fn text_and_m3u_round_trip() {
+        let entries = vec![
+            entry(
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+                Some("Rick Astley - Never Gonna Give You Up"),
+                Some(212),
+            ),
+            entry("https://example.com/music/song.mp3", None, None),
+        ];
+
+        let m3u = to_m3u(&entries);
+        assert_eq!(
+            m3u,
+            "#EXTM3U\n#EXTINF:212,Rick Astley - Never Gonna Give You Up\nhttps://www.youtube.com/watch?v=dQw4w9WgXcQ\n#EXTINF:-1,song.mp3\nhttps://example.com/music/song.mp3\n"
+        );
+        let parsed = parse(&m3u, None);
+        assert_eq!(parsed[0], entries[0]);
+        assert_eq!(
+            parsed[1],
+            entry("https://example.com/music/song.mp3", Some("song.mp3"), None)
+        );
+
+        let text = to_text(&entries);
+        assert_eq!(
+            parse(
+                &format!("# my mix\n\n{}not a link\n", text),
+                Some("https://example.com/mix.txt")
+            ),
+            vec![
+                entry("https://www.youtube.com/watch?v=dQw4w9WgXcQ", None, None),
+                entry("https://example.com/music/song.mp3", None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn pls_round_trip() {
+        let pls = "[playlist]\r\nNumberOfEntries=3\r\nFile2=http://backup.example.com:8000/live\r\nFile1=http://stream.example.com/live.aac\r\nTitle1=Kiss FM\r\nLength1=-1\r\nFile3=mobile.aac\r\nTitle4=No file\r\nVersion=2\r\n";
+
+        let entries = parse(pls, Some("https://example.com/stations/kiss.pls"));
+        assert_eq!(
+            entries,
+            vec![
+                entry("http://stream.example.com/live.aac", Some("Kiss FM"), None),
+                entry("http://backup.example.com:8000/live", None, None),
+                entry("https://example.com/stations/mobile.aac", None, None),
+            ]
+        );
+
+        let written = to_pls(&entries[..1]);
+        assert_eq!(
+            written,
+            "[playlist]\nFile1=http://stream.example.com/live.aac\nTitle1=Kiss FM\nLength1=-1\nNumberOfEntries=1\nVersion=2\n"
+        );
+        assert_eq!(parse(&written, None), entries[..1]);
+    }
+
+    #[test]
+    fn extended_m3u_paths_and_hls() {
+        let m3u = "#EXTM3U\n#EXTINF:-1,Radio ZU\n/radiozu/live.mp3\nsecond.mp3\n";
+        assert_eq!(
+            parse(m3u, Some("http://radio.example.com/lists/zu.m3u")),
+            vec![
+                entry(
+                    "http://radio.example.com/radiozu/live.mp3",
+                    Some("Radio ZU"),
+                    None
+                ),
+                entry("http://radio.example.com/lists/second.mp3", None, None),
+            ]
+        );
+
+        assert!(is_hls(
+            "#EXTM3U\n#EXT-X-VERSION:3\n#EXTINF:10,\nsegment1.aac\n"
+        ));
+        assert!(!is_hls(m3u));
+        assert!(is_playlist_file("Kiss.PLS"));
+        assert!(is_playlist_file("mix.m3u"));
+        assert!(!is_playlist_file("song.mp3"));
+    }
+}
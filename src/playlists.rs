@@ -0,0 +1,185 @@
+use crate::{media_item_type::MediaItemType, track::Track, url_utils};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use twilight_model::id::{marker::UserMarker, Id};
+
+/// The most entries a playlist keeps.
+pub const MAX_ENTRIES: usize = 500;
+
+/// A named list of links saved in a guild, owned by the user who made it or, when
+/// `owner` is `None`, shared by the whole guild.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Playlist {
+    pub name: String,
+    pub owner: Option<Id<UserMarker>>,
+    pub entries: Vec<PlaylistEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlaylistEntry {
+    pub url: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub duration: Option<Duration>,
+}
+
+impl PlaylistEntry {
+    /// Local files and live streams have nothing to come back to later.
+    pub fn from_track(track: &Track) -> Option<Self> {
+        if !url_utils::is_url(&track.source_url) || track.kind == MediaItemType::Radio {
+            return None;
+        }
+        Some(PlaylistEntry {
+            url: track.source_url.clone(),
+            title: Some(track.display_title()),
+            duration: track.duration,
+        })
+    }
+
+    pub fn to_track(&self, playlist: &str, requester_id: Id<UserMarker>, requester: &str) -> Track {
+        Track {
+            title: self.title.clone(),
+            duration: self.duration,
+            ..Track::requested_by(
+                MediaItemType::PlaylistEntry {
+                    playlist: playlist.to_string(),
+                },
+                &self.url,
+                requester_id,
+                requester,
+            )
+        }
+    }
+
+    pub fn display_title(&self) -> String {
+        match &self.title {
+            Some(title) => title.clone(),
+            None => url_utils::display_name(&self.url),
+        }
+    }
+}
+
+/// A playlist as named in a command: `name` for the author's own, `server:name` for the
+/// guild's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaylistName {
+    pub name: String,
+    pub shared: bool,
+}
+
+impl PlaylistName {
+    pub fn parse(text: &str) -> Option<Self> {
+        let (name, shared) = match text.split_once(':') {
+            Some((scope, name)) if scope.eq_ignore_ascii_case("server") => (name, true),
+            Some((scope, name)) if scope.eq_ignore_ascii_case("guild") => (name, true),
+            Some(_) => return None,
+            None => (text, false),
+        };
+        let valid = !name.is_empty()
+            && name.len() <= 32
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+        valid.then(|| PlaylistName {
+            name: name.to_lowercase(),
+            shared,
+        })
+    }
+
+    /// The owner a new playlist by this name gets when `user_id` makes it.
+    pub fn owner(&self, user_id: Id<UserMarker>) -> Option<Id<UserMarker>> {
+        if self.shared {
+            None
+        } else {
+            Some(user_id)
+        }
+    }
+
+    /// The playlist `user_id` means: their own, or the guild's with `server:` or when
+    /// they have none by that name.
+    pub fn find<'a>(
+        &self,
+        playlists: &'a [Playlist],
+        user_id: Id<UserMarker>,
+    ) -> Option<&'a Playlist> {
+        let owned_by = |owner: Option<Id<UserMarker>>| {
+            playlists
+                .iter()
+                .find(|playlist| playlist.name == self.name && playlist.owner == owner)
+        };
+        if self.shared {
+            owned_by(None)
+        } else {
+            owned_by(Some(user_id)).or_else(|| owned_by(None))
+        }
+    }
+
+    /// Like `find`, but only the playlist `user_id` could change.
+    pub fn find_mut<'a>(
+        &self,
+        playlists: &'a mut [Playlist],
+        user_id: Id<UserMarker>,
+    ) -> Option<&'a mut Playlist> {
+        let owner = self.owner(user_id);
+        playlists
+            .iter_mut()
+            .find(|playlist| playlist.name == self.name && playlist.owner == owner)
+    }
+}
+
+impl Playlist {
+    /// `server:name` for shared playlists, `name` otherwise.
+    pub fn label(&self) -> String {
+        match self.owner {
+            Some(_) => self.name.clone(),
+            None => format!("server:{}", self.name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_and_owners() {
+        let user = Id::new(745_000_000_000_000_003);
+        let other = Id::new(745_000_000_000_000_004);
+        let playlist = |name: &str, owner| Playlist {
+            name: name.to_string(),
+            owner,
+            entries: Vec::new(),
+        };
+        let playlists = vec![
+            playlist("chill", Some(other)),
+            playlist("chill", None),
+            playlist("gym", Some(user)),
+        ];
+
+        let chill = PlaylistName::parse("Chill").unwrap();
+        assert_eq!(
+            chill.find(&playlists, user).unwrap().label(),
+            "server:chill"
+        );
+        assert_eq!(chill.find(&playlists, other).unwrap().owner, Some(other));
+        let gym = PlaylistName::parse("gym").unwrap();
+        assert!(gym.find(&playlists, other).is_none());
+        assert!(PlaylistName::parse("server:gym")
+            .unwrap()
+            .find(&playlists, user)
+            .is_none());
+
+        let mut playlists = playlists;
+        // The guild's playlist can be read by everyone but only changed as `server:`.
+        assert!(chill.find_mut(&mut playlists, user).is_none());
+        assert!(PlaylistName::parse("guild:chill")
+            .unwrap()
+            .find_mut(&mut playlists, user)
+            .is_some());
+
+        for invalid in ["", "two words", "a/b", "dj:mix", "server:"] {
+            assert_eq!(PlaylistName::parse(invalid), None, "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use crate::metadata_cache::Metadata;
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Debug,
+    time::{Duration, SystemTime},
+};
+
+/// Links without an `expire` parameter are resolved again after this long.
+pub const MAX_AGE: Duration = Duration::from_secs(3600);
+/// A link this close to expiring is resolved again rather than risked.
+pub const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct YtDlpOutput {
+    url: Option<String>,
+    http_headers: HashMap<String, String>,
+    title: Option<String>,
+    artist: Option<String>,
+    uploader: Option<String>,
+    duration: Option<f64>,
+    thumbnail: Option<String>,
+}
+
+/// The stream yt-dlp found behind a queued link, ready to be requested.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedStream {
+    pub source_url: String,
+    pub stream_url: String,
+    pub headers: Vec<(String, String)>,
+    pub metadata: Metadata,
+    pub resolved_at: SystemTime,
+}
+
+impl ResolvedStream {
+    /// From the JSON `yt-dlp -j` prints for `source_url`.
+    pub fn parse(
+        source_url: &str,
+        json: &str,
+        resolved_at: SystemTime,
+    ) -> Result<Self, Box<dyn Error + Send + Sync + 'static>> {
+        let output: YtDlpOutput = serde_json::from_str(json)?;
+        let stream_url = output.url.ok_or("yt-dlp found no stream")?;
+        let mut headers: Vec<(String, String)> = output.http_headers.into_iter().collect();
+        headers.sort();
+        Ok(ResolvedStream {
+            source_url: source_url.to_string(),
+            stream_url,
+            headers,
+            metadata: Metadata {
+                title: output.title,
+                author: output.artist.or(output.uploader),
+                duration: output
+                    .duration
+                    .filter(|secs| secs.is_finite() && *secs > 0.0)
+                    .map(Duration::from_secs_f64),
+                thumbnail: output.thumbnail,
+                description: None,
+                loudness_db: None,
+            },
+            resolved_at,
+        })
+    }
+
+    /// When the stream link stops working: its `expire` parameter (YouTube's signed links
+    /// have one), otherwise `MAX_AGE` after it was resolved.
+    pub fn expires_at(&self) -> SystemTime {
+        let query = self
+            .stream_url
+            .split_once('?')
+            .map(|(_, query)| query)
+            .unwrap_or_default();
+        query
+            .split('&')
+            .find_map(|param| param.strip_prefix("expire="))
+            .and_then(|secs| secs.parse().ok())
+            .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+            .unwrap_or(self.resolved_at + MAX_AGE)
+    }
+
+    pub fn is_fresh(&self, now: SystemTime) -> bool {
+        now + EXPIRY_MARGIN < self.expires_at()
+    }
+
+    /// The headers yt-dlp says the stream needs, leaving out any that aren't valid.
+    pub fn header_map(&self) -> HeaderMap {
+        self.headers
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((
+                    HeaderName::from_bytes(name.as_bytes()).ok()?,
+                    HeaderValue::from_str(value).ok()?,
+                ))
+            })
+            .collect()
+    }
+
+    /// The `-headers` option for ffmpeg to request the stream with, valid headers only.
+    pub fn ffmpeg_args(&self) -> Vec<String> {
+        if self.headers.is_empty() {
+            return Vec::new();
+        }
+        let headers = self
+            .headers
+            .iter()
+            .filter(|(name, _)| HeaderName::from_bytes(name.as_bytes()).is_ok())
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect();
+        vec!["-headers".to_string(), headers]
+    }
+}
+
+/// Finds the stream and details behind a link, so tracks can be looked up and prefetched
+/// without yt-dlp in tests.
+#[async_trait]
+pub trait StreamResolver: Debug + Send + Sync {
+    async fn resolve(
+        &self,
+        source_url: &str,
+    ) -> Result<ResolvedStream, Box<dyn Error + Send + Sync + 'static>>;
+}
+
+/// Asks yt-dlp, with the same format choice songbird makes for `YoutubeDl`.
+#[derive(Debug, Default)]
+pub struct YtDlp;
+
+#[async_trait]
+impl StreamResolver for YtDlp {
+    async fn resolve(
+        &self,
+        source_url: &str,
+    ) -> Result<ResolvedStream, Box<dyn Error + Send + Sync + 'static>> {
+        let output = tokio::process::Command::new("yt-dlp")
+            .args([
+                "-j",
+                "--no-playlist",
+                "-f",
+                "ba[abr>0][vcodec=none]/best",
+                source_url,
+            ])
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(format!(
+                "yt-dlp failed for {}: {}",
+                source_url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        let json = String::from_utf8_lossy(&output.stdout);
+        let first = json.lines().next().unwrap_or_default();
+        ResolvedStream::parse(source_url, first, SystemTime::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WATCH_URL: &str = "https://www.youtube.com/watch?v=dQw4w9WgXcQ";
+
+    #[test]
+    fn parses_yt_dlp_output() {
+        let json = r#"{"id":"dQw4w9WgXcQ","title":"Never Gonna Give You Up","uploader":"Rick Astley","duration":212.0,"thumbnail":"https://i.ytimg.com/vi/dQw4w9WgXcQ/maxresdefault.jpg","url":"https://rr1.googlevideo.com/videoplayback?expire=1700003600&itag=251","http_headers":{"User-Agent":"Mozilla/5.0","Accept":"*/*","Bad Header":"x"}}"#;
+        let resolved_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        let stream = ResolvedStream::parse(WATCH_URL, json, resolved_at).unwrap();
+
+        assert_eq!(stream.metadata.author.as_deref(), Some("Rick Astley"));
+        assert_eq!(stream.metadata.duration, Some(Duration::from_secs(212)));
+        assert!(stream.metadata.is_resolved());
+        assert_eq!(stream.header_map().len(), 2);
+        assert_eq!(
+            stream.ffmpeg_args()[1],
+            "Accept: */*\r\nUser-Agent: Mozilla/5.0\r\n"
+        );
+        assert_eq!(stream.expires_at(), resolved_at + Duration::from_secs(3600));
+        assert!(stream.is_fresh(resolved_at + Duration::from_secs(3500)));
+        assert!(!stream.is_fresh(resolved_at + Duration::from_secs(3550)));
+
+        assert!(ResolvedStream::parse(WATCH_URL, r#"{"title":"x"}"#, resolved_at).is_err());
+    }
+
+    #[test]
+    fn links_without_expiry_get_max_age() {
+        let resolved_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let stream = ResolvedStream::parse(
+            "https://example.com/song.mp3",
+            r#"{"url":"https://example.com/song.mp3","duration":null}"#,
+            resolved_at,
+        )
+        .unwrap();
+
+        assert_eq!(stream.expires_at(), resolved_at + MAX_AGE);
+        assert_eq!(stream.metadata.duration, None);
+        assert!(stream.headers.is_empty());
+        assert!(stream.ffmpeg_args().is_empty());
+    }
+}
@@ -0,0 +1,34 @@
+use async_trait::async_trait;
+use std::{error::Error, fmt::Debug};
+use twilight_gateway::Cluster;
+use twilight_model::gateway::{
+    payload::outgoing::UpdatePresence,
+    presence::{Activity, ActivityType, MinimalActivity, Status},
+};
+
+/// The bot's "Listening to" status, behind a trait so it can be recorded offline.
+#[async_trait]
+pub trait Presence: Debug + Send + Sync {
+    async fn set_listening(&self, name: &str)
+        -> Result<(), Box<dyn Error + Send + Sync + 'static>>;
+}
+
+#[async_trait]
+impl Presence for Cluster {
+    async fn set_listening(
+        &self,
+        name: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        let activity = Activity::from(MinimalActivity {
+            kind: ActivityType::Listening,
+            name: name.chars().take(128).collect(),
+            url: None,
+        });
+        let request = UpdatePresence::new(Vec::from([activity]), false, None, Status::Online)?;
+
+        for shard in self.shards() {
+            shard.command(&request).await?;
+        }
+        Ok(())
+    }
+}
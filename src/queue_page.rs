@@ -0,0 +1,119 @@
+use crate::{media_item_type::MediaItemType, msg_utils, track::Track};
+use std::time::Duration;
+
+/// Queued tracks shown on one page of `!list`.
+pub const PAGE_SIZE: usize = 10;
+
+pub fn page_count(queued: usize) -> usize {
+    queued.div_ceil(PAGE_SIZE).max(1)
+}
+
+fn line(track: &Track) -> String {
+    let mut line = track.display_title();
+    if let Some(duration) = track.duration.filter(|_| !track.is_live()) {
+        line.push_str(&format!(" ({})", msg_utils::format_duration(duration)));
+    }
+    if track.kind == MediaItemType::Autoplay {
+        line.push_str(" *(autoplay)*");
+    } else {
+        line.push_str(&format!(" - {}", track.requester));
+    }
+    line
+}
+
+/// What's playing, or paused, then page `page` (from 0) of the queue, numbered from 1
+/// like `!list` always was.
+pub fn describe(now_playing: Option<&Track>, paused: bool, queue: &[Track], page: usize) -> String {
+    let mut description = String::new();
+    if let Some(track) = now_playing {
+        let label = if paused { "Paused" } else { "Now playing" };
+        description.push_str(&format!("**{}:** {}\n\n", label, line(track)));
+    }
+    if queue.is_empty() {
+        description.push_str("Nothing queued");
+        return description;
+    }
+    let start = page.min(page_count(queue.len()) - 1) * PAGE_SIZE;
+    for (index, track) in queue.iter().enumerate().skip(start).take(PAGE_SIZE) {
+        description.push_str(&format!("`{}.` {}\n", index + 1, line(track)));
+    }
+    description.trim_end().to_string()
+}
+
+/// `Page 1/3 · 24 tracks · 1H:32m:10s`, with a `+` when some lengths aren't known.
+pub fn footer(queue: &[Track], page: usize) -> String {
+    let known: Duration = queue
+        .iter()
+        .filter(|track| !track.is_live())
+        .filter_map(|track| track.duration)
+        .sum();
+    let unknown = queue.iter().any(|track| track.is_live());
+    format!(
+        "Page {}/{} · {} tracks · {}{}",
+        page + 1,
+        page_count(queue.len()),
+        queue.len(),
+        msg_utils::format_duration(known),
+        if unknown { "+" } else { "" }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use twilight_model::id::Id;
+
+    fn track(title: &str, secs: Option<u64>) -> Track {
+        Track {
+            title: Some(title.to_string()),
+            duration: secs.map(Duration::from_secs),
+            ..Track::requested_by(
+                MediaItemType::Youtube,
+                "https://youtu.be/dQw4w9WgXcQ",
+                Id::new(745_000_000_000_000_003),
+                "tester",
+            )
+        }
+    }
+
+    #[test]
+    fn pages_of_ten() {
+        let queue: Vec<Track> = (1..=23)
+            .map(|number| track(&format!("Song {}", number), Some(60)))
+            .collect();
+        let playing = track("Playing", Some(212));
+
+        let first = describe(Some(&playing), false, &queue, 0);
+        assert!(first.starts_with("**Now playing:** Playing (0H:3m:32s) - tester\n\n`1.` Song 1"));
+        assert_eq!(first.lines().count(), 12);
+        assert!(describe(Some(&playing), true, &queue, 0).starts_with("**Paused:** Playing"));
+        let last = describe(None, false, &queue, 2);
+        assert_eq!(
+            last,
+            "`21.` Song 21 (0H:1m:0s) - tester\n`22.` Song 22 (0H:1m:0s) - tester\n`23.` Song 23 (0H:1m:0s) - tester"
+        );
+        assert_eq!(describe(None, false, &queue, 9), last);
+        assert_eq!(footer(&queue, 2), "Page 3/3 · 23 tracks · 0H:23m:0s");
+    }
+
+    #[test]
+    fn live_and_autoplay_tracks() {
+        let queue = vec![
+            track("Song", Some(90)),
+            track("Stream", None),
+            Track {
+                kind: MediaItemType::Autoplay,
+                ..track("Related", Some(30))
+            },
+        ];
+        assert_eq!(
+            describe(None, false, &queue, 0),
+            "`1.` Song (0H:1m:30s) - tester\n`2.` Stream - tester\n`3.` Related (0H:0m:30s) *(autoplay)*"
+        );
+        assert_eq!(footer(&queue, 0), "Page 1/1 · 3 tracks · 0H:2m:0s+");
+        assert_eq!(describe(None, false, &[], 0), "Nothing queued");
+        assert_eq!(page_count(0), 1);
+        assert_eq!(page_count(10), 1);
+        assert_eq!(page_count(11), 2);
+    }
+}
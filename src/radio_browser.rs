@@ -0,0 +1,335 @@
+use crate::{
+    fetcher::Fetcher,
+    stations::{self, Station},
+};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::{cmp::Reverse, env, error::Error, fmt::Debug, fs, path::Path, sync::Arc};
+
+const RESULTS: usize = 10;
+
+/// A station as listed by the radio-browser.info `json/stations` endpoints. Only the
+/// fields the bot uses are kept.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RadioBrowserStation {
+    pub stationuuid: String,
+    pub name: String,
+    pub url: String,
+    pub url_resolved: String,
+    pub favicon: String,
+    pub tags: String,
+    pub country: String,
+    pub countrycode: String,
+    pub codec: String,
+    pub bitrate: u32,
+    pub lastcheckok: u8,
+    pub clickcount: u32,
+}
+
+impl RadioBrowserStation {
+    pub fn stream_url(&self) -> &str {
+        if self.url_resolved.is_empty() {
+            &self.url
+        } else {
+            &self.url_resolved
+        }
+    }
+
+    /// `Romania · MP3 128kbps · house, dance`
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.country.is_empty() {
+            parts.push(self.country.clone());
+        }
+        match (self.codec.is_empty(), self.bitrate) {
+            (false, 0) => parts.push(self.codec.clone()),
+            (false, bitrate) => parts.push(format!("{} {}kbps", self.codec, bitrate)),
+            _ => {}
+        }
+        if !self.tags.is_empty() {
+            parts.push(self.tags.split(',').take(4).collect::<Vec<_>>().join(", "));
+        }
+        parts.join(" · ")
+    }
+
+    /// A guild station for this result, with a command no other station in `taken` uses.
+    pub fn to_station(&self, taken: &[Station]) -> Station {
+        let name = self.name.trim().to_string();
+        Station {
+            command: stations::command_for(&name, taken),
+            description: format!("Play {}", name),
+            urls: vec![self.stream_url().to_string()],
+            image: if url_is_image(&self.favicon) {
+                self.favicon.clone()
+            } else {
+                stations::RADIO_IMAGE.to_string()
+            },
+            input_args: Vec::new(),
+            name,
+        }
+    }
+}
+
+fn url_is_image(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+/// What `!radiosearch` looks for: `tag:house`, `genre:house`, `country:romania` or `country:ro`,
+/// and a station name otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RadioQuery {
+    Name(String),
+    Tag(String),
+    Country(String),
+    CountryCode(String),
+}
+
+impl RadioQuery {
+    pub fn parse(args: &str) -> Option<Self> {
+        let args = args.trim();
+        let (kind, value) = match args.split_once(':') {
+            Some((kind, value)) => (kind.trim().to_lowercase(), value.trim()),
+            None => (String::new(), args),
+        };
+        if value.is_empty() {
+            return None;
+        }
+
+        Some(match kind.as_str() {
+            "tag" | "genre" => RadioQuery::Tag(value.to_lowercase()),
+            "country" if value.len() == 2 => RadioQuery::CountryCode(value.to_uppercase()),
+            "country" => RadioQuery::Country(value.to_string()),
+            _ => RadioQuery::Name(args.to_string()),
+        })
+    }
+
+    fn matches(&self, station: &RadioBrowserStation) -> bool {
+        match self {
+            RadioQuery::Name(name) => station.name.to_lowercase().contains(&name.to_lowercase()),
+            RadioQuery::Tag(tag) => station
+                .tags
+                .split(',')
+                .any(|station_tag| station_tag.trim().eq_ignore_ascii_case(tag)),
+            RadioQuery::Country(country) => station.country.eq_ignore_ascii_case(country),
+            RadioQuery::CountryCode(code) => station.countrycode.eq_ignore_ascii_case(code),
+        }
+    }
+}
+
+/// Where `!radiosearch` finds stations.
+#[async_trait]
+pub trait RadioDirectory: Debug + Send + Sync {
+    async fn search(
+        &self,
+        query: &RadioQuery,
+    ) -> Result<Vec<RadioBrowserStation>, Box<dyn Error + Send + Sync + 'static>>;
+}
+
+/// The public radio-browser.info API, or any server speaking its schema.
+#[derive(Debug)]
+pub struct RadioBrowserApi {
+    fetcher: Arc<dyn Fetcher>,
+    base_url: String,
+}
+
+impl RadioBrowserApi {
+    pub fn new(fetcher: Arc<dyn Fetcher>, base_url: &str) -> Self {
+        RadioBrowserApi {
+            fetcher,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub fn search_url(
+        &self,
+        query: &RadioQuery,
+    ) -> Result<String, Box<dyn Error + Send + Sync + 'static>> {
+        let (field, value) = match query {
+            RadioQuery::Name(name) => ("name", name),
+            RadioQuery::Tag(tag) => ("tagList", tag),
+            RadioQuery::Country(country) => ("country", country),
+            RadioQuery::CountryCode(code) => ("countrycode", code),
+        };
+        let limit = RESULTS.to_string();
+        let url = reqwest::Url::parse_with_params(
+            &format!("{}/json/stations/search", self.base_url),
+            &[
+                (field, value.as_str()),
+                ("hidebroken", "true"),
+                ("order", "clickcount"),
+                ("reverse", "true"),
+                ("limit", limit.as_str()),
+            ],
+        )?;
+        Ok(url.to_string())
+    }
+}
+
+#[async_trait]
+impl RadioDirectory for RadioBrowserApi {
+    async fn search(
+        &self,
+        query: &RadioQuery,
+    ) -> Result<Vec<RadioBrowserStation>, Box<dyn Error + Send + Sync + 'static>> {
+        let content = self.fetcher.fetch_text(&self.search_url(query)?).await?;
+        let stations: Vec<RadioBrowserStation> = serde_json::from_str(&content)?;
+        Ok(stations.into_iter().take(RESULTS).collect())
+    }
+}
+
+/// A saved `json/stations` dump, searched offline.
+#[derive(Debug, Default)]
+pub struct RadioBrowserDump {
+    stations: Vec<RadioBrowserStation>,
+}
+
+impl RadioBrowserDump {
+    pub fn new(stations: Vec<RadioBrowserStation>) -> Self {
+        RadioBrowserDump { stations }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error + Send + Sync + 'static>> {
+        Ok(Self::new(serde_json::from_slice(&fs::read(path)?)?))
+    }
+}
+
+#[async_trait]
+impl RadioDirectory for RadioBrowserDump {
+    async fn search(
+        &self,
+        query: &RadioQuery,
+    ) -> Result<Vec<RadioBrowserStation>, Box<dyn Error + Send + Sync + 'static>> {
+        let mut found: Vec<RadioBrowserStation> = self
+            .stations
+            .iter()
+            .filter(|station| station.lastcheckok != 0 && query.matches(station))
+            .cloned()
+            .collect();
+        found.sort_by_key(|station| Reverse(station.clickcount));
+        found.truncate(RESULTS);
+        Ok(found)
+    }
+}
+
+/// `AURRAS_RADIO_BROWSER_DUMP` when set, otherwise the API at `AURRAS_RADIO_BROWSER_URL`
+/// (a public radio-browser.info mirror by default).
+pub fn directory_from_env(
+    fetcher: Arc<dyn Fetcher>,
+) -> Result<Arc<dyn RadioDirectory>, Box<dyn Error + Send + Sync + 'static>> {
+    if let Some(path) = env::var_os("AURRAS_RADIO_BROWSER_DUMP") {
+        return Ok(Arc::new(RadioBrowserDump::load(Path::new(&path))?));
+    }
+    let base_url = env::var("AURRAS_RADIO_BROWSER_URL")
+        .unwrap_or_else(|_| "https://de1.api.radio-browser.info".to_string());
+    Ok(Arc::new(RadioBrowserApi::new(fetcher, &base_url)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{stations::builtin_stations, test_harness::FixtureFetcher};
+
+    const SEARCH_RESULTS: &str = include_str!("../tests/fixtures/radio_browser_search.json");
+
+    #[test]
+    fn parses_queries() {
+        assert_eq!(
+            RadioQuery::parse(" jazz fm "),
+            Some(RadioQuery::Name("jazz fm".to_string()))
+        );
+        assert_eq!(
+            RadioQuery::parse("genre: Deep House"),
+            Some(RadioQuery::Tag("deep house".to_string()))
+        );
+        assert_eq!(
+            RadioQuery::parse("country:ro"),
+            Some(RadioQuery::CountryCode("RO".to_string()))
+        );
+        assert_eq!(
+            RadioQuery::parse("country:Romania"),
+            Some(RadioQuery::Country("Romania".to_string()))
+        );
+        assert_eq!(
+            RadioQuery::parse("Radio 1: Live"),
+            Some(RadioQuery::Name("Radio 1: Live".to_string()))
+        );
+        assert_eq!(RadioQuery::parse("tag:"), None);
+        assert_eq!(RadioQuery::parse(""), None);
+    }
+
+    #[tokio::test]
+    async fn searches_the_api() {
+        let fetcher = Arc::new(FixtureFetcher::default().page(
+            "https://radio.example.com/json/stations/search",
+            SEARCH_RESULTS,
+        ));
+        let api = RadioBrowserApi::new(fetcher.clone(), "https://radio.example.com/");
+
+        let found = api
+            .search(&RadioQuery::Tag("house".to_string()))
+            .await
+            .unwrap();
+
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0].name, "Deep House Radio");
+        assert_eq!(
+            found[0].stream_url(),
+            "https://deephouseradio.radioca.st/deep"
+        );
+        assert_eq!(
+            found[0].summary(),
+            "The United States Of America · MP3 128kbps · deep house, house"
+        );
+        assert_eq!(
+            fetcher.requests(),
+            vec!["https://radio.example.com/json/stations/search?tagList=house&hidebroken=true&order=clickcount&reverse=true&limit=10"]
+        );
+    }
+
+    #[tokio::test]
+    async fn searches_a_dump() {
+        let stations: Vec<RadioBrowserStation> = serde_json::from_str(SEARCH_RESULTS).unwrap();
+        let dump = RadioBrowserDump::new(stations);
+
+        let names = |found: Vec<RadioBrowserStation>| -> Vec<String> {
+            found
+                .iter()
+                .map(|station| station.name.trim().to_string())
+                .collect()
+        };
+        assert_eq!(
+            names(
+                dump.search(&RadioQuery::CountryCode("RO".to_string()))
+                    .await
+                    .unwrap()
+            ),
+            vec!["Kiss FM Romania"]
+        );
+        assert_eq!(
+            names(
+                dump.search(&RadioQuery::Tag("house".to_string()))
+                    .await
+                    .unwrap()
+            ),
+            vec!["Deep House Radio", "Kiss FM Romania"]
+        );
+        // Broken stations are left out.
+        assert!(dump
+            .search(&RadioQuery::Name("offline".to_string()))
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn saved_as_guild_station() {
+        let stations: Vec<RadioBrowserStation> = serde_json::from_str(SEARCH_RESULTS).unwrap();
+        let station = stations[1].to_station(&builtin_stations());
+
+        assert_eq!(station.command, "radiokissfmromania");
+        assert_eq!(station.name, "Kiss FM Romania");
+        assert_eq!(station.urls, vec!["http://live.kissfm.ro:9128/live.aac"]);
+        assert_eq!(station.image, stations::RADIO_IMAGE);
+    }
+}
@@ -0,0 +1,496 @@
+use crate::{
+    fetcher::Fetcher,
+    url_utils::{self, UrlKind},
+};
+use std::{error::Error, time::Duration};
+use tokio::sync::mpsc::Sender;
+
+/// Splits an Icecast/Shoutcast body into audio and the metadata blocks inserted every
+/// `icy-metaint` bytes, returning the `StreamTitle`s it finds.
+#[derive(Debug)]
+pub struct IcyParser {
+    metaint: usize,
+    state: IcyState,
+    metadata: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum IcyState {
+    Audio(usize),
+    Length,
+    Metadata(usize),
+}
+
+impl IcyParser {
+    pub fn new(metaint: usize) -> Option<Self> {
+        if metaint == 0 {
+            return None;
+        }
+        Some(IcyParser {
+            metaint,
+            state: IcyState::Audio(metaint),
+            metadata: Vec::new(),
+        })
+    }
+
+    pub fn feed(&mut self, mut data: &[u8]) -> Vec<String> {
+        let mut titles = Vec::new();
+
+        while !data.is_empty() {
+            match self.state {
+                IcyState::Audio(left) => {
+                    let n = left.min(data.len());
+                    data = &data[n..];
+                    self.state = if n == left {
+                        IcyState::Length
+                    } else {
+                        IcyState::Audio(left - n)
+                    };
+                }
+                IcyState::Length => {
+                    let len = data[0] as usize * 16;
+                    data = &data[1..];
+                    self.metadata.clear();
+                    self.state = if len == 0 {
+                        IcyState::Audio(self.metaint)
+                    } else {
+                        IcyState::Metadata(len)
+                    };
+                }
+                IcyState::Metadata(left) => {
+                    let n = left.min(data.len());
+                    self.metadata.extend_from_slice(&data[..n]);
+                    data = &data[n..];
+                    if n == left {
+                        if let Some(title) = parse_icy_metadata(&self.metadata) {
+                            titles.push(title);
+                        }
+                        self.state = IcyState::Audio(self.metaint);
+                    } else {
+                        self.state = IcyState::Metadata(left - n);
+                    }
+                }
+            }
+        }
+        titles
+    }
+}
+
+/// `StreamTitle='Artist - Song';StreamUrl='';` -> `Artist - Song`
+pub fn parse_icy_metadata(block: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(block);
+    let text = text.trim_end_matches('\0');
+    let start = text.find("StreamTitle='")? + "StreamTitle='".len();
+    let rest = &text[start..];
+    // Titles may contain apostrophes, so only `';` or the final quote ends them.
+    let end = rest.find("';").or_else(|| rest.rfind('\''))?;
+    let title = rest[..end].trim();
+
+    if title.is_empty() {
+        None
+    } else {
+        Some(title.to_string())
+    }
+}
+
+/// Title from the first ID3v2 tag in `data` that has one: `TPE1 - TIT2`, or a `TXXX`
+/// frame describing the title. HLS radio puts these at the start of audio segments.
+pub fn parse_id3_title(data: &[u8]) -> Option<String> {
+    let mut offset = 0;
+    while let Some(found) = find(&data[offset..], b"ID3") {
+        let start = offset + found;
+        if let Some(title) = parse_id3_tag(&data[start..]) {
+            return Some(title);
+        }
+        offset = start + 3;
+    }
+    None
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |size, byte| (size << 7) | (*byte & 0x7f) as usize)
+}
+
+fn parse_id3_tag(tag: &[u8]) -> Option<String> {
+    if tag.len() < 10 || !tag.starts_with(b"ID3") {
+        return None;
+    }
+    let version = tag[3];
+    if !(3..=4).contains(&version) {
+        return None;
+    }
+    let size = syncsafe(&tag[6..10]);
+    let frames = tag.get(10..)?;
+    let mut frames = &frames[..size.min(frames.len())];
+
+    // Skip the extended header.
+    if tag[5] & 0x40 != 0 {
+        let header = frames.get(..4)?;
+        let len = if version == 4 {
+            syncsafe(header)
+        } else {
+            u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize + 4
+        };
+        frames = frames.get(len..)?;
+    }
+
+    let mut title = None;
+    let mut artist = None;
+    while frames.len() >= 10 && frames[0] != 0 {
+        let id = &frames[..4];
+        let len = if version == 4 {
+            syncsafe(&frames[4..8])
+        } else {
+            u32::from_be_bytes([frames[4], frames[5], frames[6], frames[7]]) as usize
+        };
+        let body = frames.get(10..10 + len)?;
+        frames = &frames[10 + len..];
+
+        let strings = decode_text(body);
+        match id {
+            b"TIT2" => title = strings.into_iter().next(),
+            b"TPE1" => artist = strings.into_iter().next(),
+            b"TXXX" => {
+                if let [description, value, ..] = strings.as_slice() {
+                    let description = description.to_lowercase();
+                    if title.is_none() && (description == "streamtitle" || description == "title") {
+                        title = Some(value.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    match (artist, title) {
+        (Some(artist), Some(title)) => Some(format!("{} - {}", artist, title)),
+        (_, title) => title,
+    }
+}
+
+/// The NUL separated strings of a text frame, in whichever encoding it declares.
+fn decode_text(body: &[u8]) -> Vec<String> {
+    let (encoding, text) = match body.split_first() {
+        Some((encoding, text)) => (*encoding, text),
+        None => return Vec::new(),
+    };
+    let text = match encoding {
+        0 => text.iter().map(|byte| *byte as char).collect(),
+        1 | 2 => {
+            let mut big_endian = encoding == 2;
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .filter_map(|pair| match pair {
+                    [0xfe, 0xff] => {
+                        big_endian = true;
+                        None
+                    }
+                    [0xff, 0xfe] => {
+                        big_endian = false;
+                        None
+                    }
+                    [a, b] if big_endian => Some(u16::from_be_bytes([*a, *b])),
+                    [a, b] => Some(u16::from_le_bytes([*a, *b])),
+                    _ => None,
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(text).to_string(),
+    };
+
+    text.split('\0')
+        .map(|part| part.trim().to_string())
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+/// Resolves `uri` from a playlist against the playlist's own URL.
+pub fn resolve_uri(base: &str, uri: &str) -> String {
+    if url_utils::is_url(uri) {
+        return uri.to_string();
+    }
+    let (scheme, rest) = base.split_once("://").unwrap_or(("https", base));
+    if uri.starts_with('/') {
+        let host = rest.split('/').next().unwrap_or_default();
+        return format!("{}://{}{}", scheme, host, uri);
+    }
+    let path = rest.split(['?', '#']).next().unwrap_or_default();
+    match path.rsplit_once('/') {
+        Some((dir, _)) => format!("{}://{}/{}", scheme, dir, uri),
+        None => format!("{}://{}/{}", scheme, path, uri),
+    }
+}
+
+fn playlist_uris(playlist: &str) -> impl Iterator<Item = &str> {
+    playlist
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// Title carried by the newest segment of an HLS stream, following a master playlist
+/// to its first variant.
+pub async fn hls_title(
+    fetcher: &dyn Fetcher,
+    url: &str,
+) -> Result<Option<String>, Box<dyn Error + Send + Sync + 'static>> {
+    let mut url = url.to_string();
+    let mut playlist = fetcher.fetch_text(&url).await?;
+    if playlist.contains("#EXT-X-STREAM-INF") {
+        let variant = playlist_uris(&playlist)
+            .next()
+            .ok_or("Empty HLS master playlist")?;
+        url = resolve_uri(&url, variant);
+        playlist = fetcher.fetch_text(&url).await?;
+    }
+
+    let segment = match playlist_uris(&playlist).last() {
+        Some(segment) => resolve_uri(&url, segment),
+        None => return Ok(None),
+    };
+    let data = fetcher.fetch_bytes(&segment).await?;
+    Ok(parse_id3_title(&data))
+}
+
+/// Sends every new song title of the stream at `url` until the stream ends or nobody
+/// listens anymore.
+pub async fn watch_titles(
+    fetcher: &dyn Fetcher,
+    url: &str,
+    titles: Sender<String>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    if url_utils::classify_url(url) == Some(UrlKind::Hls) {
+        watch_hls(fetcher, url, titles).await
+    } else {
+        watch_icy(url, titles).await
+    }
+}
+
+async fn watch_icy(
+    url: &str,
+    titles: Sender<String>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let mut response = reqwest::Client::new()
+        .get(url)
+        .header("Icy-MetaData", "1")
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let metaint = response
+        .headers()
+        .get("icy-metaint")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .ok_or("Stream has no ICY metadata")?;
+    let mut parser = IcyParser::new(metaint).ok_or("Stream has no ICY metadata")?;
+
+    let mut last = String::new();
+    while let Some(chunk) = response.chunk().await? {
+        for title in parser.feed(&chunk) {
+            if title != last {
+                last = title.clone();
+                if titles.send(title).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn watch_hls(
+    fetcher: &dyn Fetcher,
+    url: &str,
+    titles: Sender<String>,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let mut last = String::new();
+    loop {
+        if let Some(title) = hls_title(fetcher, url).await? {
+            if title != last {
+                last = title.clone();
+                if titles.send(title).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+        if titles.is_closed() {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_secs(10)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_harness::FixtureFetcher;
+    use proptest::prelude::*;
+
+    fn id3(version: u8, frames: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (id, content) in frames {
+            body.extend_from_slice(*id);
+            let len = content.len() as u32;
+            if version == 4 {
+                body.extend([
+                    (len >> 21) as u8 & 0x7f,
+                    (len >> 14) as u8 & 0x7f,
+                    (len >> 7) as u8 & 0x7f,
+                    len as u8 & 0x7f,
+                ]);
+            } else {
+                body.extend(len.to_be_bytes());
+            }
+            body.extend([0, 0]);
+            body.extend(content);
+        }
+        let len = body.len() as u32;
+        let mut tag = vec![b'I', b'D', b'3', version, 0, 0];
+        tag.extend([
+            (len >> 21) as u8 & 0x7f,
+            (len >> 14) as u8 & 0x7f,
+            (len >> 7) as u8 & 0x7f,
+            len as u8 & 0x7f,
+        ]);
+        tag.extend(body);
+        tag
+    }
+
+    fn text(encoding: u8, text: &str) -> Vec<u8> {
+        let mut frame = vec![encoding];
+        frame.extend(text.as_bytes());
+        frame
+    }
+
+    #[test]
+    fn icy_titles() {
+        assert_eq!(
+            parse_icy_metadata(
+                b"StreamTitle='Rick Astley - Never Gonna Give You Up';StreamUrl='';\0\0"
+            ),
+            Some("Rick Astley - Never Gonna Give You Up".to_string())
+        );
+        assert_eq!(
+            parse_icy_metadata(b"StreamTitle='Guns N' Roses - Don't Cry';"),
+            Some("Guns N' Roses - Don't Cry".to_string())
+        );
+        assert_eq!(parse_icy_metadata(b"StreamTitle='';"), None);
+        assert_eq!(parse_icy_metadata(b"StreamUrl='x';"), None);
+    }
+
+    #[test]
+    fn icy_blocks_across_chunks() {
+        let metadata = b"StreamTitle='Song A';\0\0\0\0\0\0\0\0\0\0\0";
+        assert_eq!(metadata.len(), 32);
+        let mut stream = vec![0u8; 8];
+        stream.push(2);
+        stream.extend(metadata);
+        stream.extend([1u8; 8]);
+        stream.push(0);
+        stream.extend([1u8; 8]);
+        stream.push(2);
+        stream.extend(
+            metadata
+                .iter()
+                .map(|byte| if *byte == b'A' { b'B' } else { *byte }),
+        );
+
+        let mut parser = IcyParser::new(8).unwrap();
+        let titles: Vec<String> = stream
+            .chunks(5)
+            .flat_map(|chunk| parser.feed(chunk))
+            .collect();
+        assert_eq!(titles, vec!["Song A", "Song B"]);
+        assert!(IcyParser::new(0).is_none());
+    }
+
+    #[test]
+    fn id3_titles() {
+        let tag = id3(
+            3,
+            &[
+                (b"TPE1", text(0, "Rick Astley")),
+                (b"TIT2", text(0, "Never Gonna Give You Up\0")),
+            ],
+        );
+        assert_eq!(
+            parse_id3_title(&tag),
+            Some("Rick Astley - Never Gonna Give You Up".to_string())
+        );
+
+        let mut utf16 = vec![1, 0xff, 0xfe];
+        utf16.extend("Ăsta e".encode_utf16().flat_map(u16::to_le_bytes));
+        let tag = id3(4, &[(b"PRIV", vec![0; 20]), (b"TIT2", utf16)]);
+        assert_eq!(parse_id3_title(&tag), Some("Ăsta e".to_string()));
+
+        let tag = id3(4, &[(b"TXXX", text(3, "StreamTitle\0DJ Set"))]);
+        let mut segment = vec![0xff, 0xf1, 0x50];
+        segment.extend(tag);
+        assert_eq!(parse_id3_title(&segment), Some("DJ Set".to_string()));
+
+        assert_eq!(parse_id3_title(&id3(4, &[(b"PRIV", vec![1; 8])])), None);
+        assert_eq!(parse_id3_title(b"ID3\x04\0\0\x7f\x7f\x7f\x7fTIT2"), None);
+    }
+
+    #[test]
+    fn playlist_uris_resolve() {
+        let base = "https://live4ro.antenaplay.ro/radiozu/radiozu-48000.m3u8?token=1";
+        assert_eq!(
+            resolve_uri(base, "seg_12.aac"),
+            "https://live4ro.antenaplay.ro/radiozu/seg_12.aac"
+        );
+        assert_eq!(
+            resolve_uri(base, "/other/seg.aac"),
+            "https://live4ro.antenaplay.ro/other/seg.aac"
+        );
+        assert_eq!(
+            resolve_uri(base, "https://cdn.example.com/seg.aac"),
+            "https://cdn.example.com/seg.aac"
+        );
+    }
+
+    #[tokio::test]
+    async fn hls_title_from_newest_segment() {
+        let master = "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=48000\nchunks.m3u8\n";
+        let media =
+            "#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXTINF:10,\nseg_1.aac\n#EXTINF:10,\nseg_2.aac\n";
+        let segment = String::from_utf8(id3(3, &[(b"TIT2", text(0, "Live from ZU"))])).unwrap();
+        let fetcher = FixtureFetcher::default()
+            .page("https://radio.example.com/zu/master.m3u8", master)
+            .page("https://radio.example.com/zu/chunks.m3u8", media)
+            .page("https://radio.example.com/zu/seg_2.aac", &segment);
+
+        let title = hls_title(&fetcher, "https://radio.example.com/zu/master.m3u8")
+            .await
+            .unwrap();
+
+        assert_eq!(title.as_deref(), Some("Live from ZU"));
+        assert_eq!(
+            fetcher.requests().last().map(String::as_str),
+            Some("https://radio.example.com/zu/seg_2.aac")
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn metadata_parsers_never_panic(data in proptest::collection::vec(any::<u8>(), 0..512), metaint in 1usize..64) {
+            let _ = parse_icy_metadata(&data);
+            let _ = parse_id3_title(&data);
+            let mut tagged = b"ID3\x04\0".to_vec();
+            tagged.extend(&data);
+            let _ = parse_id3_title(&tagged);
+            let mut parser = IcyParser::new(metaint).unwrap();
+            let _ = parser.feed(&data);
+        }
+    }
+}
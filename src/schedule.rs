@@ -0,0 +1,377 @@
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, time::Duration};
+use twilight_model::id::{
+    marker::{ChannelMarker, UserMarker},
+    Id,
+};
+
+/// Something a guild wants played at set times, saved with its other data.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub id: u32,
+    /// Five field cron expression, in the bot's local time.
+    pub cron: String,
+    pub voice_channel_id: Id<ChannelMarker>,
+    /// Where `!schedule` was used, and where the bot reports what it started.
+    pub text_channel_id: Id<ChannelMarker>,
+    pub target: ScheduleTarget,
+    #[serde(default)]
+    pub leave_after: Option<Duration>,
+    pub requester_id: Id<UserMarker>,
+    pub requester: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "name", rename_all = "lowercase")]
+pub enum ScheduleTarget {
+    /// A built-in or saved station, by command.
+    Station(String),
+    /// A playlist as the schedule's requester would name it.
+    Playlist(String),
+}
+
+impl Schedule {
+    pub fn spec(&self) -> Result<CronSpec, Box<dyn Error + Send + Sync + 'static>> {
+        CronSpec::parse(&self.cron)
+    }
+
+    /// `!radiozu` or `playlist server:chill`.
+    pub fn target_label(&self) -> String {
+        match &self.target {
+            ScheduleTarget::Station(command) => format!("!{}", command),
+            ScheduleTarget::Playlist(name) => format!("playlist {}", name),
+        }
+    }
+
+    /// `` `#2` `0 20 * * 1-5` !radiozu in <#1> for 2h ``
+    pub fn list_line(&self) -> String {
+        let mut line = format!(
+            "`#{}` `{}` {} in <#{}>",
+            self.id,
+            self.cron,
+            self.target_label(),
+            self.voice_channel_id
+        );
+        if let Some(leave_after) = self.leave_after {
+            line.push_str(&format!(" for {}", format_duration(leave_after)));
+        }
+        line
+    }
+}
+
+/// The minutes a cron expression (`minute hour day-of-month month day-of-week`) fires on.
+/// Fields take `*`, numbers, `a-b` ranges, `/step` and comma separated lists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSpec {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSpec {
+    pub fn parse(expression: &str) -> Result<Self, Box<dyn Error + Send + Sync + 'static>> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err("A schedule needs 5 fields: minute hour day month weekday".into());
+        }
+
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        // Both 0 and 7 are Sunday.
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(CronSpec {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2].starts_with('*'),
+            any_weekday: fields[4].starts_with('*'),
+        })
+    }
+
+    fn matches_day(&self, time: &NaiveDateTime) -> bool {
+        let day = self.days & (1 << time.day()) != 0;
+        let weekday = self.weekdays & (1 << time.weekday().num_days_from_sunday()) != 0;
+        // Like cron, a restricted day of month and day of week fire on either.
+        if self.any_day || self.any_weekday {
+            day && weekday
+        } else {
+            day || weekday
+        }
+    }
+
+    /// Whether the minute `time` falls in is one the schedule fires on.
+    pub fn matches(&self, time: &NaiveDateTime) -> bool {
+        self.months & (1 << time.month()) != 0
+            && self.matches_day(time)
+            && self.hours & (1 << time.hour()) != 0
+            && self.minutes & (1 << time.minute()) != 0
+    }
+
+    /// The first minute after `after` the schedule fires on, looking up to a few years ahead.
+    pub fn next_after(&self, after: &NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut date = after.date();
+        for _ in 0..(366 * 5) {
+            let midnight = date.and_hms_opt(0, 0, 0)?;
+            if self.months & (1 << date.month()) != 0 && self.matches_day(&midnight) {
+                for hour in (0..24).filter(|hour| self.hours & (1 << hour) != 0) {
+                    for minute in (0..60).filter(|minute| self.minutes & (1 << minute) != 0) {
+                        let time = date.and_hms_opt(hour, minute, 0)?;
+                        if time > *after {
+                            return Some(time);
+                        }
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+}
+
+fn parse_field(
+    field: &str,
+    min: u32,
+    max: u32,
+) -> Result<u64, Box<dyn Error + Send + Sync + 'static>> {
+    let invalid = || format!("`{}` isn't a valid schedule field ({}-{})", field, min, max);
+    let number = |text: &str| text.parse::<u32>().map_err(|_| invalid());
+
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(number(step)?)),
+            None => (part, None),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (number(start)?, number(end)?)
+        } else {
+            // `5/15` runs from 5 to the end of the range.
+            let start = number(range)?;
+            (start, if step.is_some() { max } else { start })
+        };
+
+        let step = step.unwrap_or(1);
+        if step == 0 || start < min || end > max || start > end {
+            return Err(invalid().into());
+        }
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+/// What `!schedule <when> <channel> <station or playlist:name> [for]` was given, before the channel and
+/// station are looked up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleRequest {
+    pub cron: String,
+    pub channel: String,
+    pub target: String,
+    pub leave_after: Option<Duration>,
+}
+
+/// `<when>` is a cron expression or `HH:MM` for every day at that time.
+pub fn parse_request(
+    args: &str,
+) -> Result<ScheduleRequest, Box<dyn Error + Send + Sync + 'static>> {
+    let words: Vec<&str> = args.split_whitespace().collect();
+    let (cron, rest) = match words.first() {
+        Some(time) if time.contains(':') => {
+            let (hour, minute) = time.split_once(':').ok_or("Invalid time")?;
+            let hour: u32 = hour.parse().map_err(|_| "Invalid time, use HH:MM")?;
+            let minute: u32 = minute.parse().map_err(|_| "Invalid time, use HH:MM")?;
+            if hour > 23 || minute > 59 {
+                return Err("Invalid time, use HH:MM".into());
+            }
+            (format!("{} {} * * *", minute, hour), &words[1..])
+        }
+        _ if words.len() >= 5 => (words[..5].join(" "), &words[5..]),
+        _ => return Err("A schedule needs a time like 20:00 or a cron expression".into()),
+    };
+    CronSpec::parse(&cron)?;
+
+    match rest {
+        [channel, target] => Ok(ScheduleRequest {
+            cron,
+            channel: channel.to_string(),
+            target: target.to_string(),
+            leave_after: None,
+        }),
+        [channel, target, leave_after] => Ok(ScheduleRequest {
+            cron,
+            channel: channel.to_string(),
+            target: target.to_string(),
+            leave_after: Some(parse_duration(leave_after)?),
+        }),
+        _ => Err("A schedule needs a voice channel and a station".into()),
+    }
+}
+
+/// `90m`, `2h`, `1h30m`; a bare number is minutes.
+pub fn parse_duration(text: &str) -> Result<Duration, Box<dyn Error + Send + Sync + 'static>> {
+    let invalid = || format!("`{}` isn't a duration like 90m or 2h", text);
+    let mut minutes = 0u64;
+    let mut number = String::new();
+    for c in text.trim().to_lowercase().chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'h' | 'm' => {
+                let value: u64 = number.parse().map_err(|_| invalid())?;
+                minutes += if c == 'h' { value * 60 } else { value };
+                number.clear();
+            }
+            _ => return Err(invalid().into()),
+        }
+    }
+    if !number.is_empty() {
+        minutes += number.parse::<u64>().map_err(|_| invalid())?;
+    }
+    if minutes == 0 || minutes > 24 * 60 {
+        return Err(invalid().into());
+    }
+    Ok(Duration::from_secs(minutes * 60))
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{}m", minutes),
+        (hours, 0) => format!("{}h", hours),
+        (hours, minutes) => format!("{}h{}m", hours, minutes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        // 2024-07-01 is a Monday.
+        NaiveDate::from_ymd_opt(2024, 7, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn cron_fields() {
+        let weekday_evenings = CronSpec::parse("0 20 * * 1-5").unwrap();
+        assert!(weekday_evenings.matches(&at(1, 20, 0)));
+        assert!(!weekday_evenings.matches(&at(1, 20, 1)));
+        assert!(!weekday_evenings.matches(&at(6, 20, 0)));
+
+        let quarters = CronSpec::parse("*/15 8-9 * * *").unwrap();
+        assert!(quarters.matches(&at(3, 9, 45)));
+        assert!(!quarters.matches(&at(3, 10, 0)));
+
+        let sundays = CronSpec::parse("30 12 * * 7").unwrap();
+        assert!(sundays.matches(&at(7, 12, 30)));
+
+        // Day of month or day of week when both are restricted.
+        let either = CronSpec::parse("0 0 13 * 5").unwrap();
+        assert!(either.matches(&at(5, 0, 0)));
+        assert!(either.matches(&at(13, 0, 0)));
+        assert!(!either.matches(&at(14, 0, 0)));
+
+        for invalid in [
+            "",
+            "0 20 * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(CronSpec::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn next_run() {
+        let spec = CronSpec::parse("0 20 * * 1-5").unwrap();
+        assert_eq!(spec.next_after(&at(1, 19, 59)), Some(at(1, 20, 0)));
+        assert_eq!(spec.next_after(&at(1, 20, 0)), Some(at(2, 20, 0)));
+        assert_eq!(spec.next_after(&at(5, 21, 0)), Some(at(8, 20, 0)));
+        assert_eq!(
+            CronSpec::parse("0 0 31 2 *")
+                .unwrap()
+                .next_after(&at(1, 0, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn schedule_requests() {
+        assert_eq!(
+            parse_request("20:30 <#745000000000000002> radiozu 2h").unwrap(),
+            ScheduleRequest {
+                cron: "30 20 * * *".to_string(),
+                channel: "<#745000000000000002>".to_string(),
+                target: "radiozu".to_string(),
+                leave_after: Some(Duration::from_secs(2 * 60 * 60)),
+            }
+        );
+        let weekly = parse_request("0 8 * * 1 lounge !radiohouse").unwrap();
+        assert_eq!(weekly.cron, "0 8 * * 1");
+        assert_eq!(weekly.channel, "lounge");
+        assert_eq!(weekly.leave_after, None);
+
+        assert!(parse_request("25:00 lounge radiozu").is_err());
+        assert!(parse_request("20:00 lounge").is_err());
+        assert!(parse_request("0 8 * * lounge radiozu").is_err());
+        assert!(parse_request("20:00 lounge radiozu forever").is_err());
+    }
+
+    #[test]
+    fn list_lines() {
+        let mut schedule = Schedule {
+            id: 2,
+            cron: "0 20 * * 1-5".to_string(),
+            voice_channel_id: Id::new(745_000_000_000_000_002),
+            text_channel_id: Id::new(745_000_000_000_000_005),
+            target: ScheduleTarget::Station("radiozu".to_string()),
+            leave_after: Some(Duration::from_secs(2 * 60 * 60)),
+            requester_id: Id::new(745_000_000_000_000_003),
+            requester: "tester".to_string(),
+        };
+        assert_eq!(
+            schedule.list_line(),
+            "`#2` `0 20 * * 1-5` !radiozu in <#745000000000000002> for 2h"
+        );
+
+        schedule.target = ScheduleTarget::Playlist("server:chill".to_string());
+        schedule.leave_after = None;
+        assert_eq!(
+            schedule.list_line(),
+            "`#2` `0 20 * * 1-5` playlist server:chill in <#745000000000000002>"
+        );
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90m").unwrap(), Duration::from_secs(90 * 60));
+        assert_eq!(
+            parse_duration("1h30m").unwrap(),
+            Duration::from_secs(90 * 60)
+        );
+        assert_eq!(parse_duration("45").unwrap(), Duration::from_secs(45 * 60));
+        assert!(parse_duration("0m").is_err());
+        assert!(parse_duration("2d").is_err());
+        assert!(parse_duration("h").is_err());
+        assert_eq!(format_duration(Duration::from_secs(90 * 60)), "1h30m");
+        assert_eq!(format_duration(Duration::from_secs(2 * 60 * 60)), "2h");
+        assert_eq!(format_duration(Duration::from_secs(45 * 60)), "45m");
+    }
+}
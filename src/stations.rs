@@ -0,0 +1,495 @@
+use crate::{
+    fetcher::Fetcher,
+    playlist_file,
+    url_utils::{self, UrlKind},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, env, error::Error, time::Duration};
+
+pub const RADIO_IMAGE: &str =
+    "https://cdn2.vectorstock.com/i/1000x1000/01/16/radio-music-neon-logo-night-neon-vector-21420116.jpg";
+
+/// A radio station, built in or saved by a guild. `urls` are tried in order, later ones
+/// being fallbacks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Station {
+    pub command: String,
+    pub name: String,
+    pub description: String,
+    pub urls: Vec<String>,
+    pub image: String,
+    /// Extra ffmpeg options placed before `-i`.
+    #[serde(default)]
+    pub input_args: Vec<String>,
+}
+
+struct BuiltinStation {
+    command: &'static str,
+    name: &'static str,
+    description: &'static str,
+    urls: &'static [&'static str],
+    image: &'static str,
+    input_args: &'static [&'static str],
+}
+
+static BUILTIN_STATIONS: [BuiltinStation; 7] = [
+    BuiltinStation {
+        command: "radiozu",
+        name: "RadioZU Romania",
+        description: "Play Radio ZU",
+        urls: &["https://live4ro.antenaplay.ro/radiozu/radiozu-48000.m3u8"],
+        image: "https://static.tuneyou.com/images/logos/500_500/33/3133/RadioZU.jpg",
+        input_args: &[],
+    },
+    BuiltinStation {
+        command: "radiovirgin",
+        name: "Virgin Radio Romania",
+        description: "Play Virgin Radio Romania",
+        urls: &[
+            "https://astreaming.edi.ro:8443/VirginRadio_aac",
+            "http://astreaming.virginradio.ro:8000/virgin_aacp_64k",
+        ],
+        image: "https://virginradio.ro/wp-content/uploads/2019/06/VR_ROMANIA_WHITE-STAR-LOGO_RGB_ONLINE_1600x1600.png",
+        input_args: &[],
+    },
+    BuiltinStation {
+        command: "radio24house",
+        name: "Radio 24 House",
+        description: "Play Radio 24 House",
+        urls: &["https://24houseradio-adradio.radioca.st/128"],
+        image: RADIO_IMAGE,
+        input_args: &[],
+    },
+    BuiltinStation {
+        command: "radioclubbers",
+        name: "Radio Clubbers",
+        description: "Play Radio Clubbers",
+        urls: &["https://s3.slotex.pl/shoutcast/7300/stream?sid=1"],
+        image: RADIO_IMAGE,
+        input_args: &[],
+    },
+    BuiltinStation {
+        command: "radiouv",
+        name: "Radio Underground Vibe",
+        description: "Play Radio Undergound Vibe",
+        urls: &["https://stream-21.zeno.fm/s98kga59qnruv?zs=y9vZRej5RV69g4Ld8fD7QQ"],
+        image: RADIO_IMAGE,
+        input_args: &[],
+    },
+    BuiltinStation {
+        command: "radiodancefm",
+        name: "DanceFM.RO",
+        description: "Play Radio Dance FM Romania",
+        urls: &["https://edge126.rcs-rds.ro/profm/dancefm.mp3"],
+        image: RADIO_IMAGE,
+        input_args: &[],
+    },
+    BuiltinStation {
+        command: "radiohouse",
+        name: "Radio House Santa Monica",
+        description: "Play Radio House Santa Monica",
+        urls: &[
+            "https://deephouseradio.radioca.st/deep?type=http&nocache=16",
+            "https://deephouseradio.radioca.st/deep",
+        ],
+        image: RADIO_IMAGE,
+        input_args: &["-thread_queue_size", "3"],
+    },
+];
+
+impl From<&BuiltinStation> for Station {
+    fn from(station: &BuiltinStation) -> Self {
+        Station {
+            command: station.command.to_string(),
+            name: station.name.to_string(),
+            description: station.description.to_string(),
+            urls: station.urls.iter().map(|url| url.to_string()).collect(),
+            image: station.image.to_string(),
+            input_args: station
+                .input_args
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// The stations every guild has.
+pub fn builtin_stations() -> Vec<Station> {
+    BUILTIN_STATIONS.iter().map(Station::from).collect()
+}
+
+/// The station started by `!<command>` among `stations`.
+pub fn find_station(stations: &[Station], command: &str) -> Option<Station> {
+    let command = command.strip_prefix('!').unwrap_or(command);
+    stations
+        .iter()
+        .find(|station| station.command == command)
+        .cloned()
+}
+
+/// A free `!radio...` command for a station called `name`.
+pub fn command_for(name: &str, taken: &[Station]) -> String {
+    let slug: String = name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .take(24)
+        .collect();
+    let base = format!("radio{}", slug);
+    let is_free = |command: &str| {
+        !RESERVED_COMMANDS.contains(&command)
+            && !taken.iter().any(|station| station.command == command)
+    };
+
+    if is_free(&base) {
+        return base;
+    }
+    (2..)
+        .map(|n| format!("{}{}", base, n))
+        .find(|command| is_free(command))
+        .unwrap_or(base)
+}
+
+/// Bot commands a saved station must not shadow.
+const RESERVED_COMMANDS: [&str; 3] = ["radio", "radiolist", "radiosearch"];
+
+/// The command for a station a guild adds as `!radio add <alias> ...`: the alias itself
+/// when it already starts with `radio`, `radio<alias>` otherwise. `None` for aliases that
+/// aren't plain letters and digits or that would shadow a bot command.
+pub fn alias_command(alias: &str) -> Option<String> {
+    let alias = alias.strip_prefix('!').unwrap_or(alias).to_lowercase();
+    if alias.is_empty() || alias.len() > 32 || !alias.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let command = if alias.starts_with("radio") {
+        alias
+    } else {
+        format!("radio{}", alias)
+    };
+    Some(command).filter(|command| !RESERVED_COMMANDS.contains(&command.as_str()))
+}
+
+/// A station a guild added by hand.
+pub fn custom_station(
+    command: String,
+    alias: &str,
+    urls: Vec<String>,
+    logo: Option<&str>,
+) -> Station {
+    Station {
+        command,
+        name: alias.to_string(),
+        description: format!("Play {}", alias),
+        urls,
+        image: logo
+            .filter(|logo| url_utils::is_url(logo))
+            .unwrap_or(RADIO_IMAGE)
+            .to_string(),
+        input_args: Vec::new(),
+    }
+}
+
+/// Whether each stream URL answered the last health probe. URLs never probed are
+/// assumed to be up.
+pub type StationHealth = HashMap<String, bool>;
+
+impl Station {
+    /// Stream URLs with the ones known to be down moved to the end.
+    pub fn urls_by_health(&self, health: &StationHealth) -> Vec<String> {
+        let mut urls = self.urls.clone();
+        urls.sort_by_key(|url| !health.get(url).copied().unwrap_or(true));
+        urls
+    }
+
+    pub fn radiolist_line(&self, health: &StationHealth) -> String {
+        let up = self
+            .urls
+            .iter()
+            .filter(|url| health.get(*url).copied().unwrap_or(true))
+            .count();
+        let status = if up == 0 {
+            " - **offline**".to_string()
+        } else if up < self.urls.len() {
+            format!(" - {}/{} streams up", up, self.urls.len())
+        } else {
+            String::new()
+        };
+        format!("`{}` - {}{}", self.command, self.description, status)
+    }
+}
+
+/// A stream that played at least this long before ending counts as dropped rather than
+/// dead, and is reconnected before trying its fallbacks.
+pub const HEALTHY_PLAY: Duration = Duration::from_secs(60);
+
+/// How many times in a row a station is reconnected before giving up:
+/// `AURRAS_RADIO_RECONNECTS` or 5; zero counts as unset.
+pub fn reconnect_attempts() -> u32 {
+    env::var("AURRAS_RADIO_RECONNECTS")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .filter(|attempts| *attempts > 0)
+        .unwrap_or(5)
+}
+
+/// Seconds without data before a stream counts as stalled: `AURRAS_RADIO_STALL_SECS`
+/// or 15.
+pub fn stall_timeout() -> Duration {
+    env::var("AURRAS_RADIO_STALL_SECS")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(15))
+}
+
+/// 1s, 2s, 4s, ... capped at a minute.
+pub fn reconnect_delay(attempt: u32) -> Duration {
+    let secs = 1u64 << attempt.saturating_sub(1).min(6);
+    Duration::from_secs(secs.min(60))
+}
+
+/// Probes every stream of every station.
+pub async fn check_stations(fetcher: &dyn Fetcher, stations: &[Station]) -> StationHealth {
+    let mut health = StationHealth::new();
+    for station in stations {
+        for url in &station.urls {
+            let up = match fetcher.probe(url).await {
+                Ok(_) => true,
+                Err(why) => {
+                    println!("{} is down ({}): {}", station.name, url, why);
+                    false
+                }
+            };
+            health.insert(url.to_string(), up);
+        }
+    }
+    health
+}
+
+/// Makes sure `url` answers with a live audio stream before a guild saves it.
+pub async fn check_stream(
+    fetcher: &dyn Fetcher,
+    url: &str,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    if !url_utils::is_url(url) {
+        return Err("A station needs an http(s) stream link".into());
+    }
+    let probe = fetcher
+        .probe(url)
+        .await
+        .map_err(|why| format!("Couldn't reach that stream ({})", why))?;
+
+    match url_utils::classify_probe(url, &probe).or_else(|| url_utils::classify_url(url)) {
+        Some(UrlKind::LiveStream | UrlKind::Hls) => Ok(()),
+        Some(UrlKind::DirectFile) => Err("That link is an audio file, not a live stream".into()),
+        _ => Err(format!(
+            "That link doesn't look like a radio stream ({})",
+            probe.content_type.as_deref().unwrap_or("unknown content")
+        )
+        .into()),
+    }
+}
+
+/// The most streams kept from a station playlist.
+const MAX_STREAMS: usize = 8;
+
+/// The streams a station link stands for: the entries of a `.pls`/`.m3u` playlist that
+/// answer as live streams, in order, or the link itself.
+pub async fn station_streams(
+    fetcher: &dyn Fetcher,
+    url: &str,
+) -> Result<Vec<String>, Box<dyn Error + Send + Sync + 'static>> {
+    let kind = match url_utils::classify_url(url) {
+        Some(kind) => Some(kind),
+        None => match fetcher.probe(url).await {
+            Ok(probe) => url_utils::classify_probe(url, &probe),
+            Err(_) => None,
+        },
+    };
+    if kind != Some(UrlKind::Playlist) {
+        check_stream(fetcher, url).await?;
+        return Ok(vec![url.to_string()]);
+    }
+
+    let mut streams: Vec<String> = Vec::new();
+    let mut failure = None;
+    for entry in playlist_file::fetch(fetcher, url).await? {
+        if streams.len() == MAX_STREAMS || streams.contains(&entry.url) {
+            continue;
+        }
+        match check_stream(fetcher, &entry.url).await {
+            Ok(()) => streams.push(entry.url),
+            Err(why) => failure = Some(why),
+        }
+    }
+    match failure {
+        _ if !streams.is_empty() => Ok(streams),
+        Some(why) => Err(format!("none of the playlist's streams work ({})", why).into()),
+        None => Err("That playlist has no stream links".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fetcher::UrlProbe, test_harness::FixtureFetcher};
+
+    #[test]
+    fn finds_stations_by_command() {
+        let stations = builtin_stations();
+        assert_eq!(
+            find_station(&stations, "!radiozu").map(|s| s.name),
+            Some("RadioZU Romania".to_string())
+        );
+        assert_eq!(
+            find_station(&stations, "radiohouse").map(|s| s.input_args.len()),
+            Some(2)
+        );
+        assert!(find_station(&stations, "!radiolist").is_none());
+    }
+
+    #[test]
+    fn free_commands_for_saved_stations() {
+        let stations = builtin_stations();
+        assert_eq!(command_for("Jazz FM 91.1", &stations), "radiojazzfm911");
+        assert_eq!(command_for("House", &stations), "radiohouse2");
+        assert_eq!(command_for("List", &stations), "radiolist2");
+        assert_eq!(command_for("Ăăă", &stations), "radio2");
+    }
+
+    #[test]
+    fn aliases_for_added_stations() {
+        assert_eq!(alias_command("Jazz").as_deref(), Some("radiojazz"));
+        assert_eq!(alias_command("!radioJazz").as_deref(), Some("radiojazz"));
+        assert_eq!(alias_command("list"), None);
+        assert_eq!(alias_command("search"), None);
+        assert_eq!(alias_command("radio"), None);
+        assert_eq!(alias_command("jazz fm"), None);
+        assert_eq!(alias_command("jäzz"), None);
+        assert_eq!(alias_command(""), None);
+
+        let station = custom_station(
+            "radiojazz".to_string(),
+            "Jazz",
+            vec!["http://jazz".to_string()],
+            Some("logo"),
+        );
+        assert_eq!(station.image, RADIO_IMAGE);
+        assert_eq!(
+            station.radiolist_line(&StationHealth::new()),
+            "`radiojazz` - Play Jazz"
+        );
+    }
+
+    #[tokio::test]
+    async fn added_streams_are_probed() {
+        let audio = |content_type: &str, content_length: Option<u64>| UrlProbe {
+            content_type: Some(content_type.to_string()),
+            content_length,
+            icy: false,
+        };
+        let fetcher = FixtureFetcher::default()
+            .probe("http://live.example.com/", audio("audio/mpeg", None))
+            .probe(
+                "http://hls.example.com/",
+                audio("application/vnd.apple.mpegurl", None),
+            )
+            .probe(
+                "http://files.example.com/",
+                audio("audio/mpeg", Some(4_000_000)),
+            )
+            .probe("http://web.example.com/", audio("text/html", None));
+
+        assert!(check_stream(&fetcher, "http://live.example.com/stream")
+            .await
+            .is_ok());
+        assert!(check_stream(&fetcher, "http://hls.example.com/live.m3u8")
+            .await
+            .is_ok());
+        assert!(check_stream(&fetcher, "http://files.example.com/song.mp3")
+            .await
+            .is_err());
+        assert!(check_stream(&fetcher, "http://web.example.com/")
+            .await
+            .is_err());
+        assert!(check_stream(&fetcher, "http://down.example.com/")
+            .await
+            .is_err());
+        assert!(check_stream(&fetcher, "live.example.com").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn station_playlists_are_expanded() {
+        let live = UrlProbe {
+            content_type: Some("audio/aacp".to_string()),
+            content_length: None,
+            icy: true,
+        };
+        let fetcher = FixtureFetcher::default()
+            .page(
+                "http://radio.example.com/kiss.pls",
+                "[playlist]\nFile1=http://live.example.com/kiss\nFile2=http://down.example.com/kiss\nFile3=http://live.example.com/kiss\nNumberOfEntries=3\n",
+            )
+            .page("http://radio.example.com/empty.m3u", "#EXTM3U\n")
+            .probe("http://live.example.com/", live);
+
+        assert_eq!(
+            station_streams(&fetcher, "http://radio.example.com/kiss.pls")
+                .await
+                .unwrap(),
+            vec!["http://live.example.com/kiss"]
+        );
+        assert_eq!(
+            station_streams(&fetcher, "http://live.example.com/kiss")
+                .await
+                .unwrap(),
+            vec!["http://live.example.com/kiss"]
+        );
+        assert!(
+            station_streams(&fetcher, "http://radio.example.com/empty.m3u")
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn reconnect_backoff() {
+        let delays: Vec<u64> = (1..=8)
+            .map(|attempt| reconnect_delay(attempt).as_secs())
+            .collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(reconnect_delay(0), Duration::from_secs(1));
+        assert_eq!(reconnect_delay(u32::MAX), Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn dead_streams_are_reported_and_tried_last() {
+        let stations = builtin_stations();
+        let virgin = find_station(&stations, "radiovirgin").unwrap();
+        let radiozu = find_station(&stations, "radiozu").unwrap();
+        let fetcher = FixtureFetcher::default().probe(&virgin.urls[1], UrlProbe::default());
+
+        let health = check_stations(&fetcher, &stations).await;
+
+        assert_eq!(health.len(), 9);
+        assert_eq!(health.get(&virgin.urls[0]), Some(&false));
+        assert_eq!(
+            virgin.urls_by_health(&health),
+            vec![virgin.urls[1].clone(), virgin.urls[0].clone()]
+        );
+        assert_eq!(
+            virgin.radiolist_line(&health),
+            "`radiovirgin` - Play Virgin Radio Romania - 1/2 streams up"
+        );
+        assert_eq!(
+            radiozu.radiolist_line(&health),
+            "`radiozu` - Play Radio ZU - **offline**"
+        );
+        assert_eq!(
+            radiozu.radiolist_line(&StationHealth::new()),
+            "`radiozu` - Play Radio ZU"
+        );
+    }
+}
@@ -0,0 +1,1044 @@
+//! Offline stand-ins for Discord, voice and the web so handlers can be exercised
+//! without a bot token or network access.
+
+use crate::{
+    fade::FadeSettings,
+    fetcher::{Fetcher, UrlProbe},
+    guild_store::GuildStore,
+    handle_command,
+    lyrics::LocalLyrics,
+    messenger::Messenger,
+    metadata_cache::{Metadata, MetadataCache, DEFAULT_TTL},
+    prefetch::{ResolvedStream, StreamResolver},
+    presence::Presence,
+    radio_browser::RadioBrowserDump,
+    voice::{PlayingTrack, TrackEventHandler, Voice},
+    Queue1, State, StateInfo, StateRef,
+};
+use async_trait::async_trait;
+use songbird::{
+    tracks::{PlayMode, Track as AudioTrack, TrackState},
+    TrackEvent,
+};
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    fmt,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use tokio::sync::Mutex as AsyncMutex;
+use twilight_cache_inmemory::InMemoryCache;
+use twilight_model::{
+    channel::embed::Embed,
+    gateway::payload::incoming::{MessageCreate, VoiceStateUpdate},
+    id::{
+        marker::{ChannelMarker, GuildMarker, MessageMarker},
+        Id,
+    },
+    voice::VoiceState,
+};
+use twilight_standby::Standby;
+
+pub const SEARCH_PAGE: &str = include_str!("../tests/fixtures/youtube_search.html");
+pub const EMPTY_SEARCH_PAGE: &str = include_str!("../tests/fixtures/youtube_search_empty.html");
+pub const WATCH_PAGE: &str = include_str!("../tests/fixtures/youtube_watch.html");
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    Message(Id<ChannelMarker>, String),
+    Embeds(Id<ChannelMarker>, Vec<Embed>),
+    File(Id<ChannelMarker>, String, Vec<u8>),
+    Edit(Id<ChannelMarker>, Id<MessageMarker>, Embed),
+    Reaction(Id<ChannelMarker>, Id<MessageMarker>, String),
+}
+
+/// Captures every reply instead of sending it.
+#[derive(Debug, Default)]
+pub struct RecordingMessenger {
+    replies: Mutex<Vec<Reply>>,
+}
+
+impl RecordingMessenger {
+    pub fn replies(&self) -> Vec<Reply> {
+        self.replies.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Messenger for RecordingMessenger {
+    async fn send_message(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        content: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.replies
+            .lock()
+            .unwrap()
+            .push(Reply::Message(channel_id, content.to_string()));
+        Ok(())
+    }
+
+    async fn send_embeds(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        embeds: &[Embed],
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.replies
+            .lock()
+            .unwrap()
+            .push(Reply::Embeds(channel_id, embeds.to_vec()));
+        Ok(())
+    }
+
+    async fn send_file(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        filename: &str,
+        content: &[u8],
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.replies.lock().unwrap().push(Reply::File(
+            channel_id,
+            filename.to_string(),
+            content.to_vec(),
+        ));
+        Ok(())
+    }
+
+    async fn send_embed(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        embed: &Embed,
+    ) -> Result<Id<MessageMarker>, Box<dyn Error + Send + Sync + 'static>> {
+        let mut replies = self.replies.lock().unwrap();
+        replies.push(Reply::Embeds(channel_id, vec![embed.clone()]));
+        Ok(Id::new(replies.len() as u64))
+    }
+
+    async fn edit_embed(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        message_id: Id<MessageMarker>,
+        embed: &Embed,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.replies
+            .lock()
+            .unwrap()
+            .push(Reply::Edit(channel_id, message_id, embed.clone()));
+        Ok(())
+    }
+
+    async fn add_reaction(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        message_id: Id<MessageMarker>,
+        emoji: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.replies.lock().unwrap().push(Reply::Reaction(
+            channel_id,
+            message_id,
+            emoji.to_string(),
+        ));
+        Ok(())
+    }
+}
+
+/// Serves recorded pages and headers for any URL starting with a registered prefix.
+#[derive(Debug, Default)]
+pub struct FixtureFetcher {
+    pages: HashMap<String, String>,
+    probes: HashMap<String, UrlProbe>,
+    requests: Mutex<Vec<String>>,
+}
+
+impl FixtureFetcher {
+    pub fn page(mut self, url_prefix: &str, content: &str) -> Self {
+        self.pages
+            .insert(url_prefix.to_string(), content.to_string());
+        self
+    }
+
+    pub fn probe(mut self, url_prefix: &str, probe: UrlProbe) -> Self {
+        self.probes.insert(url_prefix.to_string(), probe);
+        self
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Fetcher for FixtureFetcher {
+    async fn fetch_text(
+        &self,
+        url: &str,
+    ) -> Result<String, Box<dyn Error + Send + Sync + 'static>> {
+        self.requests.lock().unwrap().push(url.to_string());
+        self.pages
+            .iter()
+            .find(|(prefix, _)| url.starts_with(prefix.as_str()))
+            .map(|(_, content)| content.clone())
+            .ok_or_else(|| format!("No fixture for {}", url).into())
+    }
+
+    async fn fetch_bytes(
+        &self,
+        url: &str,
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync + 'static>> {
+        Ok(self.fetch_text(url).await?.into_bytes())
+    }
+
+    async fn probe(&self, url: &str) -> Result<UrlProbe, Box<dyn Error + Send + Sync + 'static>> {
+        self.requests.lock().unwrap().push(url.to_string());
+        self.probes
+            .iter()
+            .find(|(prefix, _)| url.starts_with(prefix.as_str()))
+            .map(|(_, probe)| probe.clone())
+            .ok_or_else(|| format!("No fixture for {}", url).into())
+    }
+}
+
+/// Serves what yt-dlp would find behind each registered link.
+#[derive(Debug, Default)]
+pub struct FixtureStreams {
+    streams: Mutex<HashMap<String, ResolvedStream>>,
+}
+
+impl FixtureStreams {
+    pub fn insert(&self, stream: ResolvedStream) {
+        self.streams
+            .lock()
+            .unwrap()
+            .insert(stream.source_url.clone(), stream);
+    }
+}
+
+#[async_trait]
+impl StreamResolver for FixtureStreams {
+    async fn resolve(
+        &self,
+        source_url: &str,
+    ) -> Result<ResolvedStream, Box<dyn Error + Send + Sync + 'static>> {
+        self.streams
+            .lock()
+            .unwrap()
+            .get(source_url)
+            .cloned()
+            .ok_or_else(|| format!("No fixture for {}", source_url).into())
+    }
+}
+
+/// Plays nothing, but keeps every track it was asked to play so tests can end them.
+#[derive(Debug, Default)]
+pub struct FakeVoice {
+    calls: Mutex<HashMap<Id<GuildMarker>, Id<ChannelMarker>>>,
+    tracks: Mutex<Vec<Arc<FakeTrack>>>,
+}
+
+impl FakeVoice {
+    /// The voice channel joined in the guild.
+    pub fn channel(&self, guild_id: Id<GuildMarker>) -> Option<Id<ChannelMarker>> {
+        self.calls.lock().unwrap().get(&guild_id).copied()
+    }
+
+    /// Every track played so far, oldest first.
+    pub fn tracks(&self) -> Vec<Arc<FakeTrack>> {
+        self.tracks.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Voice for FakeVoice {
+    async fn join(
+        &self,
+        guild_id: Id<GuildMarker>,
+        channel_id: Id<ChannelMarker>,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.calls.lock().unwrap().insert(guild_id, channel_id);
+        Ok(())
+    }
+
+    async fn leave(
+        &self,
+        guild_id: Id<GuildMarker>,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.calls.lock().unwrap().remove(&guild_id);
+        Ok(())
+    }
+
+    fn is_connected(&self, guild_id: Id<GuildMarker>) -> bool {
+        self.calls.lock().unwrap().contains_key(&guild_id)
+    }
+
+    async fn play(
+        &self,
+        guild_id: Id<GuildMarker>,
+        _track: AudioTrack,
+    ) -> Option<Arc<dyn PlayingTrack>> {
+        if !self.is_connected(guild_id) {
+            return None;
+        }
+        let mut tracks = self.tracks.lock().unwrap();
+        let track = Arc::new(FakeTrack::new(tracks.len() as u128 + 1));
+        tracks.push(Arc::clone(&track));
+        Some(track)
+    }
+
+    async fn stop(&self, _guild_id: Id<GuildMarker>) {
+        for track in self.tracks() {
+            let _ = track.stop();
+        }
+    }
+}
+
+/// A track `FakeVoice` pretends to play until `finish` or `stop`.
+pub struct FakeTrack {
+    id: u128,
+    info: Mutex<TrackState>,
+    handlers: Mutex<Vec<(TrackEvent, Arc<dyn TrackEventHandler>)>>,
+}
+
+impl FakeTrack {
+    fn new(id: u128) -> Self {
+        FakeTrack {
+            id,
+            info: Mutex::default(),
+            handlers: Mutex::default(),
+        }
+    }
+
+    pub fn playing(&self) -> PlayMode {
+        self.info.lock().unwrap().playing.clone()
+    }
+
+    /// Plays the track to its end, running what waits for that like songbird would.
+    pub async fn finish(&self) {
+        self.info.lock().unwrap().playing = PlayMode::End;
+        let handlers: Vec<_> = self
+            .handlers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(event, _)| *event == TrackEvent::End)
+            .map(|(_, handler)| Arc::clone(handler))
+            .collect();
+        for handler in handlers {
+            handler.act(self.id).await;
+        }
+    }
+}
+
+impl fmt::Debug for FakeTrack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FakeTrack")
+            .field("id", &self.id)
+            .field("playing", &self.playing())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl PlayingTrack for FakeTrack {
+    fn id(&self) -> u128 {
+        self.id
+    }
+
+    async fn get_info(&self) -> Result<TrackState, Box<dyn Error + Send + Sync + 'static>> {
+        Ok(self.info.lock().unwrap().clone())
+    }
+
+    fn play(&self) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.info.lock().unwrap().playing = PlayMode::Play;
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.info.lock().unwrap().playing = PlayMode::Pause;
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.info.lock().unwrap().playing = PlayMode::Stop;
+        Ok(())
+    }
+
+    fn set_volume(&self, volume: f32) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.info.lock().unwrap().volume = volume;
+        Ok(())
+    }
+
+    fn add_event(
+        &self,
+        event: TrackEvent,
+        handler: Arc<dyn TrackEventHandler>,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.handlers.lock().unwrap().push((event, handler));
+        Ok(())
+    }
+}
+
+/// Keeps every "Listening to" status instead of sending it.
+#[derive(Debug, Default)]
+pub struct RecordingPresence {
+    names: Mutex<Vec<String>>,
+}
+
+impl RecordingPresence {
+    pub fn names(&self) -> Vec<String> {
+        self.names.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Presence for RecordingPresence {
+    async fn set_listening(
+        &self,
+        name: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.names.lock().unwrap().push(name.to_string());
+        Ok(())
+    }
+}
+
+pub const GUILD_ID: u64 = 745_000_000_000_000_001;
+pub const CHANNEL_ID: u64 = 745_000_000_000_000_002;
+pub const AUTHOR_ID: u64 = 745_000_000_000_000_003;
+pub const VOICE_CHANNEL_ID: u64 = 745_000_000_000_000_004;
+
+/// A `MessageCreate` as the gateway would deliver it for a guild text channel.
+pub fn message_create(content: &str) -> MessageCreate {
+    serde_json::from_value(serde_json::json!({
+        "id": "1055000000000000001",
+        "channel_id": CHANNEL_ID.to_string(),
+        "guild_id": GUILD_ID.to_string(),
+        "author": {
+            "id": AUTHOR_ID.to_string(),
+            "username": "tester",
+            "discriminator": "0001",
+            "avatar": null,
+            "bot": false
+        },
+        "content": content,
+        "timestamp": "2022-12-20T12:00:00.000000+00:00",
+        "edited_timestamp": null,
+        "tts": false,
+        "mention_everyone": false,
+        "mentions": [],
+        "mention_roles": [],
+        "attachments": [],
+        "embeds": [],
+        "pinned": false,
+        "type": 0
+    }))
+    .unwrap()
+}
+
+/// The bot with the stand-ins above in place of Discord, songbird and the web, driven
+/// through the same dispatcher as gateway messages.
+pub struct Harness {
+    pub state: State,
+    pub state_info: Arc<AsyncMutex<StateInfo>>,
+    pub queue: Arc<AsyncMutex<Queue1>>,
+    pub messenger: Arc<RecordingMessenger>,
+    pub streams: Arc<FixtureStreams>,
+    pub voice: Arc<FakeVoice>,
+    pub presence: Arc<RecordingPresence>,
+    dir: PathBuf,
+}
+
+impl Harness {
+    /// `name` keeps the guild files of tests running side by side apart.
+    pub async fn new(name: &str, fetcher: FixtureFetcher) -> Self {
+        let dir = env::temp_dir().join(format!("aurras-harness-{}-{}", name, std::process::id()));
+        let messenger = Arc::new(RecordingMessenger::default());
+        let streams = Arc::new(FixtureStreams::default());
+        let voice = Arc::new(FakeVoice::default());
+        let presence = Arc::new(RecordingPresence::default());
+        let state = Arc::new(StateRef {
+            messenger: Arc::clone(&messenger) as Arc<dyn Messenger>,
+            fetcher: Arc::new(fetcher),
+            streams: Arc::clone(&streams) as Arc<dyn StreamResolver>,
+            trackdata: Default::default(),
+            station_health: Default::default(),
+            guild_store: GuildStore::new(dir.join("guilds")),
+            music_dir: dir.join("music"),
+            radio_directory: Arc::new(RadioBrowserDump::new(Vec::new())),
+            lyrics: Arc::new(LocalLyrics::new(dir.join("lyrics"))),
+            metadata: MetadataCache::new(None, 10, DEFAULT_TTL),
+            voice: Arc::clone(&voice) as Arc<dyn Voice>,
+            presence: Arc::clone(&presence) as Arc<dyn Presence>,
+            standby: Standby::new(),
+            cache: InMemoryCache::new(),
+        });
+        // No fades, so stopping and skipping don't wait on them.
+        state
+            .guild_store
+            .update(Id::new(GUILD_ID), |data| {
+                data.fade = FadeSettings {
+                    fade_out: Duration::ZERO,
+                    crossfade: Duration::ZERO,
+                }
+            })
+            .await
+            .unwrap();
+
+        Harness {
+            state,
+            state_info: Arc::new(AsyncMutex::new(StateInfo::default())),
+            queue: Arc::new(AsyncMutex::new(Queue1 {
+                queue: Vec::new(),
+                prefetched: None,
+            })),
+            messenger,
+            streams,
+            voice,
+            presence,
+            dir,
+        }
+    }
+
+    /// Sends `content` as the tester and waits for its handler.
+    pub async fn run(&self, content: &str) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        handle_command(
+            message_create(content).0,
+            Arc::clone(&self.state),
+            Arc::clone(&self.state_info),
+            Arc::clone(&self.queue),
+        )
+        .await
+    }
+
+    /// Puts the tester in a voice channel, as the gateway would report it.
+    pub fn enter_voice_channel(&self) {
+        let voice_state: VoiceState = serde_json::from_value(serde_json::json!({
+            "channel_id": VOICE_CHANNEL_ID.to_string(),
+            "deaf": false,
+            "guild_id": GUILD_ID.to_string(),
+            "member": null,
+            "mute": false,
+            "self_deaf": false,
+            "self_mute": false,
+            "self_stream": false,
+            "self_video": false,
+            "session_id": "a5e8f2b8d2f84a0c9e8b6f2b0c2d4e6f",
+            "suppress": false,
+            "user_id": AUTHOR_ID.to_string(),
+            "request_to_speak_timestamp": null
+        }))
+        .unwrap();
+        self.state.cache.update(&VoiceStateUpdate(voice_state));
+    }
+
+    /// What yt-dlp would find behind `url`, so playing it doesn't need yt-dlp.
+    pub fn resolved(&self, url: &str, author: &str, title: &str, secs: u64) {
+        self.streams.insert(ResolvedStream {
+            source_url: url.to_string(),
+            stream_url: format!("https://rr1.googlevideo.com/videoplayback?source={}", url),
+            headers: Vec::new(),
+            metadata: Metadata {
+                title: Some(title.to_string()),
+                author: Some(author.to_string()),
+                duration: Some(Duration::from_secs(secs)),
+                ..Metadata::default()
+            },
+            resolved_at: SystemTime::now(),
+        });
+    }
+
+    /// The `count`th track played, once it's the guild's current one. Tracks started in
+    /// the background, like the next one in the queue, take a moment.
+    pub async fn playing(&self, count: usize) -> Arc<FakeTrack> {
+        for _ in 0..500 {
+            let tracks = self.voice.tracks();
+            if let Some(track) = tracks.get(count - 1) {
+                let current = self
+                    .state
+                    .trackdata
+                    .read()
+                    .await
+                    .get(&Id::new(GUILD_ID))
+                    .map(|current| current.id());
+                if current == Some(track.id) {
+                    return Arc::clone(track);
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!(
+            "track {} never started, played {:?}",
+            count,
+            self.voice.tracks()
+        );
+    }
+
+    pub async fn now_playing(&self) -> Option<String> {
+        self.state_info
+            .lock()
+            .await
+            .now_playing
+            .as_ref()
+            .map(|track| track.display_title())
+    }
+
+    pub async fn queued(&self) -> Vec<String> {
+        self.queue
+            .lock()
+            .await
+            .queue
+            .iter()
+            .map(|track| track.display_title())
+            .collect()
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        filters::Filter,
+        media_item_type::MediaItemType,
+        msg_utils, resolve_link, send_description, send_text_file,
+        track::Track,
+        url_utils::{self, UrlKind},
+    };
+
+    const SEARCH_URL: &str = "https://www.youtube.com/results?search_query=";
+    const WATCH_URL: &str = "https://www.youtube.com/watch?v=dQw4w9WgXcQ";
+    const NEXT_URL: &str = "https://www.youtube.com/watch?v=djV11Xbc914";
+
+    /// In the tester's voice channel, with both links known without yt-dlp.
+    async fn harness(name: &str) -> Harness {
+        let harness = Harness::new(name, FixtureFetcher::default()).await;
+        harness.enter_voice_channel();
+        harness.resolved(WATCH_URL, "Rick Astley", "Never Gonna Give You Up", 213);
+        harness.resolved(NEXT_URL, "a-ha", "a-ha - Take On Me", 225);
+        harness
+    }
+
+    fn message(content: &str) -> Reply {
+        Reply::Message(Id::new(CHANNEL_ID), content.to_string())
+    }
+
+    #[tokio::test]
+    async fn play_joins_the_voice_channel_and_starts_the_link() {
+        let h = harness("play").await;
+
+        h.run(&format!("!play {}", WATCH_URL)).await.unwrap();
+
+        let guild_id = Id::new(GUILD_ID);
+        assert_eq!(h.voice.channel(guild_id), Some(Id::new(VOICE_CHANNEL_ID)));
+        assert!(matches!(h.playing(1).await.playing(), PlayMode::Play));
+        assert_eq!(
+            h.now_playing().await.as_deref(),
+            Some("Rick Astley - Never Gonna Give You Up")
+        );
+        assert_eq!(
+            h.messenger.replies(),
+            vec![
+                message(&format!("Joined <#{}>!", VOICE_CHANNEL_ID)),
+                message("Playing **\"Never Gonna Give You Up\"**"),
+            ]
+        );
+        assert_eq!(h.presence.names(), vec!["Never Gonna Give You Up"]);
+    }
+
+    #[tokio::test]
+    async fn added_tracks_are_listed_and_play_next() {
+        let h = harness("add").await;
+
+        h.run(&format!("!play {}", WATCH_URL)).await.unwrap();
+        h.run(&format!("!add {}", NEXT_URL)).await.unwrap();
+        h.run("!list").await.unwrap();
+
+        assert_eq!(h.queued().await, vec!["a-ha - Take On Me"]);
+        let replies = h.messenger.replies();
+        assert_eq!(replies[2], message("**\"a-ha - Take On Me\"** added !"));
+        match &replies[3..] {
+            [Reply::Embeds(_, embeds)] => {
+                let description = embeds[0].description.as_deref().unwrap();
+                assert!(description
+                    .starts_with("**Now playing:** Rick Astley - Never Gonna Give You Up"));
+                assert!(description.contains("`1.` a-ha - Take On Me"));
+            }
+            replies => panic!("unexpected replies {:?}", replies),
+        }
+
+        h.playing(1).await.finish().await;
+        h.playing(2).await;
+        assert_eq!(h.now_playing().await.as_deref(), Some("a-ha - Take On Me"));
+        assert!(h.queued().await.is_empty());
+        assert_eq!(
+            h.messenger.replies().last(),
+            Some(&message("Playing **\"a-ha - Take On Me\"**"))
+        );
+    }
+
+    #[tokio::test]
+    async fn added_tracks_are_prefetched_while_another_plays() {
+        let h = harness("prefetch").await;
+
+        h.run(&format!("!play {}", WATCH_URL)).await.unwrap();
+        h.playing(1).await;
+        h.run(&format!("!add {}", NEXT_URL)).await.unwrap();
+
+        for _ in 0..500 {
+            if h.queue.lock().await.prefetched.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let prefetched = h.queue.lock().await.prefetched.clone().unwrap();
+        assert_eq!(prefetched.source_url, NEXT_URL);
+    }
+
+    #[tokio::test]
+    async fn added_tracks_play_after_local_files() {
+        let h = harness("local").await;
+        std::fs::create_dir_all(&h.state.music_dir).unwrap();
+        std::fs::write(h.state.music_dir.join("Dreams.mp3"), b"").unwrap();
+
+        h.run("!local dreams").await.unwrap();
+        h.run(&format!("!add {}", NEXT_URL)).await.unwrap();
+        assert_eq!(h.now_playing().await.as_deref(), Some("Dreams.mp3"));
+
+        h.playing(1).await.finish().await;
+        h.playing(2).await;
+        assert_eq!(h.now_playing().await.as_deref(), Some("a-ha - Take On Me"));
+        assert!(h.queued().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn queued_playlist_files_play_without_yt_dlp() {
+        let playlist = "https://example.com/mix.m3u";
+        let song = "https://example.com/music/dreams.mp3";
+        let fetcher = FixtureFetcher::default()
+            .page(
+                playlist,
+                &format!(
+                    "#EXTM3U\n#EXTINF:213,Never Gonna Give You Up\n{}\n#EXTINF:180,Dreams\n{}\n",
+                    WATCH_URL, song
+                ),
+            )
+            .probe(
+                song,
+                UrlProbe {
+                    content_type: Some("audio/mpeg".to_string()),
+                    content_length: Some(4_320_000),
+                    icy: false,
+                },
+            );
+        let h = Harness::new("playlist", fetcher).await;
+        h.enter_voice_channel();
+        h.resolved(WATCH_URL, "Rick Astley", "Never Gonna Give You Up", 213);
+
+        h.run(&format!("!play {}", playlist)).await.unwrap();
+        assert_eq!(h.queued().await, vec!["Dreams"]);
+        h.playing(1).await.finish().await;
+        h.playing(2).await;
+
+        assert_eq!(h.now_playing().await.as_deref(), Some("Dreams"));
+        match h.messenger.replies().last() {
+            Some(Reply::Embeds(_, embeds)) => {
+                assert_eq!(embeds[0].title.as_deref(), Some("Dreams"));
+                assert!(embeds[0]
+                    .fields
+                    .iter()
+                    .any(|field| field.name == "Type" && field.value == "File"));
+            }
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+    }
+
+    #[tokio::test]
+    async fn added_tracks_play_after_a_restart_with_new_details() {
+        let h = harness("restart").await;
+
+        h.run(&format!("!play {}", WATCH_URL)).await.unwrap();
+        h.run(&format!("!add {}", NEXT_URL)).await.unwrap();
+        h.playing(1).await;
+        // Switched on behind the bot's back, so the track started without ffmpeg.
+        h.state
+            .guild_store
+            .update(Id::new(GUILD_ID), |data| data.filters = vec![Filter::Bass])
+            .await
+            .unwrap();
+        let thumbnail = "https://i.ytimg.com/vi/dQw4w9WgXcQ/hqdefault.jpg";
+        let details = Metadata {
+            thumbnail: Some(thumbnail.to_string()),
+            ..Metadata::default()
+        };
+        h.state.metadata.insert(WATCH_URL, details).await;
+        // Back to no filters, so the restart doesn't need yt-dlp for ffmpeg.
+        h.run("!filter clear").await.unwrap();
+
+        let restarted = h.playing(2).await;
+        let playing = h.state_info.lock().await.now_playing.clone().unwrap();
+        assert_eq!(playing.thumbnail.as_deref(), Some(thumbnail));
+        restarted.finish().await;
+        h.playing(3).await;
+        assert_eq!(h.now_playing().await.as_deref(), Some("a-ha - Take On Me"));
+    }
+
+    #[tokio::test]
+    async fn stop_clears_the_queue_and_what_is_playing() {
+        let h = harness("stop").await;
+
+        h.run(&format!("!play {}", WATCH_URL)).await.unwrap();
+        h.run(&format!("!add {}", NEXT_URL)).await.unwrap();
+        let track = h.playing(1).await;
+        h.run("!stop").await.unwrap();
+
+        assert!(matches!(track.playing(), PlayMode::Stop));
+        assert!(h.queued().await.is_empty());
+        assert_eq!(h.now_playing().await, None);
+        assert!(h.state.trackdata.read().await.is_empty());
+        assert_eq!(
+            h.messenger.replies().last(),
+            Some(&message("Stopped and cleared the queue"))
+        );
+        assert_eq!(
+            h.presence.names().last().map(String::as_str),
+            Some("Nothing")
+        );
+
+        h.run("!list").await.unwrap();
+        assert_eq!(
+            h.messenger.replies().last(),
+            Some(&message("No songs in queue!"))
+        );
+        assert_eq!(h.voice.tracks().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn announcing_titles_is_saved_for_the_guild() {
+        let h = harness("announce").await;
+
+        h.run("!announce on").await.unwrap();
+
+        let reopened = GuildStore::new(h.dir.join("guilds"));
+        let guild = reopened.get(Id::new(GUILD_ID)).await.unwrap();
+        assert!(guild.announce_titles);
+        let other = reopened.get(Id::new(GUILD_ID + 1)).await.unwrap();
+        assert!(!other.announce_titles);
+        assert_eq!(
+            h.messenger.replies(),
+            vec![message(
+                "Song changes on radio streams will be announced here"
+            )]
+        );
+    }
+
+    #[test]
+    fn routes_synthetic_messages() {
+        let msg = message_create("!play  never gonna give you up");
+        assert!(msg.guild_id.is_some());
+        assert_eq!(msg_utils::get_command(&msg.content), "!play");
+        assert_eq!(
+            msg_utils::get_query(&msg.content),
+            "never+gonna+give+you+up"
+        );
+    }
+
+    #[tokio::test]
+    async fn help_replies_with_one_field_per_command() {
+        let messenger = RecordingMessenger::default();
+        let msg = message_create("!help");
+
+        send_text_file(&messenger, msg.channel_id, "Commands:", "./help.txt")
+            .await
+            .unwrap();
+
+        let lines = std::fs::read_to_string("./help.txt")
+            .unwrap()
+            .lines()
+            .count();
+        match messenger.replies().as_slice() {
+            [Reply::Embeds(channel_id, embeds)] => {
+                assert_eq!(channel_id.get(), CHANNEL_ID);
+                assert_eq!(embeds[0].description.as_deref(), Some("Commands:"));
+                assert!(embeds.iter().all(|embed| embed.fields.len() <= 25));
+                assert_eq!(
+                    embeds.iter().map(|embed| embed.fields.len()).sum::<usize>(),
+                    lines
+                );
+            }
+            replies => panic!("unexpected replies {:?}", replies),
+        }
+    }
+
+    #[tokio::test]
+    async fn play_search_picks_first_result() {
+        let fetcher = FixtureFetcher::default().page(SEARCH_URL, SEARCH_PAGE);
+        let msg = message_create("!play never gonna give you up");
+
+        let link = resolve_link(&fetcher, &msg_utils::get_query(&msg.content))
+            .await
+            .unwrap();
+
+        assert_eq!(link.as_deref(), Some(WATCH_URL));
+        assert_eq!(
+            fetcher.requests(),
+            vec![format!("{}never+gonna+give+you+up", SEARCH_URL)]
+        );
+    }
+
+    #[tokio::test]
+    async fn play_search_without_results() {
+        let fetcher = FixtureFetcher::default().page(SEARCH_URL, EMPTY_SEARCH_PAGE);
+
+        let link = resolve_link(&fetcher, "zzzzqqqq").await.unwrap();
+
+        assert_eq!(link, None);
+    }
+
+    #[tokio::test]
+    async fn play_link_skips_search() {
+        let fetcher = FixtureFetcher::default();
+
+        let link = resolve_link(&fetcher, WATCH_URL).await.unwrap();
+
+        assert_eq!(link.as_deref(), Some(WATCH_URL));
+        assert!(fetcher.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn play_links_are_probed_once() {
+        let stream = "http://astreaming.virginradio.ro:8000/virgin_aacp_64k";
+        let page = "https://example.com/article";
+        let fetcher = FixtureFetcher::default()
+            .probe(
+                stream,
+                UrlProbe {
+                    content_type: Some("audio/aacp".to_string()),
+                    content_length: None,
+                    icy: true,
+                },
+            )
+            .probe(
+                page,
+                UrlProbe {
+                    content_type: Some("text/html".to_string()),
+                    ..UrlProbe::default()
+                },
+            );
+
+        let link = resolve_link(&fetcher, stream).await.unwrap();
+        assert_eq!(link.as_deref(), Some(stream));
+        assert_eq!(
+            url_utils::resolve_url_kind(&fetcher, stream).await.unwrap(),
+            UrlKind::LiveStream
+        );
+        assert_eq!(
+            url_utils::resolve_url_kind(&fetcher, WATCH_URL)
+                .await
+                .unwrap(),
+            UrlKind::Youtube
+        );
+        let why = url_utils::resolve_url_kind(&fetcher, page)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(why.starts_with("Unsupported link: text/html"), "{}", why);
+        assert!(
+            url_utils::resolve_url_kind(&fetcher, "https://dead.example.com/")
+                .await
+                .unwrap_err()
+                .to_string()
+                .starts_with("Couldn't reach that link")
+        );
+        assert_eq!(
+            fetcher.requests(),
+            vec![stream, page, "https://dead.example.com/"]
+        );
+    }
+
+    #[tokio::test]
+    async fn description_of_current_song() {
+        let messenger = RecordingMessenger::default();
+        let fetcher = FixtureFetcher::default().page(WATCH_URL, WATCH_PAGE);
+        let state_info = AsyncMutex::new(StateInfo::default());
+        let msg = message_create("!desc");
+        state_info.lock().await.set_now_playing(Some(Track::new(
+            MediaItemType::Youtube,
+            WATCH_URL,
+            &msg.author,
+        )));
+
+        let metadata = MetadataCache::new(None, 10, DEFAULT_TTL);
+        for _ in 0..2 {
+            send_description(&messenger, &fetcher, &metadata, &state_info, msg.channel_id)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(fetcher.requests(), vec![WATCH_URL]);
+        assert!(state_info
+            .lock()
+            .await
+            .current_song_desc
+            .starts_with("The official video"));
+        let description = Reply::Message(
+            msg.channel_id,
+            "The official video for “Never Gonna Give You Up” by Rick Astley\nListen to Rick Astley: [http][//]RickAstley.lnk.to/_listenYD\n#RickAstley".to_string()
+        );
+        assert_eq!(messenger.replies(), vec![description.clone(), description]);
+    }
+
+    #[tokio::test]
+    async fn description_needs_a_youtube_track() {
+        let messenger = RecordingMessenger::default();
+        let fetcher = FixtureFetcher::default();
+        let state_info = AsyncMutex::new(StateInfo::default());
+        let msg = message_create("!desc");
+        state_info.lock().await.set_now_playing(Some(Track::new(
+            MediaItemType::LocalFile,
+            "music/song.mp3",
+            &msg.author,
+        )));
+
+        let metadata = MetadataCache::new(None, 10, DEFAULT_TTL);
+        send_description(&messenger, &fetcher, &metadata, &state_info, msg.channel_id)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            messenger.replies(),
+            vec![Reply::Message(
+                msg.channel_id,
+                "`Only YouTube videos have a description`".to_string()
+            )]
+        );
+        assert!(fetcher.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn fetch_failures_are_errors() {
+        let messenger = RecordingMessenger::default();
+        let fetcher = FixtureFetcher::default();
+        let state_info = AsyncMutex::new(StateInfo::default());
+        let msg = message_create("!desc");
+        state_info.lock().await.set_now_playing(Some(Track::new(
+            MediaItemType::Youtube,
+            WATCH_URL,
+            &msg.author,
+        )));
+
+        let metadata = MetadataCache::new(None, 10, DEFAULT_TTL);
+        assert!(resolve_link(&fetcher, "anything").await.is_err());
+        assert!(
+            send_description(&messenger, &fetcher, &metadata, &state_info, msg.channel_id)
+                .await
+                .is_err()
+        );
+        assert!(messenger.replies().is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{local_files::LocalTags, media_item_type::MediaItemType, url_utils};
+use serde::{Deserialize, Serialize};
+use songbird::input::AuxMetadata;
+use std::time::{Duration, SystemTime};
+use twilight_model::{
+    id::{marker::UserMarker, Id},
+    user::User,
+};
+
+/// A queued, playing or played item together with everything resolved about it, so it
+/// can be listed, shown and saved without asking yt-dlp again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Track {
+    pub kind: MediaItemType,
+    /// Link for web sources, path for local files.
+    pub source_url: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub duration: Option<Duration>,
+    pub thumbnail: Option<String>,
+    pub requester_id: Id<UserMarker>,
+    pub requester: String,
+    pub added_at: SystemTime,
+}
+
+impl Track {
+    pub fn new(kind: MediaItemType, source_url: impl Into<String>, requester: &User) -> Self {
+        Self::requested_by(kind, source_url, requester.id, &requester.name)
+    }
+
+    /// For tracks started without a message at hand, like scheduled ones.
+    pub fn requested_by(
+        kind: MediaItemType,
+        source_url: impl Into<String>,
+        requester_id: Id<UserMarker>,
+        requester: &str,
+    ) -> Self {
+        Track {
+            kind,
+            source_url: source_url.into(),
+            title: None,
+            artist: None,
+            duration: None,
+            thumbnail: None,
+            requester_id,
+            requester: requester.to_string(),
+            added_at: SystemTime::now(),
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: &AuxMetadata) -> Self {
+        self.title = metadata.title.clone().or(self.title);
+        self.artist = metadata.artist.clone().or(self.artist);
+        self.duration = metadata.duration.or(self.duration);
+        self.thumbnail = metadata.thumbnail.clone().or(self.thumbnail);
+        self
+    }
+
+    pub fn with_tags(mut self, tags: &LocalTags) -> Self {
+        self.title = tags.title.clone().or(self.title);
+        self.artist = tags.artist.clone().or(self.artist);
+        self.duration = tags.duration.or(self.duration);
+        self
+    }
+
+    /// `Artist - Title` when both are known, otherwise whatever identifies the track best.
+    pub fn display_title(&self) -> String {
+        match (&self.artist, &self.title) {
+            (Some(artist), Some(title)) if !title.contains(artist.as_str()) => {
+                format!("{} - {}", artist, title)
+            }
+            (_, Some(title)) => title.clone(),
+            _ => url_utils::display_name(&self.source_url),
+        }
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.kind == MediaItemType::Radio || self.duration.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn requester() -> User {
+        serde_json::from_value(serde_json::json!({
+            "id": "745000000000000003",
+            "username": "tester",
+            "discriminator": "0001",
+            "avatar": null
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn display_titles() {
+        let track = Track::new(
+            MediaItemType::DirectUrl,
+            "https://example.com/music/song.mp3",
+            &requester(),
+        );
+        assert_eq!(track.display_title(), "song.mp3");
+        assert_eq!(track.requester, "tester");
+
+        let track = track.with_tags(&LocalTags {
+            title: Some("Never Gonna Give You Up".to_string()),
+            artist: Some("Rick Astley".to_string()),
+            album: None,
+            duration: Some(Duration::from_secs(212)),
+        });
+        assert_eq!(
+            track.display_title(),
+            "Rick Astley - Never Gonna Give You Up"
+        );
+        assert!(!track.is_live());
+
+        let track = Track {
+            title: Some("Rick Astley - Never Gonna Give You Up".to_string()),
+            ..track
+        };
+        assert_eq!(
+            track.display_title(),
+            "Rick Astley - Never Gonna Give You Up"
+        );
+    }
+
+    #[test]
+    fn survives_a_round_trip() {
+        let track = Track {
+            title: Some("RadioZU Romania".to_string()),
+            thumbnail: Some("https://example.com/logo.jpg".to_string()),
+            ..Track::new(
+                MediaItemType::PlaylistEntry {
+                    playlist: "mix".to_string(),
+                },
+                "https://live4ro.antenaplay.ro/radiozu/radiozu-48000.m3u8",
+                &requester(),
+            )
+        };
+
+        let json = serde_json::to_string(&track).unwrap();
+        assert_eq!(serde_json::from_str::<Track>(&json).unwrap(), track);
+        assert!(track.is_live());
+    }
+}
@@ -0,0 +1,258 @@
+use crate::fetcher::{Fetcher, UrlProbe};
+use std::error::Error;
+
+/// How a link given to `!play` gets turned into audio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UrlKind {
+    /// YouTube video, resolved with yt-dlp.
+    Youtube,
+    /// Other sites yt-dlp knows how to extract (SoundCloud, Bandcamp, ...).
+    YtDlp,
+    /// A plain audio file, decoded directly over HTTP.
+    DirectFile,
+    /// HLS playlist (`.m3u8`), handed to ffmpeg.
+    Hls,
+    /// Icecast/Shoutcast or any other endless audio stream, handed to ffmpeg.
+    LiveStream,
+    /// M3U or PLS playlist, expanded into the links it lists.
+    Playlist,
+}
+
+const YOUTUBE_HOSTS: [&str; 4] = [
+    "youtube.com",
+    "youtu.be",
+    "music.youtube.com",
+    "youtube-nocookie.com",
+];
+const YTDLP_HOSTS: [&str; 4] = [
+    "soundcloud.com",
+    "bandcamp.com",
+    "mixcloud.com",
+    "vimeo.com",
+];
+const AUDIO_EXTENSIONS: [&str; 8] = ["mp3", "ogg", "oga", "opus", "flac", "m4a", "aac", "wav"];
+const HLS_CONTENT_TYPES: [&str; 2] = ["application/vnd.apple.mpegurl", "application/x-mpegurl"];
+const PLAYLIST_CONTENT_TYPES: [&str; 4] = [
+    "audio/x-scpls",
+    "audio/scpls",
+    "audio/x-mpegurl",
+    "audio/mpegurl",
+];
+
+pub fn is_url(text: &str) -> bool {
+    let text = text.to_lowercase();
+    text.starts_with("http://") || text.starts_with("https://")
+}
+
+fn host(url: &str) -> Option<String> {
+    let rest = url.split_once("://")?.1;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?.split(':').next()?;
+    let host = host.to_lowercase();
+    Some(host.strip_prefix("www.").unwrap_or(&host).to_string())
+}
+
+fn extension(url: &str) -> Option<String> {
+    let rest = url.split_once("://")?.1;
+    let path = rest.split(['?', '#']).next()?;
+    let (_, path) = path.split_once('/')?;
+    let file = path.rsplit('/').next()?;
+    let (_, ext) = file.rsplit_once('.')?;
+    Some(ext.to_lowercase())
+}
+
+fn host_matches(host: &str, known: &[&str]) -> bool {
+    known
+        .iter()
+        .any(|known| host == *known || host.ends_with(&format!(".{}", known)))
+}
+
+/// Last path segment of the link, or its host when the path is empty.
+pub fn display_name(url: &str) -> String {
+    let rest = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let path = rest.split(['?', '#']).next().unwrap_or_default();
+    path.trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+        .unwrap_or(url)
+        .to_string()
+}
+
+/// Classification that only needs the URL itself. `None` means the server has to be asked.
+pub fn classify_url(url: &str) -> Option<UrlKind> {
+    let host = host(url)?;
+    if host_matches(&host, &YOUTUBE_HOSTS) {
+        return Some(UrlKind::Youtube);
+    }
+    if host_matches(&host, &YTDLP_HOSTS) {
+        return Some(UrlKind::YtDlp);
+    }
+    match extension(url).as_deref() {
+        Some("m3u8") => Some(UrlKind::Hls),
+        Some("m3u" | "pls") => Some(UrlKind::Playlist),
+        _ => None,
+    }
+}
+
+/// Classification from the response headers of the link.
+pub fn classify_probe(url: &str, probe: &UrlProbe) -> Option<UrlKind> {
+    if probe.icy {
+        return Some(UrlKind::LiveStream);
+    }
+
+    let content_type = probe.content_type.as_deref().unwrap_or_default();
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+
+    if HLS_CONTENT_TYPES.contains(&mime.as_str()) {
+        Some(UrlKind::Hls)
+    } else if PLAYLIST_CONTENT_TYPES.contains(&mime.as_str()) {
+        Some(UrlKind::Playlist)
+    } else if mime.starts_with("audio/") || mime == "application/ogg" {
+        match probe.content_length {
+            Some(_) => Some(UrlKind::DirectFile),
+            None => Some(UrlKind::LiveStream),
+        }
+    } else if mime.is_empty() || mime == "application/octet-stream" {
+        // Servers that don't say what they send: trust a known audio extension.
+        let is_audio = extension(url)
+            .map(|ext| AUDIO_EXTENSIONS.contains(&ext.as_str()))
+            .unwrap_or(false);
+        match (is_audio, probe.content_length) {
+            (true, Some(_)) => Some(UrlKind::DirectFile),
+            (true, None) => Some(UrlKind::LiveStream),
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+pub async fn resolve_url_kind(
+    fetcher: &dyn Fetcher,
+    url: &str,
+) -> Result<UrlKind, Box<dyn Error + Send + Sync + 'static>> {
+    if let Some(kind) = classify_url(url) {
+        return Ok(kind);
+    }
+
+    let probe = fetcher
+        .probe(url)
+        .await
+        .map_err(|why| format!("Couldn't reach that link ({})", why))?;
+
+    classify_probe(url, &probe).ok_or_else(|| {
+        format!(
+            "Unsupported link: {} is not audio, a stream or a supported site",
+            probe.content_type.as_deref().unwrap_or("unknown content")
+        )
+        .into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(content_type: Option<&str>, content_length: Option<u64>, icy: bool) -> UrlProbe {
+        UrlProbe {
+            content_type: content_type.map(str::to_string),
+            content_length,
+            icy,
+        }
+    }
+
+    #[test]
+    fn classifies_by_url() {
+        let cases = [
+            (
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+                Some(UrlKind::Youtube),
+            ),
+            ("https://youtu.be/dQw4w9WgXcQ", Some(UrlKind::Youtube)),
+            (
+                "https://music.youtube.com/watch?v=x",
+                Some(UrlKind::Youtube),
+            ),
+            ("https://soundcloud.com/artist/track", Some(UrlKind::YtDlp)),
+            (
+                "https://artist.bandcamp.com/track/song",
+                Some(UrlKind::YtDlp),
+            ),
+            (
+                "https://live4ro.antenaplay.ro/radiozu/radiozu-48000.m3u8",
+                Some(UrlKind::Hls),
+            ),
+            (
+                "http://stream.example.com/listen.pls?sid=1",
+                Some(UrlKind::Playlist),
+            ),
+            ("https://example.com/song.mp3", None),
+            ("https://notyoutube.com/watch", None),
+            ("https://user@youtube.com.evil.org/", None),
+            ("not a url", None),
+        ];
+        for (url, kind) in cases {
+            assert_eq!(classify_url(url), kind, "{}", url);
+        }
+    }
+
+    #[test]
+    fn display_names() {
+        assert_eq!(
+            display_name("https://example.com/music/song.mp3?x=1"),
+            "song.mp3"
+        );
+        assert_eq!(display_name("http://radio.example.com:8000/live/"), "live");
+        assert_eq!(display_name("https://example.com"), "example.com");
+    }
+
+    #[test]
+    fn classifies_by_probe() {
+        let file = "https://example.com/song.mp3?x=1";
+        let stream = "https://24houseradio-adradio.radioca.st/128";
+        assert_eq!(
+            classify_probe(file, &probe(Some("audio/mpeg"), Some(4_000_000), false)),
+            Some(UrlKind::DirectFile)
+        );
+        assert_eq!(
+            classify_probe(stream, &probe(Some("audio/mpeg"), None, false)),
+            Some(UrlKind::LiveStream)
+        );
+        assert_eq!(
+            classify_probe(stream, &probe(Some("audio/aacp"), None, true)),
+            Some(UrlKind::LiveStream)
+        );
+        assert_eq!(
+            classify_probe(
+                stream,
+                &probe(Some("application/vnd.apple.mpegurl"), None, false)
+            ),
+            Some(UrlKind::Hls)
+        );
+        assert_eq!(
+            classify_probe(stream, &probe(Some("audio/x-scpls"), Some(120), false)),
+            Some(UrlKind::Playlist)
+        );
+        assert_eq!(
+            classify_probe(
+                file,
+                &probe(Some("application/octet-stream"), Some(10), false)
+            ),
+            Some(UrlKind::DirectFile)
+        );
+        assert_eq!(classify_probe(stream, &probe(None, None, false)), None);
+        assert_eq!(
+            classify_probe(
+                file,
+                &probe(Some("text/html; charset=utf-8"), Some(10), false)
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use async_trait::async_trait;
+use songbird::{
+    tracks::{Track as AudioTrack, TrackHandle, TrackState},
+    Event, EventContext, EventHandler, Songbird, TrackEvent,
+};
+use std::{error::Error, fmt::Debug, sync::Arc};
+use twilight_model::id::{
+    marker::{ChannelMarker, GuildMarker},
+    Id,
+};
+
+/// Joining, leaving and playing in voice channels goes through here, so commands can be
+/// run offline against a stand-in instead of songbird.
+#[async_trait]
+pub trait Voice: Debug + Send + Sync {
+    async fn join(
+        &self,
+        guild_id: Id<GuildMarker>,
+        channel_id: Id<ChannelMarker>,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>>;
+
+    /// Leaves the guild's call and forgets it, when there is one.
+    async fn leave(
+        &self,
+        guild_id: Id<GuildMarker>,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>>;
+
+    fn is_connected(&self, guild_id: Id<GuildMarker>) -> bool;
+
+    /// Starts `track` in the guild's call, or returns `None` when there's no call.
+    async fn play(
+        &self,
+        guild_id: Id<GuildMarker>,
+        track: AudioTrack,
+    ) -> Option<Arc<dyn PlayingTrack>>;
+
+    /// Stops every track in the guild's call, including ones still fading out.
+    async fn stop(&self, guild_id: Id<GuildMarker>);
+}
+
+/// A track started by `Voice::play`.
+#[async_trait]
+pub trait PlayingTrack: Debug + Send + Sync {
+    /// Tells this track apart from the others played in the guild.
+    fn id(&self) -> u128;
+
+    async fn get_info(&self) -> Result<TrackState, Box<dyn Error + Send + Sync + 'static>>;
+
+    fn play(&self) -> Result<(), Box<dyn Error + Send + Sync + 'static>>;
+
+    fn pause(&self) -> Result<(), Box<dyn Error + Send + Sync + 'static>>;
+
+    fn stop(&self) -> Result<(), Box<dyn Error + Send + Sync + 'static>>;
+
+    fn set_volume(&self, volume: f32) -> Result<(), Box<dyn Error + Send + Sync + 'static>>;
+
+    /// Calls `handler` with this track's `id` once `event` happens to it.
+    fn add_event(
+        &self,
+        event: TrackEvent,
+        handler: Arc<dyn TrackEventHandler>,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>>;
+}
+
+/// What to do when a track ends or fails.
+#[async_trait]
+pub trait TrackEventHandler: Send + Sync {
+    async fn act(&self, track_id: u128);
+}
+
+#[async_trait]
+impl Voice for Songbird {
+    async fn join(
+        &self,
+        guild_id: Id<GuildMarker>,
+        channel_id: Id<ChannelMarker>,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        let (_handle, success) = Songbird::join(self, guild_id.into_nonzero(), channel_id).await;
+        Ok(success?)
+    }
+
+    async fn leave(
+        &self,
+        guild_id: Id<GuildMarker>,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        if self.get(guild_id).is_some() {
+            Songbird::leave(self, guild_id).await?;
+            self.remove(guild_id).await?;
+        }
+        Ok(())
+    }
+
+    fn is_connected(&self, guild_id: Id<GuildMarker>) -> bool {
+        self.get(guild_id).is_some()
+    }
+
+    async fn play(
+        &self,
+        guild_id: Id<GuildMarker>,
+        track: AudioTrack,
+    ) -> Option<Arc<dyn PlayingTrack>> {
+        let call_lock = self.get(guild_id)?;
+        let handle = call_lock.lock().await.play(track);
+        Some(Arc::new(handle))
+    }
+
+    async fn stop(&self, guild_id: Id<GuildMarker>) {
+        if let Some(call_lock) = self.get(guild_id) {
+            call_lock.lock().await.stop();
+        }
+    }
+}
+
+#[async_trait]
+impl PlayingTrack for TrackHandle {
+    fn id(&self) -> u128 {
+        self.uuid().as_u128()
+    }
+
+    async fn get_info(&self) -> Result<TrackState, Box<dyn Error + Send + Sync + 'static>> {
+        Ok(TrackHandle::get_info(self).await?)
+    }
+
+    fn play(&self) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        Ok(TrackHandle::play(self)?)
+    }
+
+    fn pause(&self) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        Ok(TrackHandle::pause(self)?)
+    }
+
+    fn stop(&self) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        Ok(TrackHandle::stop(self)?)
+    }
+
+    fn set_volume(&self, volume: f32) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        Ok(TrackHandle::set_volume(self, volume)?)
+    }
+
+    fn add_event(
+        &self,
+        event: TrackEvent,
+        handler: Arc<dyn TrackEventHandler>,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        let forward = ForwardEvent {
+            track_id: self.id(),
+            handler,
+        };
+        Ok(TrackHandle::add_event(self, Event::Track(event), forward)?)
+    }
+}
+
+/// Hands a songbird track event on to a `TrackEventHandler`.
+struct ForwardEvent {
+    track_id: u128,
+    handler: Arc<dyn TrackEventHandler>,
+}
+
+#[async_trait]
+impl EventHandler for ForwardEvent {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        self.handler.act(self.track_id).await;
+        None
+    }
+}
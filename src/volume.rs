@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A guild's `!volume` in percent, where 100 plays tracks at their own level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Volume(u8);
+
+impl Default for Volume {
+    fn default() -> Self {
+        Volume(Volume::MAX)
+    }
+}
+
+impl Volume {
+    pub const MAX: u8 = 100;
+
+    pub fn new(percent: u8) -> Option<Self> {
+        (percent <= Volume::MAX).then_some(Volume(percent))
+    }
+
+    pub fn percent(self) -> u8 {
+        self.0.min(Volume::MAX)
+    }
+
+    /// The multiplier songbird plays a track with.
+    pub fn gain(self) -> f32 {
+        f32::from(self.percent()) / f32::from(Volume::MAX)
+    }
+}
+
+impl fmt::Display for Volume {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}%", self.percent())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_and_gain() {
+        assert_eq!(Volume::default().gain(), 1.0);
+        assert_eq!(Volume::new(40).unwrap().gain(), 0.4);
+        assert_eq!(Volume::new(101), None);
+        assert_eq!(Volume::new(5).unwrap().to_string(), "5%");
+        // Hand-edited guild files can't push a track past its own level.
+        let loud: Volume = serde_json::from_str("250").unwrap();
+        assert_eq!(loud.gain(), 1.0);
+    }
+}
@@ -1,59 +1,10 @@
+use crate::fetcher::Fetcher;
 use lazy_static_include::lazy_static::lazy_static;
 use linked_hash_set::LinkedHashSet;
 use regex::Regex;
 use std::borrow::Cow;
+use std::error::Error;
 
-use std::ops::{Bound, RangeBounds};
-
-trait StringUtils {
-    fn substring(&self, start: usize, len: usize) -> &str;
-    fn slice(&self, range: impl RangeBounds<usize>) -> &str;
-}
-// https://users.rust-lang.org/t/how-to-get-a-substring-of-a-string/1351/11 solution
-impl StringUtils for str {
-    fn substring(&self, start: usize, len: usize) -> &str {
-        let mut char_pos = 0;
-        let mut byte_start = 0;
-        let mut it = self.chars();
-        loop {
-            if char_pos == start {
-                break;
-            }
-            if let Some(c) = it.next() {
-                char_pos += 1;
-                byte_start += c.len_utf8();
-            } else {
-                break;
-            }
-        }
-        char_pos = 0;
-        let mut byte_end = byte_start;
-        loop {
-            if char_pos == len {
-                break;
-            }
-            if let Some(c) = it.next() {
-                char_pos += 1;
-                byte_end += c.len_utf8();
-            } else {
-                break;
-            }
-        }
-        &self[byte_start..byte_end]
-    }
-    fn slice(&self, range: impl RangeBounds<usize>) -> &str {
-        let start = match range.start_bound() {
-            Bound::Included(bound) | Bound::Excluded(bound) => *bound,
-            Bound::Unbounded => 0,
-        };
-        let len = match range.end_bound() {
-            Bound::Included(bound) => *bound + 1,
-            Bound::Excluded(bound) => *bound,
-            Bound::Unbounded => self.len(),
-        } - start;
-        self.substring(start, len)
-    }
-}
 #[derive(Debug, Default, Clone)]
 pub struct YtInfo {
     _yt_link: String,
@@ -63,6 +14,7 @@ pub struct YtInfo {
     _yt_thumbnail: String,
     _yt_author: String,
     _is_live: bool,
+    loudness_db: Option<f32>,
 }
 
 impl YtInfo {
@@ -78,6 +30,16 @@ impl YtInfo {
     pub fn get_yt_desc(&self) -> String {
         self.yt_desc.clone()
     }
+    pub fn get_yt_title(&self) -> String {
+        self._yt_title.clone()
+    }
+    pub fn get_yt_author(&self) -> String {
+        self._yt_author.clone()
+    }
+    /// How much louder than YouTube's reference level the video is, in dB.
+    pub fn get_loudness_db(&self) -> Option<f32> {
+        self.loudness_db
+    }
 }
 /* same as: #[derive(Default)]
 impl Default for YtInfo {
@@ -94,40 +56,69 @@ impl Default for YtInfo {
     }
 }
 */
-pub fn _extract_links(content: &str) -> LinkedHashSet<Cow<str>> {
+/// Searches YouTube and returns the watch link of the first result, if any.
+pub async fn search(
+    fetcher: &dyn Fetcher,
+    query: &str,
+) -> Result<Option<String>, Box<dyn Error + Send + Sync + 'static>> {
+    let mut search_str: String = String::from("https://www.youtube.com/results?search_query=");
+    search_str.push_str(query);
+
+    let content = fetcher.fetch_text(&search_str).await?;
+    let links = _extract_links(content.as_str())?;
+    //  .skip(1)
+    let yt_link = links
+        .iter()
+        .next()
+        .map(|link| format!("https://www.youtube.com/watch?v={}", link));
+    Ok(yt_link)
+}
+
+pub async fn get_info(
+    fetcher: &dyn Fetcher,
+    yt_link: &str,
+) -> Result<YtInfo, Box<dyn Error + Send + Sync + 'static>> {
+    let content = fetcher.fetch_text(yt_link).await?;
+    get_link_content(content.as_str(), yt_link.to_string())
+}
+
+pub fn _extract_links(
+    content: &str,
+) -> Result<LinkedHashSet<Cow<'_, str>>, Box<dyn Error + Send + Sync + 'static>> {
     //pub fn extract_links(content: &str) -> YtInfo {
     // let mut fileRef = std::fs::File::create("saved.txt").expect("create failed");
     //std::io::Write::write_all(&mut fileRef, &content.as_bytes()).expect("write failed");
-    let init_content_first_index = content.find("ytConfigData"); //orig ytInitialData
+    let init_content_first_index = content
+        .find("ytConfigData") //orig ytInitialData
+        .ok_or("No search data found in the page")?;
 
-    let pre_unparsed_content = content
-        .substring(init_content_first_index.unwrap(), content.len())
-        .to_string();
+    let pre_unparsed_content = &content[init_content_first_index..];
 
-    let init_content_last_index = pre_unparsed_content.find("</script>");
+    let init_content_last_index = pre_unparsed_content
+        .find("</script>")
+        .ok_or("Search data is not terminated")?;
 
-    let unparsed_content = pre_unparsed_content
-        .substring(0, init_content_last_index.unwrap())
-        .to_string();
+    let unparsed_content = &pre_unparsed_content[..init_content_last_index];
 
     lazy_static! {
         static ref YT_LINK_REGEX: Regex = Regex::new("\\{\"videoId\":\"(.*?)\"").unwrap();
     }
 
     let mut links: LinkedHashSet<_> = YT_LINK_REGEX
-        .captures_iter(&unparsed_content.to_string())
+        .captures_iter(unparsed_content)
         .take(3)
-        .map(|c| match c.get(1) {
-            Some(val) => Cow::from(val.as_str().to_string()),
-            _ => unreachable!(),
-        })
+        .filter_map(|c| c.get(1))
+        .map(|val| Cow::from(val.as_str().to_string()))
         .collect();
     links.reserve(links.len());
 
-    links
+    Ok(links)
 }
 
-pub fn get_link_content(content: &str, yt_link: String) -> YtInfo {
+pub fn get_link_content(
+    content: &str,
+    yt_link: String,
+) -> Result<YtInfo, Box<dyn Error + Send + Sync + 'static>> {
     // let mut fileRef = std::fs::File::create("saved.txt").expect("create failed");
     // std::io::Write::write_all(&mut fileRef, &content.as_bytes()).expect("write failed");
     lazy_static! {
@@ -140,53 +131,31 @@ pub fn get_link_content(content: &str, yt_link: String) -> YtInfo {
             Regex::new("(thumbnails\":\\[\\{\"url\":\"(.*?)\"([^\"]*)\")").unwrap();
         static ref YT_AUTHOR: Regex = Regex::new("(author\":\"(.*?)\"([^\"]*)\")").unwrap();
         static ref YT_IS_LIVE: Regex = Regex::new("(isLiveContent\":+(\\w+))").unwrap();
+        static ref YT_LOUDNESS: Regex =
+            Regex::new("(\"loudnessDb\":(-?[0-9]+(\\.[0-9]+)?))").unwrap();
     }
 
     /*for cap in YT_THUMBNAIL.captures(&content) {
         println!("2: {} 3: {} 1: {} ", &cap[2], &cap[3], &cap[1]);
     }*/
 
-    let yt_desc: String = YT_DESC_REGEX
-        .captures_iter(&content.to_string())
-        .take(1)
-        .map(|c| match c.get(2) {
-            Some(val) => Cow::from(val.as_str().to_string()),
-            _ => unreachable!(),
-        })
-        .collect();
+    let yt_desc = first_capture(&YT_DESC_REGEX, content);
 
     //   println!("{:?}", yt_desc);
 
-    let yt_title: String = YT_TITLE_REGEX
-        .captures_iter(&content.to_string())
-        .take(1)
-        .map(|c| match c.get(2) {
-            Some(val) => Cow::from(val.as_str().to_string()),
-            _ => unreachable!(),
-        })
-        .collect();
+    let yt_title = first_capture(&YT_TITLE_REGEX, content);
 
     println!(" -> {:?}", yt_title);
 
-    let yt_len_sec: String = YT_LENGTH_SEC
-        .captures_iter(&content.to_string())
-        .take(1)
-        .map(|c| match c.get(2) {
-            Some(val) => Cow::from(val.as_str().to_string()),
-            _ => unreachable!(),
-        })
-        .collect();
+    if yt_title.is_empty() && yt_desc.is_empty() {
+        return Err("Not a YouTube video page".into());
+    }
+
+    let yt_len_sec = first_capture(&YT_LENGTH_SEC, content);
 
     println!("{:?}", yt_len_sec);
 
-    let mut yt_thumbnail: String = YT_THUMBNAIL
-        .captures_iter(&content.to_string())
-        .take(1)
-        .map(|c| match c.get(2) {
-            Some(val) => Cow::from(val.as_str().to_string()),
-            _ => unreachable!(),
-        })
-        .collect();
+    let mut yt_thumbnail = first_capture(&YT_THUMBNAIL, content);
     let index_element = yt_thumbnail
         .chars()
         .position(|x| x == '?')
@@ -194,33 +163,192 @@ pub fn get_link_content(content: &str, yt_link: String) -> YtInfo {
     yt_thumbnail = yt_thumbnail.chars().take(index_element).collect();
     println!("{:?}", yt_thumbnail);
 
-    let yt_author: String = YT_AUTHOR
-        .captures_iter(&content.to_string())
-        .take(1)
-        .map(|c| match c.get(2) {
-            Some(val) => Cow::from(val.as_str().to_string()),
-            _ => unreachable!(),
-        })
-        .collect();
+    let yt_author = first_capture(&YT_AUTHOR, content);
 
     println!("{:?}", yt_author);
-    let yt_is_live: String = YT_IS_LIVE
-        .captures_iter(&content.to_string())
-        .take(3)
-        .map(|c| match c.get(2) {
-            Some(val) => Cow::from(val.as_str().to_string()),
-            _ => unreachable!(),
-        })
-        .collect();
+    let yt_is_live = first_capture(&YT_IS_LIVE, content);
+    let loudness_db = first_capture(&YT_LOUDNESS, content).parse().ok();
 
     let yt_info_con = YtInfo {
         _yt_link: yt_link,
-        yt_desc: String::from(yt_desc),
-        _yt_title: String::from(yt_title),
-        _yt_length_sec: String::from(yt_len_sec),
-        _yt_thumbnail: String::from(yt_thumbnail),
-        _yt_author: String::from(yt_author),
-        _is_live: yt_is_live.parse().unwrap(),
+        yt_desc,
+        _yt_title: yt_title,
+        _yt_length_sec: yt_len_sec,
+        _yt_thumbnail: yt_thumbnail,
+        _yt_author: yt_author,
+        _is_live: yt_is_live.parse().unwrap_or(false),
+        loudness_db,
     };
-    yt_info_con
+    Ok(yt_info_con)
+}
+
+/// The video ID of a YouTube watch, `youtu.be`, shorts or live link.
+pub fn video_id(url: &str) -> Option<String> {
+    let rest = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let query = query.split('#').next().unwrap_or_default();
+    let mut segments = path.split('/');
+    let host = segments.next().unwrap_or_default();
+    let id = if host.ends_with("youtu.be") {
+        segments.next()
+    } else {
+        match segments.next() {
+            Some("shorts" | "live" | "embed") => segments.next(),
+            _ => query.split('&').find_map(|param| param.strip_prefix("v=")),
+        }
+    }?;
+    let valid = id.len() == 11
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| id.to_string())
+}
+
+/// IDs of the videos YouTube suggests next to a watch page, in its order, leaving out
+/// `video_id` itself, repeats and playlists.
+pub fn related_videos(content: &str, video_id: &str) -> Vec<String> {
+    lazy_static! {
+        static ref RELATED_ID_REGEX: Regex =
+            Regex::new("\"(?:videoId|contentId)\":\"([A-Za-z0-9_-]{11})\"").unwrap();
+    }
+    let related = match content.find("secondaryResults") {
+        Some(index) => &content[index..],
+        None => return Vec::new(),
+    };
+    let ids: LinkedHashSet<String> = RELATED_ID_REGEX
+        .captures_iter(related)
+        .filter_map(|c| c.get(1))
+        .map(|id| id.as_str())
+        .filter(|id| *id != video_id)
+        .map(str::to_string)
+        .collect();
+    ids.into_iter().collect()
+}
+
+/// Watch links for the videos related to `yt_link`.
+pub async fn get_related(
+    fetcher: &dyn Fetcher,
+    yt_link: &str,
+) -> Result<Vec<String>, Box<dyn Error + Send + Sync + 'static>> {
+    let video_id = video_id(yt_link).ok_or("Not a YouTube video link")?;
+    let content = fetcher.fetch_text(yt_link).await?;
+    Ok(related_videos(&content, &video_id)
+        .iter()
+        .map(|id| format!("https://www.youtube.com/watch?v={}", id))
+        .collect())
+}
+
+fn first_capture(regex: &Regex, content: &str) -> String {
+    regex
+        .captures(content)
+        .and_then(|c| c.get(2))
+        .map(|val| val.as_str().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const SEARCH_PAGE: &str = r#"<html><script>var ytConfigData = {};{"videoId":"dQw4w9WgXcQ","x":1}{"videoId":"yPYZpwSpKmA"}</script><script>{"videoId":"ignored"}</script></html>"#;
+
+    const WATCH_PAGE: &str = r#"{"title":"Never Gonna Give You Up","lengthSeconds":"212","thumbnails":[{"url":"https://i.ytimg.com/vi/dQw4w9WgXcQ/hq.jpg?sqp=1"}],"shortDescription":"The official video","author":"Rick Astley","isLiveContent":false,"playerConfig":{"audioConfig":{"loudnessDb":-7.25,"perceptualLoudnessDb":-21.25}}}"#;
+
+    #[test]
+    fn extracts_links_from_search_page() {
+        let links: Vec<String> = _extract_links(SEARCH_PAGE)
+            .unwrap()
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        assert_eq!(links, vec!["dQw4w9WgXcQ", "yPYZpwSpKmA"]);
+    }
+
+    #[test]
+    fn missing_search_data_is_an_error() {
+        assert!(_extract_links("<html></html>").is_err());
+        assert!(_extract_links("ytConfigData but no end").is_err());
+    }
+
+    #[test]
+    fn parses_watch_page() {
+        let info = get_link_content(WATCH_PAGE, "link".to_string()).unwrap();
+        assert_eq!(info.get_yt_desc(), "The official video");
+        assert_eq!(info._yt_title, "Never Gonna Give You Up");
+        assert_eq!(info._yt_length_sec, "212");
+        assert_eq!(
+            info._yt_thumbnail,
+            "https://i.ytimg.com/vi/dQw4w9WgXcQ/hq.jpg"
+        );
+        assert_eq!(info._yt_author, "Rick Astley");
+        assert!(!info._is_live);
+        assert_eq!(info.get_loudness_db(), Some(-7.25));
+    }
+
+    #[test]
+    fn video_ids_from_links() {
+        for url in [
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://music.youtube.com/watch?list=RD&v=dQw4w9WgXcQ#t=10",
+            "https://youtu.be/dQw4w9WgXcQ?t=42",
+            "https://www.youtube.com/shorts/dQw4w9WgXcQ",
+        ] {
+            assert_eq!(video_id(url).as_deref(), Some("dQw4w9WgXcQ"), "{}", url);
+        }
+        assert_eq!(video_id("https://www.youtube.com/watch?v=short"), None);
+        assert_eq!(video_id("https://example.com/song.mp3"), None);
+    }
+
+    #[test]
+    fn related_videos_skip_the_video_and_playlists() {
+        let page = include_str!("../tests/fixtures/youtube_watch.html");
+        assert_eq!(
+            related_videos(page, "dQw4w9WgXcQ"),
+            vec!["yPYZpwSpKmA", "AC3Ejf7vPEY", "djV11Xbc914"]
+        );
+        assert!(related_videos(WATCH_PAGE, "dQw4w9WgXcQ").is_empty());
+    }
+
+    #[test]
+    fn malformed_watch_page_is_an_error() {
+        assert!(get_link_content("", String::new()).is_err());
+        assert!(get_link_content("<html>nothing here</html>", String::new()).is_err());
+    }
+
+    fn html() -> impl Strategy<Value = String> {
+        prop::collection::vec(
+            prop_oneof![
+                Just("ytConfigData".to_string()),
+                Just("</script>".to_string()),
+                Just("{\"videoId\":\"".to_string()),
+                Just("title\":\"".to_string()),
+                Just("thumbnails\":[{\"url\":\"".to_string()),
+                Just("isLiveContent\":".to_string()),
+                Just("\"".to_string()),
+                "\\PC{0,8}",
+            ],
+            0..40,
+        )
+        .prop_map(|parts| parts.concat())
+    }
+
+    proptest! {
+        #[test]
+        fn extract_links_never_panics(content in "\\PC*") {
+            let _ = _extract_links(&content);
+        }
+
+        #[test]
+        fn extract_links_never_panics_on_html(content in html()) {
+            if let Ok(links) = _extract_links(&content) {
+                prop_assert!(links.len() <= 3);
+            }
+        }
+
+        #[test]
+        fn get_link_content_never_panics(content in html()) {
+            let _ = get_link_content(&content, String::new());
+        }
+    }
 }